@@ -0,0 +1,327 @@
+//! Where long files were left, named bookmarks in them and the speed they play at, kept in
+//! `bookmarks.toml`.
+//!
+//! Files are found by path, or by a hash of their start when they were moved or renamed.
+//! Every change reads the store again before writing it, so the TUI and the daemon can share it.
+use crate::config::get_app_config_path;
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::io::{ErrorKind, Read};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// How much of the start of a file goes into its hash.
+const HASH_BYTES: u64 = 64 * 1024;
+/// Closer to the start or the end than this, a file is played from the start next time.
+const RESUME_MARGIN: Duration = Duration::from_secs(10);
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Bookmark {
+    pub name: String,
+    /// Seconds into the file.
+    pub position: u64,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+struct FileMarks {
+    path: String,
+    hash: String,
+    /// Seconds into the file where it was left.
+    resume: Option<u64>,
+    /// Playback speed chosen for the file, when it isn't 1.0.
+    speed: Option<f32>,
+    #[serde(default)]
+    bookmarks: Vec<Bookmark>,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+struct Store {
+    #[serde(default)]
+    files: Vec<FileMarks>,
+}
+
+impl Store {
+    fn path() -> Result<PathBuf> {
+        let mut path = get_app_config_path()?;
+        path.push("bookmarks.toml");
+        Ok(path)
+    }
+
+    /// The store, empty while there's none yet. One that can't be read isn't written over.
+    fn load() -> Result<Self> {
+        Self::read(&Self::path()?)
+    }
+
+    fn read(path: &Path) -> Result<Self> {
+        match fs::read_to_string(path) {
+            Ok(string) => toml::from_str(&string)
+                .map_err(|e| anyhow!("{} is broken, fix or remove it: {}", path.display(), e)),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn save(&mut self) -> Result<()> {
+        self.files.retain(|marks| {
+            marks.resume.is_some() || marks.speed.is_some() || !marks.bookmarks.is_empty()
+        });
+        fs::write(Self::path()?, toml::to_string(self)?)?;
+        Ok(())
+    }
+
+    /// The marks of `file`, following it when it was moved.
+    fn find(&mut self, file: &str) -> Option<&mut FileMarks> {
+        if let Some(index) = self.files.iter().position(|marks| marks.path == file) {
+            return self.files.get_mut(index);
+        }
+        // only the marks of files gone from their path can be the ones of a moved file
+        let mut moved = self
+            .files
+            .iter_mut()
+            .filter(|marks| !Path::new(&marks.path).exists())
+            .peekable();
+        moved.peek()?;
+        let hash = hash(file).ok()?;
+        let marks = moved.find(|marks| marks.hash == hash)?;
+        marks.path = file.to_string();
+        Some(marks)
+    }
+
+    fn entry(&mut self, file: &str) -> Result<&mut FileMarks> {
+        if self.find(file).is_none() {
+            self.files.push(FileMarks {
+                path: file.to_string(),
+                hash: hash(file)?,
+                resume: None,
+                speed: None,
+                bookmarks: Vec::new(),
+            });
+        }
+        Ok(self.find(file).unwrap())
+    }
+
+    /// Keeps `resume` as where `file` was left, `false` when there was nothing to change.
+    fn set_resume(&mut self, file: &str, resume: Option<u64>) -> Result<bool> {
+        if resume.is_none() && self.find(file).is_none() {
+            return Ok(false);
+        }
+        self.entry(file)?.resume = resume;
+        Ok(true)
+    }
+
+    fn speed(&mut self, file: &str) -> f32 {
+        self.find(file).and_then(|marks| marks.speed).unwrap_or(1.0)
+    }
+
+    /// Keeps `speed` for `file`, `false` when there was nothing to change.
+    fn set_speed(&mut self, file: &str, speed: f32) -> Result<bool> {
+        let speed = Some(speed).filter(|speed| (speed - 1.0).abs() > f32::EPSILON);
+        if speed.is_none() && self.find(file).is_none() {
+            return Ok(false);
+        }
+        self.entry(file)?.speed = speed;
+        Ok(true)
+    }
+}
+
+/// A hash of the size and the start of `file`, which stays the same when it's moved.
+fn hash(file: &str) -> Result<String> {
+    let file = File::open(file)?;
+    let size = file.metadata()?.len();
+    let mut data = size.to_le_bytes().to_vec();
+    file.take(HASH_BYTES).read_to_end(&mut data)?;
+    Ok(format!("{:x}", md5::compute(data)))
+}
+
+/// Whether a file of `length` is long enough to be resumed, see `resume_min_secs`.
+pub fn resumes(length: Duration, min_secs: u64) -> bool {
+    min_secs > 0 && length >= Duration::from_secs(min_secs)
+}
+
+/// Where to continue `file` of `length`, if it's long enough and was left in the middle.
+pub fn resume_position(file: &str, length: Duration, min_secs: u64) -> Option<Duration> {
+    if !resumes(length, min_secs) {
+        return None;
+    }
+    let mut store = Store::load().ok()?;
+    store.find(file)?.resume.map(Duration::from_secs)
+}
+
+/// Remembers that `file` of `length` was left at `position`. Near its start or its end, it's
+/// forgotten instead, so it plays from the start again.
+pub fn remember(file: &str, position: Duration, length: Duration, min_secs: u64) -> Result<()> {
+    if !resumes(length, min_secs) {
+        return Ok(());
+    }
+    let mut store = Store::load()?;
+    if store.set_resume(file, resume_at(position, length))? {
+        store.save()?;
+    }
+    Ok(())
+}
+
+/// The seconds to keep for `position` in a file of `length`, unless it's near either end.
+fn resume_at(position: Duration, length: Duration) -> Option<u64> {
+    (position > RESUME_MARGIN && position + RESUME_MARGIN < length).then_some(position.as_secs())
+}
+
+/// The speed `file` was last played at, 1.0 unless it was changed.
+pub fn speed(file: &str) -> f32 {
+    Store::load().map_or(1.0, |mut store| store.speed(file))
+}
+
+pub fn remember_speed(file: &str, speed: f32) -> Result<()> {
+    let mut store = Store::load()?;
+    if store.set_speed(file, speed)? {
+        store.save()?;
+    }
+    Ok(())
+}
+
+/// The bookmarks of `file`, in the order of their positions.
+pub fn list(file: &str) -> Vec<Bookmark> {
+    Store::load()
+        .ok()
+        .and_then(|mut store| store.find(file).map(|marks| marks.bookmarks.clone()))
+        .unwrap_or_default()
+}
+
+pub fn add(file: &str, name: &str, position: Duration) -> Result<()> {
+    let mut store = Store::load()?;
+    let bookmarks = &mut store.entry(file)?.bookmarks;
+    bookmarks.push(Bookmark {
+        name: name.to_string(),
+        position: position.as_secs(),
+    });
+    bookmarks.sort_by_key(|bookmark| bookmark.position);
+    store.save()
+}
+
+/// Removes the bookmark at `index` of what `list` returns.
+pub fn remove(file: &str, index: usize) -> Result<()> {
+    let mut store = Store::load()?;
+    if let Some(marks) = store.find(file) {
+        if index < marks.bookmarks.len() {
+            marks.bookmarks.remove(index);
+        }
+    }
+    store.save()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A file of `size` bytes counting up, in a directory of its own.
+    fn write_file(name: &str, size: usize) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("termusic-bookmarks-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(name);
+        #[allow(clippy::cast_possible_truncation)]
+        let data: Vec<u8> = (0..size).map(|i| i as u8).collect();
+        fs::write(&path, data).unwrap();
+        path
+    }
+
+    fn path_str(path: &Path) -> &str {
+        path.to_str().unwrap()
+    }
+
+    #[test]
+    fn test_hash() {
+        let size = usize::try_from(HASH_BYTES).unwrap() * 2;
+        let original = write_file("hash-original", size);
+        let hash_original = hash(path_str(&original)).unwrap();
+
+        // past the hashed start, the content doesn't matter
+        let copy = write_file("hash-copy", size);
+        let mut data = fs::read(&copy).unwrap();
+        data[size - 1] ^= 0xff;
+        fs::write(&copy, &data).unwrap();
+        assert_eq!(hash(path_str(&copy)).unwrap(), hash_original);
+
+        // within it, it does
+        data[0] ^= 0xff;
+        fs::write(&copy, &data).unwrap();
+        assert_ne!(hash(path_str(&copy)).unwrap(), hash_original);
+
+        // and so does the size, even with the same start
+        let longer = write_file("hash-longer", size + 1);
+        assert_ne!(hash(path_str(&longer)).unwrap(), hash_original);
+
+        for path in [original, copy, longer] {
+            fs::remove_file(path).ok();
+        }
+    }
+
+    #[test]
+    fn test_follow_moved_file() {
+        let before = write_file("moved-before", 1000);
+        let after = before.with_file_name("moved-after");
+        let mut store = Store::default();
+        assert!(store.set_resume(path_str(&before), Some(60)).unwrap());
+
+        fs::rename(&before, &after).unwrap();
+        let marks = store.find(path_str(&after)).unwrap();
+        assert_eq!(marks.resume, Some(60));
+        assert_eq!(marks.path, path_str(&after));
+        fs::remove_file(after).ok();
+    }
+
+    #[test]
+    fn test_resume_margin() {
+        let secs = Duration::from_secs;
+        let length = secs(600);
+        assert_eq!(resume_at(secs(5), length), None);
+        assert_eq!(resume_at(RESUME_MARGIN, length), None);
+        assert_eq!(resume_at(RESUME_MARGIN + secs(1), length), Some(11));
+        assert_eq!(resume_at(secs(300), length), Some(300));
+        assert_eq!(resume_at(secs(589), length), Some(589));
+        assert_eq!(resume_at(secs(590), length), None);
+        assert_eq!(resume_at(length, length), None);
+
+        assert!(resumes(secs(1200), 600));
+        assert!(!resumes(secs(599), 600));
+        assert!(!resumes(secs(1200), 0));
+    }
+
+    #[test]
+    fn test_speed() {
+        let file = write_file("speed", 1000);
+        let other = write_file("speed-other", 2000);
+        let mut store = Store::default();
+        assert!((store.speed(path_str(&file)) - 1.0).abs() < f32::EPSILON);
+        // the normal speed of a file without marks isn't kept at all
+        assert!(!store.set_speed(path_str(&file), 1.0).unwrap());
+        assert!(store.files.is_empty());
+
+        assert!(store.set_speed(path_str(&file), 1.5).unwrap());
+        assert!((store.speed(path_str(&file)) - 1.5).abs() < f32::EPSILON);
+        assert!((store.speed(path_str(&other)) - 1.0).abs() < f32::EPSILON);
+
+        assert!(store.set_speed(path_str(&file), 1.0).unwrap());
+        assert_eq!(store.find(path_str(&file)).unwrap().speed, None);
+        for path in [file, other] {
+            fs::remove_file(path).ok();
+        }
+    }
+
+    #[test]
+    fn test_read_broken_store() {
+        let path = write_file("store.toml", 0);
+        fs::write(&path, "[[files]]\npath = \"/music/book.mp3\"\n").unwrap();
+        assert!(Store::read(&path).is_err());
+
+        fs::write(
+            &path,
+            "[[files]]\npath = \"/music/book.mp3\"\nhash = \"00\"\nresume = 60\n",
+        )
+        .unwrap();
+        let store = Store::read(&path).unwrap();
+        assert_eq!(store.files[0].resume, Some(60));
+
+        fs::remove_file(&path).ok();
+        assert!(Store::read(&path).unwrap().files.is_empty());
+    }
+}
@@ -27,18 +27,57 @@ use crate::ui::components::Xywh;
 use crate::ui::Loop;
 use anyhow::{anyhow, Result};
 use serde::{Deserialize, Serialize};
+use std::fmt;
 use std::fs::{self, read_to_string};
 use std::path::PathBuf;
+use std::str::FromStr;
 
 pub const MUSIC_DIR: &str = "~/Music";
 
 #[derive(Clone, Deserialize, Serialize)]
+#[allow(clippy::struct_excessive_bools)]
 pub struct Termusic {
     pub music_dir: String,
     #[serde(skip_serializing)]
     pub music_dir_from_cli: Option<String>,
     pub loop_mode: Loop,
     pub volume: i32,
+    #[serde(default)]
+    pub library_mode: LibraryMode,
+    /// Player backend, falls back to the rusty one when it can't be started.
+    #[serde(default = "default_backend")]
+    pub backend: Backend,
+    #[serde(skip_serializing)]
+    pub backend_from_cli: Option<Backend>,
+    /// Seconds the end of a song overlaps with the start of the next one, 0 to disable.
+    #[serde(default)]
+    pub crossfade_secs: u64,
+    /// Seconds the seek keys jump forward or backward.
+    #[serde(default = "default_seek_step_secs")]
+    pub seek_step_secs: u64,
+    #[serde(default)]
+    pub replaygain: ReplayGainMode,
+    /// Name of the audio output device, the system default when not set.
+    #[serde(default)]
+    pub output_device: Option<String>,
+    /// Open the output in the sample rate and channel count of each song, so it isn't resampled.
+    /// Songs with a different format than the one before can't be joined gaplessly then.
+    #[serde(default)]
+    pub bit_perfect: bool,
+    #[serde(default)]
+    pub resampler: Resampler,
+    /// Where the daemon listens for MPD clients, when built with the `mpd` feature.
+    #[serde(default = "default_mpd_address")]
+    pub mpd_address: String,
+    /// Start paused when the last session is restored, even if it was playing.
+    #[serde(default)]
+    pub resume_paused: bool,
+    /// Files at least this long continue where they were left, 0 to always start from the top.
+    #[serde(default = "default_resume_min_secs")]
+    pub resume_min_secs: u64,
+    /// Name of the equalizer preset in use.
+    #[serde(default = "default_equalizer_selected")]
+    pub equalizer_selected: String,
     pub add_playlist_front: bool,
     pub disable_exit_confirmation: bool,
     pub playlist_display_symbol: bool,
@@ -46,6 +85,8 @@ pub struct Termusic {
     pub album_photo_xywh: Xywh,
     pub style_color_symbol: StyleColorSymbol,
     pub keys: Keys,
+    #[serde(default = "default_equalizer_presets")]
+    pub equalizer_presets: Vec<EqualizerPreset>,
 }
 
 impl Default for Termusic {
@@ -55,6 +96,19 @@ impl Default for Termusic {
             music_dir_from_cli: None,
             loop_mode: Loop::Queue,
             volume: 70,
+            library_mode: LibraryMode::Directory,
+            backend: default_backend(),
+            backend_from_cli: None,
+            crossfade_secs: 0,
+            seek_step_secs: default_seek_step_secs(),
+            replaygain: ReplayGainMode::Off,
+            output_device: None,
+            bit_perfect: false,
+            resampler: Resampler::Linear,
+            mpd_address: default_mpd_address(),
+            resume_paused: false,
+            resume_min_secs: default_resume_min_secs(),
+            equalizer_selected: default_equalizer_selected(),
             add_playlist_front: false,
             disable_exit_confirmation: false,
             playlist_display_symbol: true,
@@ -62,11 +116,207 @@ impl Default for Termusic {
             theme_selected: "default".to_string(),
             style_color_symbol: StyleColorSymbol::default(),
             album_photo_xywh: Xywh::default(),
+            equalizer_presets: default_equalizer_presets(),
+        }
+    }
+}
+
+/// How the library tree is arranged, by folders or by the tags of the songs.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub enum LibraryMode {
+    #[default]
+    Directory,
+    /// Artist, album, song.
+    Artist,
+    /// Album artist, album, song.
+    AlbumArtist,
+    /// Genre, artist, song.
+    Genre,
+    /// Decade, year, song.
+    Year,
+}
+
+impl LibraryMode {
+    /// The mode after this one, back to the folders after the last.
+    pub const fn next(self) -> Self {
+        match self {
+            Self::Directory => Self::Artist,
+            Self::Artist => Self::AlbumArtist,
+            Self::AlbumArtist => Self::Genre,
+            Self::Genre => Self::Year,
+            Self::Year => Self::Directory,
+        }
+    }
+}
+
+impl fmt::Display for LibraryMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Self::Directory => "Folders",
+            Self::Artist => "Artists",
+            Self::AlbumArtist => "Album artists",
+            Self::Genre => "Genres",
+            Self::Year => "Years",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Which `ReplayGain` value the player normalizes songs with.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub enum ReplayGainMode {
+    #[default]
+    Off,
+    Track,
+    Album,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+    /// Built in, decoding with symphonia and playing through cpal.
+    Rusty,
+    Mpv,
+    Gst,
+}
+
+impl FromStr for Backend {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        match input {
+            "rusty" => Ok(Self::Rusty),
+            "mpv" => Ok(Self::Mpv),
+            "gst" => Ok(Self::Gst),
+            _ => Err(format!(
+                "{} is not a valid backend, use rusty, mpv or gst",
+                input
+            )),
         }
     }
 }
 
+impl fmt::Display for Backend {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let text = match self {
+            Self::Rusty => "rusty",
+            Self::Mpv => "mpv",
+            Self::Gst => "gst",
+        };
+        write!(f, "{}", text)
+    }
+}
+
+/// How the rusty backend converts songs to the sample rate of the output.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Resampler {
+    /// Linear interpolation, cheap but with audible aliasing.
+    #[default]
+    Linear,
+    /// Short windowed sinc filter.
+    SincFast,
+    /// Long windowed sinc filter, for when the CPU doesn't matter.
+    SincBest,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub enum EqualizerBandKind {
+    LowShelf,
+    Peaking,
+    HighShelf,
+}
+
+/// One band of the equalizer, `gain` in dB.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct EqualizerBand {
+    pub kind: EqualizerBandKind,
+    pub freq: f32,
+    pub gain: f32,
+    pub q: f32,
+}
+
+#[derive(Clone, Deserialize, Serialize)]
+pub struct EqualizerPreset {
+    pub name: String,
+    pub bands: Vec<EqualizerBand>,
+}
+
+impl EqualizerPreset {
+    // all presets share the bands, only the gains differ
+    fn new(name: &str, gains: [f32; 7]) -> Self {
+        let layout = [
+            (EqualizerBandKind::LowShelf, 60.0),
+            (EqualizerBandKind::Peaking, 150.0),
+            (EqualizerBandKind::Peaking, 400.0),
+            (EqualizerBandKind::Peaking, 1000.0),
+            (EqualizerBandKind::Peaking, 2500.0),
+            (EqualizerBandKind::Peaking, 6000.0),
+            (EqualizerBandKind::HighShelf, 12000.0),
+        ];
+        Self {
+            name: name.to_string(),
+            bands: layout
+                .iter()
+                .zip(gains)
+                .map(|((kind, freq), gain)| EqualizerBand {
+                    kind: *kind,
+                    freq: *freq,
+                    gain,
+                    q: 0.707,
+                })
+                .collect(),
+        }
+    }
+}
+
+// the backend a build with that feature used to be limited to
+const fn default_backend() -> Backend {
+    if cfg!(feature = "mpv") {
+        Backend::Mpv
+    } else if cfg!(feature = "gst") {
+        Backend::Gst
+    } else {
+        Backend::Rusty
+    }
+}
+
+const fn default_seek_step_secs() -> u64 {
+    5
+}
+
+fn default_mpd_address() -> String {
+    "127.0.0.1:6600".to_string()
+}
+
+const fn default_resume_min_secs() -> u64 {
+    20 * 60
+}
+
+fn default_equalizer_selected() -> String {
+    "flat".to_string()
+}
+
+fn default_equalizer_presets() -> Vec<EqualizerPreset> {
+    vec![
+        EqualizerPreset::new("flat", [0.0; 7]),
+        EqualizerPreset::new("bass boost", [6.0, 4.0, 1.0, 0.0, 0.0, 0.0, 0.0]),
+        EqualizerPreset::new("vocal", [-3.0, -2.0, 0.0, 3.0, 4.0, 2.0, 0.0]),
+        EqualizerPreset::new("treble boost", [0.0, 0.0, 0.0, 0.0, 2.0, 4.0, 6.0]),
+        EqualizerPreset::new("loudness", [5.0, 2.0, 0.0, -1.0, 0.0, 2.0, 4.0]),
+    ]
+}
+
 impl Termusic {
+    /// Bands of the selected equalizer preset, none when it isn't found.
+    pub fn equalizer_bands(&self) -> Vec<EqualizerBand> {
+        self.equalizer_presets
+            .iter()
+            .find(|preset| preset.name == self.equalizer_selected)
+            .map(|preset| preset.bands.clone())
+            .unwrap_or_default()
+    }
+
     pub fn save(&self) -> Result<()> {
         let mut path = get_app_config_path()?;
         path.push("config.toml");
@@ -0,0 +1,153 @@
+//! `termusic ctl`: drives the running daemon from the shell, for scripts and key bindings.
+//! A TUI playing by itself takes these commands too, over the same socket.
+use crate::daemon;
+use crate::daemon::protocol::{Notification, Request, State, Status};
+use anyhow::{anyhow, bail, Result};
+use std::path::Path;
+use std::time::Duration;
+
+pub const USAGE: &str = "\
+Commands of `termusic ctl`:
+    play              Resume, or start the playlist when stopped.
+    pause             Pause.
+    toggle            Pause or resume.
+    next              Play the next song.
+    prev              Play the previous song.
+    seek <SECS>       Seek to SECS, or by SECS with a leading + or -.
+    volume <VOL>      Set the volume to VOL, or change it by VOL with a leading + or -.
+    add <FILE>        Add FILE to the end of the playlist.
+    status [--json]   Print what is playing.";
+
+/// Runs the `ctl` command in `args`, the arguments after `ctl`.
+pub fn run(args: &[String]) -> Result<()> {
+    let command = args
+        .first()
+        .ok_or_else(|| anyhow!("ctl needs a command, see `termusic --help`"))?;
+    let argument = args.get(1).map(String::as_str);
+    let request = match (command.as_str(), argument) {
+        _ if args.len() > 2 => bail!("too many arguments for ctl {}", command),
+        ("play", None) => Request::Play,
+        ("pause", None) => Request::Pause,
+        ("toggle", None) => Request::TogglePause,
+        ("next", None) => Request::Next,
+        ("prev", None) => Request::Previous,
+        ("seek", Some(secs)) => seek(secs)?,
+        ("volume", Some(volume)) => Request::SetVolume {
+            volume: volume_from(volume, || Ok(status()?.volume))?,
+        },
+        ("add", Some(file)) => Request::Add {
+            file: Path::new(file)
+                .canonicalize()
+                .map_err(|e| anyhow!("{}: {}", file, e))?
+                .to_string_lossy()
+                .to_string(),
+            front: false,
+        },
+        ("status", None) => return print_status(&status()?, false),
+        ("status", Some("--json")) => return print_status(&status()?, true),
+        _ => bail!(
+            "unknown ctl command '{}', see `termusic --help`",
+            args.join(" ")
+        ),
+    };
+    daemon::request(&request)?;
+    Ok(())
+}
+
+fn seek(secs: &str) -> Result<Request> {
+    let value: i64 = secs
+        .parse()
+        .map_err(|_| anyhow!("seek needs a number of seconds, not '{}'", secs))?;
+    if secs.starts_with(['+', '-']) {
+        Ok(Request::Seek { secs: value })
+    } else {
+        Ok(Request::SeekTo {
+            position: Duration::from_secs(value.unsigned_abs()),
+        })
+    }
+}
+
+/// The volume `volume` asks for, `current` is only asked when it's relative.
+fn volume_from(volume: &str, current: impl FnOnce() -> Result<i32>) -> Result<i32> {
+    let value: i32 = volume
+        .parse()
+        .map_err(|_| anyhow!("volume needs a number, not '{}'", volume))?;
+    if volume.starts_with(['+', '-']) {
+        Ok(current()?.saturating_add(value).clamp(0, 100))
+    } else {
+        Ok(value.clamp(0, 100))
+    }
+}
+
+fn status() -> Result<Status> {
+    match daemon::request(&Request::Status)? {
+        Notification::Status(status) => Ok(status),
+        _ => bail!("termusic didn't answer with its status"),
+    }
+}
+
+fn print_status(status: &Status, json: bool) -> Result<()> {
+    if json {
+        println!("{}", serde_json::to_string(status)?);
+        return Ok(());
+    }
+    let state = match status.state {
+        State::Playing => "playing",
+        State::Paused => "paused",
+        State::Stopped => "stopped",
+    };
+    match (&status.artist, &status.title, &status.file) {
+        (Some(artist), Some(title), _) => println!("{}: {} - {}", state, artist, title),
+        (None, Some(title), _) => println!("{}: {}", state, title),
+        (_, None, Some(file)) => println!("{}: {}", state, file),
+        (_, None, None) => println!("{}", state),
+    }
+    if status.file.is_some() {
+        println!(
+            "position: {} / {}",
+            minutes(status.position),
+            minutes(status.duration)
+        );
+    }
+    println!("volume: {}", status.volume);
+    println!("loop: {:?}", status.loop_mode);
+    println!("playlist: {} songs", status.playlist_len);
+    Ok(())
+}
+
+fn minutes(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    format!("{}:{:02}", secs / 60, secs % 60)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_seek() {
+        assert!(matches!(seek("+10").unwrap(), Request::Seek { secs: 10 }));
+        assert!(matches!(seek("-5").unwrap(), Request::Seek { secs: -5 }));
+        assert!(matches!(
+            seek("90").unwrap(),
+            Request::SeekTo { position } if position == Duration::from_secs(90)
+        ));
+        assert!(seek("1:30").is_err());
+        assert!(seek("+").is_err());
+    }
+
+    #[test]
+    fn test_volume() {
+        let current = || Ok(50);
+        assert_eq!(volume_from("+10", current).unwrap(), 60);
+        assert_eq!(volume_from("-10", current).unwrap(), 40);
+        assert_eq!(volume_from("+80", current).unwrap(), 100);
+        assert_eq!(volume_from("-80", current).unwrap(), 0);
+        assert_eq!(volume_from("30", current).unwrap(), 30);
+        assert_eq!(volume_from("130", current).unwrap(), 100);
+        // an absolute volume doesn't need the current one
+        assert_eq!(volume_from("30", || bail!("not running")).unwrap(), 30);
+        assert!(volume_from("+10", || bail!("not running")).is_err());
+        assert!(volume_from("loud", current).is_err());
+    }
+}
@@ -0,0 +1,228 @@
+use super::protocol::{Notification, Request, State};
+use super::{socket_path, write_line};
+use crate::config::{EqualizerBand, Resampler};
+use crate::player::{GeneralP, PlayerEvent};
+use anyhow::{anyhow, bail, Result};
+use std::io::{BufRead, BufReader};
+use std::os::unix::net::UnixStream;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+/// The daemon's player as last heard of, so the getters don't have to ask.
+#[derive(Default)]
+struct Cache {
+    paused: bool,
+    volume: i32,
+    position: Duration,
+    duration: Duration,
+}
+
+/// Plays through the daemon: the player of a TUI that attached to one.
+pub struct Remote {
+    writer: Arc<Mutex<UnixStream>>,
+    cache: Arc<Mutex<Cache>>,
+}
+
+/// The rest of the connection, what the TUI needs besides the player.
+pub struct Link {
+    writer: Arc<Mutex<UnixStream>>,
+    notifications: Receiver<Notification>,
+}
+
+impl Link {
+    pub fn send(&self, request: &Request) {
+        send(&self.writer, request).ok();
+    }
+
+    /// Status and playlist changes since the last call, oldest first.
+    pub fn notifications(&self) -> Vec<Notification> {
+        self.notifications.try_iter().collect()
+    }
+}
+
+/// Connects to the running daemon and subscribes to it, its player events go to `events`.
+pub fn attach(events: Sender<PlayerEvent>) -> Result<(Remote, Link)> {
+    let stream = UnixStream::connect(socket_path()?)?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let writer = Arc::new(Mutex::new(stream));
+    send(&writer, &Request::Subscribe)?;
+    // a TUI playing by itself listens there as well, but can't be attached to
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    if let Notification::Error(e) = serde_json::from_str(&line)? {
+        bail!(e);
+    }
+
+    let cache = Arc::new(Mutex::new(Cache::default()));
+    let (tx, notifications) = mpsc::channel();
+    let cache_reader = cache.clone();
+    thread::spawn(move || {
+        for line in reader.lines().map_while(Result::ok) {
+            match serde_json::from_str(&line) {
+                Ok(Notification::Ok) | Err(_) => {}
+                Ok(Notification::Player(event)) => {
+                    cache_reader.lock().unwrap().update(&event);
+                    if events.send(event).is_err() {
+                        return;
+                    }
+                }
+                Ok(notification) => {
+                    if let Notification::Status(status) = &notification {
+                        let mut cache = cache_reader.lock().unwrap();
+                        cache.paused = status.state == State::Paused;
+                        cache.volume = status.volume;
+                        cache.position = status.position;
+                        cache.duration = status.duration;
+                    }
+                    if tx.send(notification).is_err() {
+                        return;
+                    }
+                }
+            }
+        }
+        tx.send(Notification::Error(
+            "Lost the connection to the termusic daemon.".to_string(),
+        ))
+        .ok();
+    });
+
+    Ok((
+        Remote {
+            writer: writer.clone(),
+            cache,
+        },
+        Link {
+            writer,
+            notifications,
+        },
+    ))
+}
+
+/// Sends one request to the running daemon and waits for its answer.
+pub fn request(request: &Request) -> Result<Notification> {
+    let mut stream = UnixStream::connect(socket_path()?).map_err(|e| {
+        anyhow!(
+            "couldn't reach termusic ({}), start it or `termusic daemon` first",
+            e
+        )
+    })?;
+    write_line(&mut stream, request)?;
+    let mut line = String::new();
+    BufReader::new(stream).read_line(&mut line)?;
+    match serde_json::from_str(&line)? {
+        Notification::Error(e) => bail!(e),
+        notification => Ok(notification),
+    }
+}
+
+impl Cache {
+    const fn update(&mut self, event: &PlayerEvent) {
+        match event {
+            PlayerEvent::TrackStarted(_) => {
+                self.paused = false;
+                self.position = Duration::ZERO;
+            }
+            PlayerEvent::PositionChanged(position) => self.position = *position,
+            PlayerEvent::DurationKnown(duration) => self.duration = *duration,
+            PlayerEvent::VolumeChanged(volume) => self.volume = *volume,
+            PlayerEvent::TrackEnded | PlayerEvent::Error(_) => {}
+        }
+    }
+}
+
+fn send(writer: &Mutex<UnixStream>, request: &Request) -> Result<()> {
+    write_line(&mut writer.lock().unwrap(), request)
+}
+
+impl Remote {
+    // the reader thread reports a lost connection, there's nothing more to do here
+    fn send(&self, request: &Request) {
+        send(&self.writer, request).ok();
+    }
+}
+
+impl GeneralP for Remote {
+    fn add_and_play(&mut self, new: &str, gain: f32) {
+        self.send(&Request::PlayFile {
+            file: new.to_string(),
+            gain: Some(gain),
+        });
+    }
+    fn volume(&self) -> i32 {
+        self.cache.lock().unwrap().volume
+    }
+    fn volume_up(&mut self) {
+        self.send(&Request::VolumeUp);
+    }
+    fn volume_down(&mut self) {
+        self.send(&Request::VolumeDown);
+    }
+    fn set_volume(&mut self, volume: i32) {
+        self.cache.lock().unwrap().volume = volume;
+        self.send(&Request::SetVolume { volume });
+    }
+    fn set_fade(&mut self, fade: f32) {
+        self.send(&Request::SetFade { fade });
+    }
+    fn pause(&mut self) {
+        self.cache.lock().unwrap().paused = true;
+        self.send(&Request::Pause);
+    }
+    fn resume(&mut self) {
+        self.cache.lock().unwrap().paused = false;
+        self.send(&Request::Resume);
+    }
+    fn is_paused(&mut self) -> bool {
+        self.cache.lock().unwrap().paused
+    }
+    fn seek(&mut self, secs: i64) -> Result<()> {
+        send(&self.writer, &Request::Seek { secs })
+    }
+    fn seek_to(&mut self, position: Duration) -> Result<()> {
+        send(&self.writer, &Request::SeekTo { position })
+    }
+    #[allow(clippy::cast_precision_loss, clippy::cast_possible_wrap)]
+    fn get_progress(&mut self) -> Result<(f64, i64, i64)> {
+        let (position, duration) = {
+            let cache = self.cache.lock().unwrap();
+            (
+                cache.position.as_secs() as i64,
+                cache.duration.as_secs() as i64,
+            )
+        };
+        let percent = if duration > 0 {
+            position as f64 * 100.0 / duration as f64
+        } else {
+            0.0
+        };
+        Ok((percent, position, duration))
+    }
+    fn enqueue_next(&mut self, _new: &str, _gain: f32) -> Result<()> {
+        bail!("the daemon enqueues the next song itself")
+    }
+    fn update(&mut self) {}
+    fn set_crossfade(&mut self, crossfade: Duration) {
+        self.send(&Request::SetCrossfade { crossfade });
+    }
+    fn set_equalizer(&mut self, bands: &[EqualizerBand]) {
+        self.send(&Request::SetEqualizer {
+            bands: bands.to_vec(),
+        });
+    }
+    fn set_output_device(&mut self, name: Option<&str>) {
+        self.send(&Request::SetOutputDevice {
+            name: name.map(ToString::to_string),
+        });
+    }
+    fn set_bit_perfect(&mut self, bit_perfect: bool) {
+        self.send(&Request::SetBitPerfect { bit_perfect });
+    }
+    fn set_resampler(&mut self, resampler: Resampler) {
+        self.send(&Request::SetResampler { resampler });
+    }
+    fn set_speed(&mut self, speed: f32) {
+        self.send(&Request::SetSpeed { speed });
+    }
+}
@@ -0,0 +1,724 @@
+//! Playback without the TUI. `termusic daemon` owns the player and the playlist and takes
+//! requests on a local socket, the TUI attaches to it when one is running. A TUI playing by
+//! itself listens on the same socket, for `termusic ctl`.
+mod client;
+#[cfg(feature = "mpd")]
+mod mpd;
+pub mod protocol;
+
+use crate::bookmarks;
+use crate::config::{get_app_config_path, Termusic};
+use crate::library_db::LibraryDb;
+use crate::player::{GeneralP, GeneralPl, PlayerEvent};
+use crate::session::{Session, SAVE_INTERVAL};
+use crate::song::Song;
+use crate::ui::Loop;
+use anyhow::{bail, Result};
+pub use client::{attach, request, Link};
+use protocol::{Notification, Request, State, Status};
+use std::collections::{HashMap, VecDeque};
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, Write};
+use std::net::Shutdown;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How long before the end of a song the next one gets enqueued, at least.
+const GAPLESS_PRELOAD_SECS: u64 = 5;
+/// How long a client may keep the daemon waiting to take a line, before it's dropped.
+const WRITE_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Where the daemon listens, in the runtime dir when there is one.
+pub fn socket_path() -> Result<PathBuf> {
+    let mut path = match dirs::runtime_dir() {
+        Some(dir) => dir,
+        None => get_app_config_path()?,
+    };
+    path.push("termusic.sock");
+    Ok(path)
+}
+
+/// What the connection threads pass on to the thread owning the player.
+enum Incoming {
+    Connected(usize, UnixStream),
+    Request(usize, Request),
+    Invalid(usize, String),
+    Disconnected(usize),
+    /// An MPD client, to be told about changes while it idles.
+    #[cfg(feature = "mpd")]
+    MpdConnected(Sender<mpd::Input>),
+    /// A command of an MPD client, split into its words.
+    #[cfg(feature = "mpd")]
+    Mpd(Vec<String>, Sender<mpd::Reply>),
+}
+
+struct Client {
+    stream: UnixStream,
+    subscribed: bool,
+}
+
+impl Client {
+    /// Sends `notification`, false when the client is gone or too slow to take it. It's
+    /// hung up on then, a line may have gone out only in part.
+    fn write(&mut self, notification: &Notification) -> bool {
+        let written = write_line(&mut self.stream, notification).is_ok();
+        if !written {
+            self.stream.shutdown(Shutdown::Both).ok();
+        }
+        written
+    }
+}
+
+/// Listens on the socket, unless a daemon or a TUI already does.
+fn bind() -> Result<(PathBuf, UnixListener)> {
+    let path = socket_path()?;
+    if path.exists() {
+        if UnixStream::connect(&path).is_ok() {
+            bail!("termusic is already listening on {}", path.display());
+        }
+        // left over from a termusic that didn't shut down cleanly
+        fs::remove_file(&path)?;
+    }
+    let listener = UnixListener::bind(&path)?;
+    Ok((path, listener))
+}
+
+/// Runs the daemon until it gets a `quit` request.
+pub fn run(config: &Termusic) -> Result<()> {
+    let (path, listener) = bind()?;
+    let (tx, rx) = mpsc::channel();
+    #[cfg(feature = "mpd")]
+    if let Err(e) = mpd::listen(&config.mpd_address, &tx) {
+        eprintln!(
+            "Couldn't listen for MPD clients on {}: {}",
+            config.mpd_address, e
+        );
+    }
+    thread::spawn(move || accept(&listener, &tx));
+
+    let mut playlist_file = get_app_config_path()?;
+    playlist_file.push("playlist.log");
+    let player = GeneralPl::new(config.backend_from_cli.unwrap_or(config.backend));
+    let mut daemon = Daemon::new(config, player, LibraryDb::load(), Some(playlist_file));
+    if let Some(fallback) = daemon.player.take_fallback() {
+        eprintln!("{}", fallback);
+    }
+    if let Some(session) = Session::load() {
+        daemon.restore(&session);
+    }
+    daemon.serve(&rx);
+    daemon.library_db.get_mut().unwrap().save().ok();
+    fs::remove_file(&path).ok();
+    Ok(())
+}
+
+fn accept(listener: &UnixListener, tx: &Sender<Incoming>) {
+    for (id, stream) in listener.incoming().flatten().enumerate() {
+        // a client that stops reading mustn't hold up the player
+        stream.set_write_timeout(Some(WRITE_TIMEOUT)).ok();
+        if let Ok(reader) = stream.try_clone() {
+            if tx.send(Incoming::Connected(id, stream)).is_err() {
+                return;
+            }
+            let tx = tx.clone();
+            thread::spawn(move || {
+                for line in BufReader::new(reader).lines().map_while(Result::ok) {
+                    let incoming = match serde_json::from_str(&line) {
+                        Ok(request) => Incoming::Request(id, request),
+                        Err(e) => Incoming::Invalid(id, format!("invalid request: {}", e)),
+                    };
+                    if tx.send(incoming).is_err() {
+                        return;
+                    }
+                }
+                tx.send(Incoming::Disconnected(id)).ok();
+            });
+        }
+    }
+}
+
+/// The socket of a TUI playing by itself, so `termusic ctl` reaches it like a daemon. Only
+/// the requests of `ctl` are taken, the TUI answers the others with an error.
+pub struct Control {
+    path: PathBuf,
+    rx: Receiver<Incoming>,
+    clients: HashMap<usize, UnixStream>,
+}
+
+impl Control {
+    pub fn listen() -> Result<Self> {
+        let (path, listener) = bind()?;
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || accept(&listener, &tx));
+        Ok(Self {
+            path,
+            rx,
+            clients: HashMap::new(),
+        })
+    }
+
+    /// The requests since the last call, oldest first, each to be answered with `reply`.
+    pub fn requests(&mut self) -> Vec<(usize, Request)> {
+        let mut requests = Vec::new();
+        while let Ok(incoming) = self.rx.try_recv() {
+            match incoming {
+                Incoming::Connected(id, stream) => {
+                    self.clients.insert(id, stream);
+                }
+                Incoming::Request(id, request) => requests.push((id, request)),
+                Incoming::Invalid(id, e) => self.reply(id, &Notification::Error(e)),
+                Incoming::Disconnected(id) => {
+                    self.clients.remove(&id);
+                }
+                #[cfg(feature = "mpd")]
+                Incoming::MpdConnected(_) | Incoming::Mpd(..) => {}
+            }
+        }
+        requests
+    }
+
+    pub fn reply(&mut self, id: usize, notification: &Notification) {
+        if let Some(stream) = self.clients.get_mut(&id) {
+            if write_line(stream, notification).is_err() {
+                stream.shutdown(Shutdown::Both).ok();
+                self.clients.remove(&id);
+            }
+        }
+    }
+}
+
+impl Drop for Control {
+    fn drop(&mut self) {
+        fs::remove_file(&self.path).ok();
+    }
+}
+
+struct Daemon {
+    config: Termusic,
+    player: GeneralPl,
+    /// Tags of the songs played, so a hand-off doesn't wait on reading the file.
+    library_db: Mutex<LibraryDb>,
+    playlist: VecDeque<String>,
+    /// Where the playlist is kept between runs, if anywhere. The session is kept only with it.
+    playlist_file: Option<PathBuf>,
+    current: Option<Song>,
+    /// File handed to `enqueue_next` for gapless playback, if any.
+    enqueued: Option<String>,
+    state: State,
+    position: Duration,
+    duration: Duration,
+    /// Where the song restored from the last session continues, once the player started it.
+    resume: Option<Duration>,
+    session_saved: Instant,
+    /// Playback speed of the current song.
+    speed: f32,
+    clients: HashMap<usize, Client>,
+    #[cfg(feature = "mpd")]
+    mpd: mpd::Clients,
+    quit: bool,
+}
+
+impl Daemon {
+    fn new(
+        config: &Termusic,
+        mut player: GeneralPl,
+        library_db: LibraryDb,
+        playlist_file: Option<PathBuf>,
+    ) -> Self {
+        player.apply_config(config);
+        Self {
+            config: config.clone(),
+            player,
+            library_db: Mutex::new(library_db),
+            playlist: playlist_file
+                .as_deref()
+                .and_then(|path| load_playlist(path).ok())
+                .unwrap_or_default(),
+            playlist_file,
+            current: None,
+            enqueued: None,
+            state: State::Stopped,
+            position: Duration::ZERO,
+            duration: Duration::ZERO,
+            resume: None,
+            session_saved: Instant::now(),
+            speed: 1.0,
+            clients: HashMap::new(),
+            #[cfg(feature = "mpd")]
+            mpd: mpd::Clients::default(),
+            quit: false,
+        }
+    }
+
+    /// Takes what comes in from the clients, and follows the player, until `quit`.
+    fn serve(&mut self, rx: &Receiver<Incoming>) {
+        while !self.quit {
+            match rx.recv_timeout(Duration::from_millis(20)) {
+                Ok(incoming) => self.handle(incoming),
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+            self.update_player_events();
+            if self.session_saved.elapsed() >= SAVE_INTERVAL {
+                self.save_session().ok();
+            }
+        }
+        self.save_playlist().ok();
+        self.save_session().ok();
+    }
+
+    /// Plays the song of `session` from where it was, paused if it was or if configured so.
+    fn restore(&mut self, session: &Session) {
+        self.config.loop_mode = session.loop_mode.clone();
+        session.align(&mut self.playlist, |file| Some(file.as_str()));
+        if let Some(file) = &session.file {
+            self.play(file, None);
+            self.position = Duration::from_secs(session.position);
+            self.resume = Some(self.position).filter(|p| !p.is_zero());
+            if session.paused || self.config.resume_paused {
+                self.pause();
+            }
+        }
+    }
+
+    fn handle(&mut self, incoming: Incoming) {
+        match incoming {
+            Incoming::Connected(id, stream) => {
+                self.clients.insert(
+                    id,
+                    Client {
+                        stream,
+                        subscribed: false,
+                    },
+                );
+            }
+            Incoming::Request(id, request) => {
+                let subscribe = matches!(request, Request::Subscribe);
+                let reply = match self.request(id, request) {
+                    Ok(reply) => reply,
+                    Err(e) => Notification::Error(e.to_string()),
+                };
+                self.send(id, &reply);
+                if subscribe {
+                    // a fresh subscriber starts from the current state
+                    let status = Notification::Status(self.status());
+                    let playlist = Notification::Playlist(self.playlist.iter().cloned().collect());
+                    self.send(id, &status);
+                    self.send(id, &playlist);
+                }
+            }
+            Incoming::Invalid(id, e) => self.send(id, &Notification::Error(e)),
+            Incoming::Disconnected(id) => {
+                self.clients.remove(&id);
+            }
+            #[cfg(feature = "mpd")]
+            Incoming::MpdConnected(client) => self.mpd.connected(client),
+            #[cfg(feature = "mpd")]
+            Incoming::Mpd(command, reply) => {
+                reply.send(self.mpd(&command)).ok();
+            }
+        }
+    }
+
+    #[allow(clippy::too_many_lines)]
+    fn request(&mut self, id: usize, request: Request) -> Result<Notification> {
+        match request {
+            Request::PlayFile { file, gain } => {
+                if !Path::new(&file).exists() {
+                    bail!("{} doesn't exist", file);
+                }
+                self.play(&file, gain);
+                self.broadcast(&Notification::Status(self.status()), None);
+            }
+            Request::Play => match self.state {
+                State::Playing => {}
+                State::Paused => self.resume(),
+                State::Stopped => self.next(false),
+            },
+            Request::Pause => self.pause(),
+            Request::Resume => self.resume(),
+            Request::TogglePause => match self.state {
+                State::Playing => self.pause(),
+                State::Paused => self.resume(),
+                State::Stopped => {}
+            },
+            Request::Next => self.next(false),
+            Request::Previous => self.previous(),
+            Request::Seek { secs } => self.player.seek(secs)?,
+            Request::SeekTo { position } => self.player.seek_to(position)?,
+            Request::SetVolume { volume } => self.player.set_volume(volume),
+            Request::VolumeUp => self.player.volume_up(),
+            Request::VolumeDown => self.player.volume_down(),
+            Request::SetFade { fade } => self.player.set_fade(fade),
+            Request::SetCrossfade { crossfade } => {
+                self.config.crossfade_secs = crossfade.as_secs();
+                self.player.set_crossfade(crossfade);
+            }
+            Request::SetEqualizer { bands } => self.player.set_equalizer(&bands),
+            Request::SetOutputDevice { name } => self.player.set_output_device(name.as_deref()),
+            Request::SetBitPerfect { bit_perfect } => self.player.set_bit_perfect(bit_perfect),
+            Request::SetResampler { resampler } => self.player.set_resampler(resampler),
+            Request::SetSpeed { speed } => self.set_speed(speed),
+            Request::SetLoopMode { mode } => {
+                self.config.loop_mode = mode;
+                self.broadcast(&Notification::Status(self.status()), None);
+            }
+            Request::SetPlaylist { files } => {
+                self.playlist = files.into();
+                self.playlist_changed(Some(id));
+            }
+            Request::Add { file, front } => {
+                if !Path::new(&file).exists() {
+                    bail!("{} doesn't exist", file);
+                }
+                if front {
+                    self.playlist.push_front(file);
+                } else {
+                    self.playlist.push_back(file);
+                }
+                self.playlist_changed(None);
+            }
+            Request::Remove { index } => {
+                if self.playlist.remove(index).is_none() {
+                    bail!("the playlist has no song {}", index);
+                }
+                self.playlist_changed(None);
+            }
+            Request::Clear => {
+                self.playlist.clear();
+                self.playlist_changed(None);
+            }
+            Request::Status => return Ok(Notification::Status(self.status())),
+            Request::Subscribe => {
+                if let Some(client) = self.clients.get_mut(&id) {
+                    client.subscribed = true;
+                }
+            }
+            Request::Quit => self.quit = true,
+        }
+        Ok(Notification::Ok)
+    }
+
+    fn update_player_events(&mut self) {
+        for event in self.player.events() {
+            match &event {
+                PlayerEvent::TrackStarted(file) => {
+                    if self.enqueued.as_deref() == Some(file.as_str()) {
+                        self.next(true);
+                    }
+                }
+                PlayerEvent::TrackEnded => {
+                    if !self.playlist.is_empty() {
+                        // the clients hear about the next song instead
+                        self.next(false);
+                        continue;
+                    }
+                    self.remember_position();
+                    self.state = State::Stopped;
+                    self.current = None;
+                }
+                PlayerEvent::PositionChanged(position) => {
+                    self.position = *position;
+                    self.enqueue_next();
+                }
+                PlayerEvent::DurationKnown(duration) => {
+                    self.duration = *duration;
+                    if let Some(position) = self.resume.take() {
+                        self.player.seek_to(position).ok();
+                    }
+                }
+                PlayerEvent::VolumeChanged(volume) => self.config.volume = *volume,
+                PlayerEvent::Error(_) => {}
+            }
+            self.broadcast(&Notification::Player(event), None);
+        }
+    }
+
+    fn play(&mut self, file: &str, gain: Option<f32>) {
+        let song = LibraryDb::song(&self.library_db, file).ok();
+        let gain = gain.unwrap_or_else(|| {
+            song.as_ref()
+                .map_or(1.0, |song| song.replaygain().factor(self.config.replaygain))
+        });
+        self.remember_position();
+        self.enqueued = None;
+        self.duration = Duration::ZERO;
+        self.position = Duration::ZERO;
+        self.set_speed(song.as_ref().map_or(1.0, |song| self.speed(song)));
+        self.player.add_and_play(file, gain);
+        self.resume = song.as_ref().and_then(|song| self.resume_position(song));
+        self.current = song;
+        self.state = State::Playing;
+    }
+
+    /// Moves on to the next song of the playlist, like the TUI does when it plays by itself.
+    /// With `handoff`, the player has already started the enqueued one.
+    fn next(&mut self, handoff: bool) {
+        let enqueued = self.enqueued.take();
+        if let Some(file) = self.playlist.pop_front() {
+            if handoff && enqueued.as_deref() == Some(file.as_str()) {
+                self.remember_position();
+                // its duration has been reported already
+                self.position = Duration::ZERO;
+                self.current = LibraryDb::song(&self.library_db, &file).ok();
+                self.set_speed(self.current.as_ref().map_or(1.0, |s| self.speed(s)));
+                if let Some(position) = self.current.as_ref().and_then(|s| self.resume_position(s))
+                {
+                    self.player.seek_to(position).ok();
+                }
+            } else {
+                self.play(&file, None);
+            }
+            match self.config.loop_mode {
+                Loop::Playlist => self.playlist.push_back(file),
+                Loop::Single => self.playlist.push_front(file),
+                Loop::Queue => {}
+            }
+            self.playlist_changed(None);
+            self.broadcast(&Notification::Status(self.status()), None);
+        }
+    }
+
+    fn previous(&mut self) {
+        if let Loop::Single | Loop::Queue = self.config.loop_mode {
+            return;
+        }
+        for _ in 0..2 {
+            if let Some(file) = self.playlist.pop_back() {
+                self.playlist.push_front(file);
+            }
+        }
+        self.next(false);
+    }
+
+    fn pause(&mut self) {
+        if self.state == State::Playing {
+            self.player.pause();
+            self.state = State::Paused;
+            self.broadcast(&Notification::Status(self.status()), None);
+        }
+    }
+
+    fn resume(&mut self) {
+        if self.state == State::Paused {
+            self.player.resume();
+            self.state = State::Playing;
+            self.broadcast(&Notification::Status(self.status()), None);
+        }
+    }
+
+    /// Preload the song after the current one, early enough for the crossfade.
+    fn enqueue_next(&mut self) {
+        let preload = Duration::from_secs(GAPLESS_PRELOAD_SECS.max(self.config.crossfade_secs + 1))
+            .mul_f32(self.speed.max(1.0));
+        if self.enqueued.is_some()
+            || self.state != State::Playing
+            || self.duration.is_zero()
+            || self.duration.saturating_sub(self.position) > preload
+        {
+            return;
+        }
+        if let Some(file) = self.playlist.front() {
+            let gain = LibraryDb::song(&self.library_db, file)
+                .map_or(1.0, |song| song.replaygain().factor(self.config.replaygain));
+            if self.player.enqueue_next(file, gain).is_ok() {
+                self.enqueued = Some(file.clone());
+            }
+        }
+    }
+
+    fn status(&self) -> Status {
+        let song = self.current.as_ref();
+        Status {
+            state: self.state,
+            file: song.and_then(Song::file).map(ToString::to_string),
+            title: song.and_then(Song::title).map(ToString::to_string),
+            artist: song.and_then(Song::artist).map(ToString::to_string),
+            position: self.position,
+            duration: self.duration,
+            volume: self.player.volume(),
+            loop_mode: self.config.loop_mode.clone(),
+            playlist_len: self.playlist.len(),
+        }
+    }
+
+    /// Tells the subscribers about the playlist, except `origin` which changed it itself.
+    fn playlist_changed(&mut self, origin: Option<usize>) {
+        // the enqueued song may not be the next one anymore
+        if self.enqueued.is_some() && self.enqueued.as_ref() != self.playlist.front() {
+            self.enqueued = None;
+        }
+        self.save_playlist().ok();
+        let files = self.playlist.iter().cloned().collect();
+        self.broadcast(&Notification::Playlist(files), origin);
+    }
+
+    fn save_playlist(&self) -> Result<()> {
+        if let Some(path) = &self.playlist_file {
+            let mut file = File::create(path)?;
+            for f in &self.playlist {
+                writeln!(&mut file, "{}", f)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Keeps where the current song was left, so it continues there next time. Like the
+    /// session, only when the playlist is kept.
+    fn remember_position(&self) {
+        if let (Some(song), Some(_)) = (&self.current, &self.playlist_file) {
+            if let Some(file) = song.file() {
+                bookmarks::remember(
+                    file,
+                    self.position,
+                    song.duration(),
+                    self.config.resume_min_secs,
+                )
+                .ok();
+            }
+        }
+    }
+
+    fn resume_position(&self, song: &Song) -> Option<Duration> {
+        self.playlist_file.as_ref()?;
+        bookmarks::resume_position(song.file()?, song.duration(), self.config.resume_min_secs)
+    }
+
+    fn set_speed(&mut self, speed: f32) {
+        self.speed = speed;
+        self.player.set_speed(speed);
+    }
+
+    /// The speed `song` was played at last time, which the TUI keeps.
+    fn speed(&self, song: &Song) -> f32 {
+        match (&self.playlist_file, song.file()) {
+            (Some(_), Some(file)) => bookmarks::speed(file),
+            _ => 1.0,
+        }
+    }
+
+    fn save_session(&mut self) -> Result<()> {
+        self.session_saved = Instant::now();
+        if self.playlist_file.is_none() {
+            return Ok(());
+        }
+        if self.state != State::Stopped {
+            self.remember_position();
+        }
+        let file = match self.state {
+            State::Playing | State::Paused => self.current.as_ref().and_then(Song::file),
+            State::Stopped => None,
+        };
+        Session::new(
+            file,
+            self.position,
+            self.state == State::Paused,
+            &self.config.loop_mode,
+            self.playlist.iter().map(|file| Some(file.as_str())),
+        )
+        .save()
+    }
+
+    fn send(&mut self, id: usize, notification: &Notification) {
+        if let Some(client) = self.clients.get_mut(&id) {
+            if !client.write(notification) {
+                self.clients.remove(&id);
+            }
+        }
+    }
+
+    fn broadcast(&mut self, notification: &Notification, except: Option<usize>) {
+        #[cfg(feature = "mpd")]
+        self.mpd.notify(notification);
+        self.clients.retain(|id, client| {
+            !client.subscribed || Some(*id) == except || client.write(notification)
+        });
+    }
+}
+
+/// The playlist saved last, skipping files that are gone.
+fn load_playlist(path: &Path) -> Result<VecDeque<String>> {
+    let reader = BufReader::new(File::open(path)?);
+    Ok(reader
+        .lines()
+        .map_while(Result::ok)
+        .filter(|line| Path::new(line).exists())
+        .collect())
+}
+
+fn write_line<T: serde::Serialize>(stream: &mut UnixStream, message: &T) -> Result<()> {
+    let mut line = serde_json::to_string(message)?;
+    line.push('\n');
+    stream.write_all(line.as_bytes())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Backend;
+
+    fn ask(stream: &mut UnixStream, request: &Request) -> Notification {
+        write_line(stream, request).unwrap();
+        let mut line = String::new();
+        BufReader::new(stream.try_clone().unwrap())
+            .read_line(&mut line)
+            .unwrap();
+        serde_json::from_str(&line).unwrap()
+    }
+
+    #[test]
+    fn test_socket_round_trip() {
+        let path = std::env::temp_dir().join(format!("termusic-{}.sock", std::process::id()));
+        fs::remove_file(&path).ok();
+        let listener = UnixListener::bind(&path).unwrap();
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || accept(&listener, &tx));
+        let daemon = thread::spawn(move || {
+            let mut daemon = Daemon::new(
+                &Termusic::default(),
+                GeneralPl::new(Backend::Rusty),
+                LibraryDb::default(),
+                None,
+            );
+            daemon.serve(&rx);
+        });
+
+        let mut client = UnixStream::connect(&path).unwrap();
+        // subscribes, then never reads what it's sent
+        let mut stalled = UnixStream::connect(&path).unwrap();
+        assert!(matches!(
+            ask(&mut stalled, &Request::Subscribe),
+            Notification::Ok
+        ));
+
+        let files: Vec<String> = (0..4000)
+            .map(|i| format!("/music/some artist/some album/{i:04} a song.mp3"))
+            .collect();
+        for _ in 0..5 {
+            let request = Request::SetPlaylist {
+                files: files.clone(),
+            };
+            assert!(matches!(ask(&mut client, &request), Notification::Ok));
+        }
+        match ask(&mut client, &Request::Status) {
+            Notification::Status(status) => {
+                assert_eq!(status.state, State::Stopped);
+                assert_eq!(status.playlist_len, files.len());
+            }
+            other => panic!("expected the status, got {other:?}"),
+        }
+        assert!(matches!(
+            ask(&mut client, &Request::Remove { index: 4000 }),
+            Notification::Error(_)
+        ));
+
+        assert!(matches!(ask(&mut client, &Request::Quit), Notification::Ok));
+        daemon.join().unwrap();
+        fs::remove_file(&path).ok();
+    }
+}
@@ -0,0 +1,794 @@
+//! Enough of the MPD protocol for MPD clients to drive the daemon: playback, volume, the
+//! playlist, browsing `music_dir` and waiting for changes with `idle`.
+use super::protocol::{Notification, State};
+use super::{Daemon, Incoming};
+use crate::library_db::LibraryDb;
+use crate::player::{GeneralP, PlayerEvent};
+use crate::song::Song;
+use crate::ui::Loop;
+use anyhow::Result;
+use std::fmt::Write as _;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+const GREETING: &str = "OK MPD 0.23.0\n";
+
+const ACK_ERROR_ARG: u32 = 2;
+const ACK_ERROR_UNKNOWN: u32 = 5;
+const ACK_ERROR_NO_EXIST: u32 = 50;
+const ACK_ERROR_SYSTEM: u32 = 52;
+
+const COMMANDS: [&str; 17] = [
+    "add",
+    "clear",
+    "currentsong",
+    "delete",
+    "deleteid",
+    "lsinfo",
+    "next",
+    "pause",
+    "play",
+    "playid",
+    "playlistinfo",
+    "previous",
+    "seekcur",
+    "setvol",
+    "status",
+    "ping",
+    "close",
+];
+
+/// What a connection waits for: a line from its client, or a change in the daemon.
+pub enum Input {
+    Line(String),
+    Changed(Subsystem),
+    Closed,
+}
+
+/// The parts of the daemon `idle` waits on.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Subsystem {
+    Player,
+    Mixer,
+    Playlist,
+    Options,
+}
+
+impl Subsystem {
+    const fn name(self) -> &'static str {
+        match self {
+            Self::Player => "player",
+            Self::Mixer => "mixer",
+            Self::Playlist => "playlist",
+            Self::Options => "options",
+        }
+    }
+}
+
+/// The response lines to a command without the closing `OK`, or why it failed.
+pub type Reply = std::result::Result<String, Ack>;
+
+pub struct Ack {
+    code: u32,
+    message: String,
+}
+
+impl Ack {
+    fn arg(message: impl Into<String>) -> Self {
+        Self {
+            code: ACK_ERROR_ARG,
+            message: message.into(),
+        }
+    }
+
+    fn line(&self, index: usize, command: &str) -> String {
+        format!(
+            "ACK [{}@{}] {{{}}} {}\n",
+            self.code, index, command, self.message
+        )
+    }
+}
+
+/// The MPD clients of the daemon.
+#[derive(Default)]
+pub struct Clients {
+    clients: Vec<Sender<Input>>,
+    /// Goes up with every change of the playlist, clients reload it when it does.
+    playlist_version: u32,
+}
+
+impl Clients {
+    pub fn connected(&mut self, client: Sender<Input>) {
+        self.clients.push(client);
+    }
+
+    pub fn notify(&mut self, notification: &Notification) {
+        match notification {
+            Notification::Status(_) => {
+                self.changed(Subsystem::Player);
+                self.changed(Subsystem::Options);
+            }
+            Notification::Playlist(_) => {
+                self.playlist_version += 1;
+                self.changed(Subsystem::Playlist);
+            }
+            Notification::Player(PlayerEvent::TrackStarted(_) | PlayerEvent::TrackEnded) => {
+                self.changed(Subsystem::Player);
+            }
+            Notification::Player(PlayerEvent::VolumeChanged(_)) => self.changed(Subsystem::Mixer),
+            Notification::Player(_) | Notification::Ok | Notification::Error(_) => {}
+        }
+    }
+
+    fn changed(&mut self, subsystem: Subsystem) {
+        self.clients
+            .retain(|client| client.send(Input::Changed(subsystem)).is_ok());
+    }
+}
+
+/// Starts taking MPD clients on `address`.
+pub fn listen(address: &str, tx: &Sender<Incoming>) -> Result<()> {
+    let listener = TcpListener::bind(address)?;
+    let tx = tx.clone();
+    thread::spawn(move || serve(&listener, &tx));
+    Ok(())
+}
+
+fn serve(listener: &TcpListener, tx: &Sender<Incoming>) {
+    for stream in listener.incoming().flatten() {
+        let tx = tx.clone();
+        thread::spawn(move || connection(stream, &tx).ok());
+    }
+}
+
+fn connection(mut stream: TcpStream, tx: &Sender<Incoming>) -> Result<()> {
+    let (input_tx, input) = mpsc::channel();
+    let reader = stream.try_clone()?;
+    let lines = input_tx.clone();
+    thread::spawn(move || {
+        for line in BufReader::new(reader).lines().map_while(Result::ok) {
+            if lines.send(Input::Line(line)).is_err() {
+                return;
+            }
+        }
+        lines.send(Input::Closed).ok();
+    });
+    tx.send(Incoming::MpdConnected(input_tx))?;
+    stream.write_all(GREETING.as_bytes())?;
+
+    let mut changed = Vec::new();
+    // the commands of an open command list, and whether each gets a `list_OK`
+    let mut list: Option<(Vec<String>, bool)> = None;
+    loop {
+        let line = match input.recv()? {
+            Input::Line(line) => line,
+            Input::Changed(subsystem) => {
+                if !changed.contains(&subsystem) {
+                    changed.push(subsystem);
+                }
+                continue;
+            }
+            Input::Closed => return Ok(()),
+        };
+        if let Some((commands, list_ok)) = &mut list {
+            if line == "command_list_end" {
+                let response = execute_list(tx, commands, *list_ok);
+                stream.write_all(response.as_bytes())?;
+                list = None;
+            } else {
+                commands.push(line);
+            }
+            continue;
+        }
+        let response = match line.as_str() {
+            "command_list_begin" => {
+                list = Some((Vec::new(), false));
+                continue;
+            }
+            "command_list_ok_begin" => {
+                list = Some((Vec::new(), true));
+                continue;
+            }
+            "close" => return Ok(()),
+            // only meaningful while idling
+            "noidle" => continue,
+            _ if line == "idle" || line.starts_with("idle ") => {
+                match idle(&line, &input, &mut changed) {
+                    Some(response) => response,
+                    None => return Ok(()),
+                }
+            }
+            _ => execute_list(tx, &[line], false),
+        };
+        stream.write_all(response.as_bytes())?;
+    }
+}
+
+/// Waits for a change in one of the subsystems `line` asks for, or all of them, until the
+/// client sends `noidle`. `None` when the client goes away instead.
+fn idle(line: &str, input: &Receiver<Input>, changed: &mut Vec<Subsystem>) -> Option<String> {
+    let names: Vec<String> = split(line).ok()?.into_iter().skip(1).collect();
+    let wanted = |subsystem: &Subsystem| {
+        names.is_empty() || names.iter().any(|name| name == subsystem.name())
+    };
+    loop {
+        if changed.iter().any(wanted) {
+            let mut response = String::new();
+            changed.retain(|subsystem| {
+                if wanted(subsystem) {
+                    writeln!(response, "changed: {}", subsystem.name()).ok();
+                    false
+                } else {
+                    true
+                }
+            });
+            response.push_str("OK\n");
+            return Some(response);
+        }
+        match input.recv().ok()? {
+            Input::Changed(subsystem) => {
+                if !changed.contains(&subsystem) {
+                    changed.push(subsystem);
+                }
+            }
+            Input::Line(line) if line == "noidle" => return Some("OK\n".to_string()),
+            // anything else while idling is a protocol error, MPD hangs up on it too
+            Input::Line(_) | Input::Closed => return None,
+        }
+    }
+}
+
+/// Runs `commands` one after the other, stopping at the first that fails.
+fn execute_list(tx: &Sender<Incoming>, commands: &[String], list_ok: bool) -> String {
+    let mut response = String::new();
+    for (index, line) in commands.iter().enumerate() {
+        let name = line.split_whitespace().next().unwrap_or_default();
+        match split(line).and_then(|words| execute(tx, words)) {
+            Ok(lines) => response.push_str(&lines),
+            Err(ack) => {
+                response.push_str(&ack.line(index, name));
+                return response;
+            }
+        }
+        if list_ok {
+            response.push_str("list_OK\n");
+        }
+    }
+    response.push_str("OK\n");
+    response
+}
+
+fn execute(tx: &Sender<Incoming>, words: Vec<String>) -> Reply {
+    match words.first().map(String::as_str) {
+        None => Err(Ack {
+            code: ACK_ERROR_UNKNOWN,
+            message: "No command given".to_string(),
+        }),
+        Some("ping") => Ok(String::new()),
+        Some(_) => {
+            let (reply_tx, reply) = mpsc::channel();
+            tx.send(Incoming::Mpd(words, reply_tx))
+                .map_err(|_| Ack::system("the daemon shut down"))?;
+            reply
+                .recv()
+                .map_err(|_| Ack::system("the daemon shut down"))?
+        }
+    }
+}
+
+/// Splits a command line into its words, unquoting the quoted ones.
+fn split(line: &str) -> std::result::Result<Vec<String>, Ack> {
+    let mut words = Vec::new();
+    let mut chars = line.chars().peekable();
+    loop {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        match chars.next() {
+            None => return Ok(words),
+            Some('"') => {
+                let mut word = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => word.extend(chars.next()),
+                        Some(c) => word.push(c),
+                        None => return Err(Ack::arg("Missing closing '\"'")),
+                    }
+                }
+                words.push(word);
+            }
+            Some(c) => {
+                let mut word = c.to_string();
+                while let Some(c) = chars.next_if(|c| !c.is_whitespace()) {
+                    word.push(c);
+                }
+                words.push(word);
+            }
+        }
+    }
+}
+
+impl Ack {
+    fn system(message: impl Into<String>) -> Self {
+        Self {
+            code: ACK_ERROR_SYSTEM,
+            message: message.into(),
+        }
+    }
+
+    fn no_exist(message: impl Into<String>) -> Self {
+        Self {
+            code: ACK_ERROR_NO_EXIST,
+            message: message.into(),
+        }
+    }
+}
+
+impl Daemon {
+    /// Runs the MPD command made of `words`.
+    #[allow(clippy::too_many_lines)]
+    pub(super) fn mpd(&mut self, words: &[String]) -> Reply {
+        let (name, args) = match words.split_first() {
+            Some((name, args)) => (name.as_str(), args),
+            None => return Ok(String::new()),
+        };
+        match (name, args) {
+            ("status", []) => return Ok(self.mpd_status()),
+            ("currentsong", []) => {
+                return Ok(self.current.as_ref().map_or_else(String::new, |song| {
+                    self.mpd_song(song, self.mpd_current_index())
+                }));
+            }
+            ("play" | "playid", []) => match self.state {
+                State::Playing => {}
+                State::Paused => self.resume(),
+                State::Stopped => self.next(false),
+            },
+            ("play" | "playid", [position]) => {
+                let index = self.mpd_index(position)?;
+                // the songs before it go round, so the playlist stays in order
+                self.playlist.rotate_left(index);
+                self.next(false);
+            }
+            ("pause", []) => match self.state {
+                State::Playing => self.pause(),
+                State::Paused => self.resume(),
+                State::Stopped => {}
+            },
+            ("pause", [pause]) => match pause.as_str() {
+                "1" => self.pause(),
+                "0" => self.resume(),
+                _ => return Err(Ack::arg(format!("Boolean (0/1) expected: {}", pause))),
+            },
+            ("next", []) => self.next(false),
+            ("previous", []) => self.previous(),
+            ("seekcur", [time]) => {
+                let secs: f64 = time
+                    .parse()
+                    .map_err(|_| Ack::arg(format!("Number expected: {}", time)))?;
+                #[allow(clippy::cast_possible_truncation)]
+                let sought = if time.starts_with(['+', '-']) {
+                    self.player.seek(secs.round() as i64)
+                } else {
+                    self.player.seek_to(Duration::from_secs_f64(secs.max(0.0)))
+                };
+                sought.map_err(|e| Ack::system(e.to_string()))?;
+                self.mpd.changed(Subsystem::Player);
+            }
+            ("setvol", [volume]) => match volume.parse() {
+                Ok(volume @ 0..=100) => self.player.set_volume(volume),
+                _ => return Err(Ack::arg(format!("Invalid volume value: {}", volume))),
+            },
+            ("add", [uri]) => {
+                let path = self.mpd_path(uri)?;
+                if path.is_dir() {
+                    let mut files = Vec::new();
+                    songs_in(&self.library_db, &path, &mut files);
+                    self.playlist.extend(files);
+                } else if path.is_file() {
+                    self.playlist.push_back(path.to_string_lossy().to_string());
+                } else {
+                    return Err(Ack::no_exist("Not found"));
+                }
+                self.playlist_changed(None);
+            }
+            ("delete", [range]) => {
+                let (start, end) = self.mpd_range(range)?;
+                self.playlist.drain(start..end);
+                self.playlist_changed(None);
+            }
+            ("deleteid", [id]) => {
+                let index = self.mpd_index(id)?;
+                self.playlist.remove(index);
+                self.playlist_changed(None);
+            }
+            ("clear", []) => {
+                self.playlist.clear();
+                self.playlist_changed(None);
+            }
+            ("playlistinfo", []) => return Ok(self.mpd_playlist(0, self.playlist.len())),
+            ("playlistinfo", [range]) => {
+                let (start, end) = self.mpd_range(range)?;
+                return Ok(self.mpd_playlist(start, end));
+            }
+            ("lsinfo", []) => return self.mpd_lsinfo(""),
+            ("lsinfo", [uri]) => return self.mpd_lsinfo(uri),
+            _ if COMMANDS.contains(&name) => {
+                return Err(Ack::arg(format!(
+                    "wrong number of arguments for \"{}\"",
+                    name
+                )))
+            }
+            _ => {
+                return Err(Ack {
+                    code: ACK_ERROR_UNKNOWN,
+                    message: format!("unknown command \"{}\"", name),
+                })
+            }
+        }
+        Ok(String::new())
+    }
+
+    fn mpd_status(&self) -> String {
+        let (repeat, single, consume) = match self.config.loop_mode {
+            Loop::Single => (1, 1, 0),
+            Loop::Playlist => (1, 0, 0),
+            Loop::Queue => (0, 0, 1),
+        };
+        let state = match self.state {
+            State::Playing => "play",
+            State::Paused => "pause",
+            State::Stopped => "stop",
+        };
+        let mut status = format!(
+            "volume: {}\nrepeat: {}\nrandom: 0\nsingle: {}\nconsume: {}\nplaylist: {}\n\
+             playlistlength: {}\nstate: {}\n",
+            self.player.volume(),
+            repeat,
+            single,
+            consume,
+            self.mpd.playlist_version,
+            self.playlist.len(),
+            state
+        );
+        if let Some(index) = self.mpd_current_index() {
+            writeln!(status, "song: {index}\nsongid: {index}").ok();
+        }
+        if self.current.is_some() {
+            writeln!(
+                status,
+                "time: {}:{}\nelapsed: {:.3}\nduration: {:.3}",
+                self.position.as_secs(),
+                self.duration.as_secs(),
+                self.position.as_secs_f64(),
+                self.duration.as_secs_f64()
+            )
+            .ok();
+        }
+        status
+    }
+
+    /// Where the current song is in the playlist. Only the loop modes keeping it there have it.
+    fn mpd_current_index(&self) -> Option<usize> {
+        let file = self.current.as_ref().and_then(Song::file)?;
+        let index = match self.config.loop_mode {
+            Loop::Single => 0,
+            Loop::Playlist => self.playlist.len().checked_sub(1)?,
+            Loop::Queue => return None,
+        };
+        (self.playlist.get(index).map(String::as_str) == Some(file)).then_some(index)
+    }
+
+    fn mpd_song(&self, song: &Song, index: Option<usize>) -> String {
+        let mut lines = format!("file: {}\n", self.mpd_uri(song.file().unwrap_or_default()));
+        if let Some(title) = song.title() {
+            writeln!(lines, "Title: {title}").ok();
+        }
+        if let Some(artist) = song.artist() {
+            writeln!(lines, "Artist: {artist}").ok();
+        }
+        if let Some(album) = song.album() {
+            writeln!(lines, "Album: {album}").ok();
+        }
+        writeln!(
+            lines,
+            "Time: {}\nduration: {:.3}",
+            song.duration().as_secs(),
+            song.duration().as_secs_f64()
+        )
+        .ok();
+        if let Some(index) = index {
+            writeln!(lines, "Pos: {index}\nId: {index}").ok();
+        }
+        lines
+    }
+
+    fn mpd_playlist(&self, start: usize, end: usize) -> String {
+        let mut lines = String::new();
+        for (index, file) in self.playlist.iter().enumerate().take(end).skip(start) {
+            if let Ok(song) = LibraryDb::song(&self.library_db, file) {
+                lines.push_str(&self.mpd_song(&song, Some(index)));
+                continue;
+            }
+            let uri = self.mpd_uri(file);
+            writeln!(lines, "file: {uri}\nPos: {index}\nId: {index}").ok();
+        }
+        lines
+    }
+
+    fn mpd_lsinfo(&self, uri: &str) -> Reply {
+        let path = self.mpd_path(uri)?;
+        if path.is_file() {
+            return LibraryDb::song(&self.library_db, &path.to_string_lossy())
+                .map(|song| self.mpd_song(&song, None))
+                .map_err(|_| Ack::no_exist("Not found"));
+        }
+        let mut entries: Vec<PathBuf> = std::fs::read_dir(&path)
+            .map_err(|_| Ack::no_exist("Not found"))?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .collect();
+        entries.sort();
+        let mut lines = String::new();
+        for entry in entries {
+            if entry.is_dir() {
+                let uri = self.mpd_uri(&entry.to_string_lossy());
+                writeln!(lines, "directory: {uri}").ok();
+            } else if let Ok(song) = LibraryDb::song(&self.library_db, &entry.to_string_lossy()) {
+                lines.push_str(&self.mpd_song(&song, None));
+            }
+        }
+        Ok(lines)
+    }
+
+    fn music_dir(&self) -> PathBuf {
+        let music_dir = self
+            .config
+            .music_dir_from_cli
+            .as_ref()
+            .unwrap_or(&self.config.music_dir);
+        PathBuf::from(shellexpand::tilde(music_dir).as_ref())
+    }
+
+    /// MPD clients name songs relative to the music directory.
+    fn mpd_uri(&self, file: &str) -> String {
+        Path::new(file).strip_prefix(self.music_dir()).map_or_else(
+            |_| file.to_string(),
+            |uri| uri.to_string_lossy().to_string(),
+        )
+    }
+
+    /// The file or directory `uri` names, which has to be in the music directory: the clients
+    /// may be on other hosts, they don't get to read anything else.
+    fn mpd_path(&self, uri: &str) -> std::result::Result<PathBuf, Ack> {
+        let music_dir = self.music_dir();
+        let uri = uri.trim_start_matches("file://");
+        let not_found = |_| Ack::no_exist("Not found");
+        let root = music_dir.canonicalize().map_err(not_found)?;
+        let path = music_dir.join(uri).canonicalize().map_err(not_found)?;
+        // under the music directory as configured, so `mpd_uri` strips it again
+        path.strip_prefix(&root)
+            .map(|relative| music_dir.join(relative))
+            .map_err(|_| Ack::no_exist("Not found"))
+    }
+
+    fn mpd_index(&self, position: &str) -> std::result::Result<usize, Ack> {
+        match position.parse() {
+            Ok(index) if index < self.playlist.len() => Ok(index),
+            _ => Err(Ack::arg("Bad song index")),
+        }
+    }
+
+    /// A `START:END` range of the playlist, or the one song at `POS`.
+    fn mpd_range(&self, range: &str) -> std::result::Result<(usize, usize), Ack> {
+        let bad = || Ack::arg("Bad song index");
+        let (start, end) = match range.split_once(':') {
+            Some((start, "")) => (start.parse().map_err(|_| bad())?, self.playlist.len()),
+            Some((start, end)) => (
+                start.parse().map_err(|_| bad())?,
+                end.parse().map_err(|_| bad())?,
+            ),
+            None => {
+                let index = self.mpd_index(range)?;
+                (index, index + 1)
+            }
+        };
+        if start >= end || end > self.playlist.len() {
+            return Err(bad());
+        }
+        Ok((start, end))
+    }
+}
+
+/// The songs under `dir` and its subdirectories, in order.
+fn songs_in(db: &Mutex<LibraryDb>, dir: &Path, files: &mut Vec<String>) {
+    if let Ok(entries) = std::fs::read_dir(dir) {
+        let mut entries: Vec<PathBuf> = entries
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .collect();
+        entries.sort();
+        for entry in entries {
+            if entry.is_dir() {
+                songs_in(db, &entry, files);
+            } else {
+                let file = entry.to_string_lossy().to_string();
+                if LibraryDb::song(db, &file).is_ok() {
+                    files.push(file);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{Daemon, Incoming};
+    use super::serve;
+    use crate::config::{Backend, Termusic};
+    use crate::library_db::LibraryDb;
+    use crate::player::GeneralPl;
+    use std::fs;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::{TcpListener, TcpStream};
+    use std::path::Path;
+    use std::sync::mpsc;
+    use std::thread;
+
+    /// A second of silence, 16 bit mono.
+    fn write_wav(path: &Path) {
+        let data_len: u32 = 16000;
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(b"RIFF");
+        bytes.extend_from_slice(&(36 + data_len).to_le_bytes());
+        bytes.extend_from_slice(b"WAVEfmt ");
+        bytes.extend_from_slice(&16_u32.to_le_bytes());
+        bytes.extend_from_slice(&1_u16.to_le_bytes());
+        bytes.extend_from_slice(&1_u16.to_le_bytes());
+        bytes.extend_from_slice(&8000_u32.to_le_bytes());
+        bytes.extend_from_slice(&16000_u32.to_le_bytes());
+        bytes.extend_from_slice(&2_u16.to_le_bytes());
+        bytes.extend_from_slice(&16_u16.to_le_bytes());
+        bytes.extend_from_slice(b"data");
+        bytes.extend_from_slice(&data_len.to_le_bytes());
+        bytes.resize(bytes.len() + data_len as usize, 0);
+        fs::write(path, bytes).unwrap();
+    }
+
+    struct Client {
+        stream: TcpStream,
+        reader: BufReader<TcpStream>,
+    }
+
+    impl Client {
+        fn connect(address: &str) -> Self {
+            let stream = TcpStream::connect(address).unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut greeting = String::new();
+            reader.read_line(&mut greeting).unwrap();
+            assert!(greeting.starts_with("OK MPD "));
+            Self { stream, reader }
+        }
+
+        fn send(&mut self, command: &str) {
+            writeln!(self.stream, "{}", command).unwrap();
+        }
+
+        /// The response lines up to and including the closing `OK` or `ACK` line.
+        fn response(&mut self) -> Vec<String> {
+            let mut lines = Vec::new();
+            loop {
+                let mut line = String::new();
+                self.reader.read_line(&mut line).unwrap();
+                let line = line.trim_end().to_string();
+                let last = line == "OK" || line.starts_with("ACK ");
+                lines.push(line);
+                if last {
+                    return lines;
+                }
+            }
+        }
+
+        fn command(&mut self, command: &str) -> Vec<String> {
+            self.send(command);
+            self.response()
+        }
+    }
+
+    #[test]
+    fn mpd_client_session() {
+        let music_dir = std::env::temp_dir().join(format!("termusic-mpd-{}", std::process::id()));
+        fs::create_dir_all(music_dir.join("album")).unwrap();
+        write_wav(&music_dir.join("album").join("song.wav"));
+
+        let config = Termusic {
+            music_dir: music_dir.to_string_lossy().to_string(),
+            ..Termusic::default()
+        };
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let (tx, rx) = mpsc::channel::<Incoming>();
+        thread::spawn(move || serve(&listener, &tx));
+        thread::spawn(move || {
+            let mut daemon = Daemon::new(
+                &config,
+                GeneralPl::new(Backend::Rusty),
+                LibraryDb::default(),
+                None,
+            );
+            daemon.serve(&rx);
+        });
+
+        let mut client = Client::connect(&address);
+        let mut idler = Client::connect(&address);
+
+        assert_eq!(client.command("lsinfo"), ["directory: album", "OK"]);
+        for outside in ["/etc", "file:///etc/passwd", "..", "album/../.."] {
+            assert_eq!(
+                client.command(&format!("lsinfo \"{outside}\"")),
+                ["ACK [50@0] {lsinfo} Not found"]
+            );
+        }
+        let absolute = music_dir.join("album").join("song.wav");
+        let songs = client.command(&format!("lsinfo \"{}\"", absolute.display()));
+        assert_eq!(songs[0], "file: album/song.wav");
+        let songs = client.command("lsinfo album");
+        assert_eq!(songs[0], "file: album/song.wav");
+        assert!(songs.contains(&"Time: 1".to_string()));
+
+        idler.send("idle playlist");
+        assert_eq!(client.command("add \"album/song.wav\""), ["OK"]);
+        assert_eq!(idler.response(), ["changed: playlist", "OK"]);
+        assert_eq!(client.command("add album"), ["OK"]);
+
+        let playlist = client.command("playlistinfo");
+        assert_eq!(
+            playlist
+                .iter()
+                .filter(|line| line.starts_with("file: "))
+                .count(),
+            2
+        );
+        assert!(playlist.contains(&"Pos: 1".to_string()));
+
+        idler.send("idle mixer");
+        assert_eq!(client.command("setvol 30"), ["OK"]);
+        assert_eq!(idler.response(), ["changed: mixer", "OK"]);
+        let status = client.command("status");
+        assert!(status.contains(&"volume: 30".to_string()));
+        assert!(status.contains(&"playlistlength: 2".to_string()));
+        assert!(status.contains(&"state: stop".to_string()));
+
+        assert_eq!(client.command("delete 0"), ["OK"]);
+        assert_eq!(
+            client.command("delete 3"),
+            ["ACK [2@0] {delete} Bad song index"]
+        );
+        assert_eq!(
+            client.command("add \"album"),
+            ["ACK [2@0] {add} Missing closing '\"'"]
+        );
+        assert_eq!(
+            client.command("bogus"),
+            ["ACK [5@0] {bogus} unknown command \"bogus\""]
+        );
+
+        client.send("command_list_ok_begin");
+        client.send("currentsong");
+        client.send("clear");
+        assert_eq!(
+            client.command("command_list_end"),
+            ["list_OK", "list_OK", "OK"]
+        );
+        assert_eq!(client.command("playlistinfo"), ["OK"]);
+
+        // the clear above happened while it wasn't idling, it's still reported
+        assert_eq!(idler.command("idle playlist"), ["changed: playlist", "OK"]);
+        idler.send("idle player");
+        assert_eq!(idler.command("noidle"), ["OK"]);
+
+        fs::remove_dir_all(&music_dir).ok();
+    }
+}
@@ -0,0 +1,117 @@
+//! What goes over the control socket: one JSON object per line each way.
+//!
+//! Every request is answered with exactly one `ok`, `error` or `status` line. A client that
+//! subscribed also gets the `player` events and `playlist` changes as they happen.
+use crate::config::{EqualizerBand, Resampler};
+use crate::player::PlayerEvent;
+use crate::ui::Loop;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(tag = "request", rename_all = "kebab-case")]
+pub enum Request {
+    /// Play `file` right away, outside of the playlist. Without `gain` its `ReplayGain` is
+    /// looked up as configured.
+    PlayFile {
+        file: String,
+        gain: Option<f32>,
+    },
+    /// Resume when paused, start the playlist when stopped.
+    Play,
+    Pause,
+    Resume,
+    TogglePause,
+    Next,
+    Previous,
+    /// Seek `secs` forward, or backward when negative.
+    Seek {
+        secs: i64,
+    },
+    SeekTo {
+        position: Duration,
+    },
+    SetVolume {
+        volume: i32,
+    },
+    VolumeUp,
+    VolumeDown,
+    /// Scale the volume by `fade`, from 0.0 to 1.0, without changing it.
+    SetFade {
+        fade: f32,
+    },
+    SetCrossfade {
+        crossfade: Duration,
+    },
+    SetEqualizer {
+        bands: Vec<EqualizerBand>,
+    },
+    SetOutputDevice {
+        name: Option<String>,
+    },
+    SetBitPerfect {
+        bit_perfect: bool,
+    },
+    SetResampler {
+        resampler: Resampler,
+    },
+    /// Play `speed` times as fast, keeping the pitch.
+    SetSpeed {
+        speed: f32,
+    },
+    /// Only changes what happens after the current song, the playlist is left as it is.
+    SetLoopMode {
+        mode: Loop,
+    },
+    /// Replace the whole playlist, the way the TUI keeps the daemon up to date.
+    SetPlaylist {
+        files: Vec<String>,
+    },
+    /// Add `file` to the end of the playlist, or the start with `front`.
+    Add {
+        file: String,
+        front: bool,
+    },
+    Remove {
+        index: usize,
+    },
+    Clear,
+    Status,
+    /// Keep sending player events and playlist changes over this connection.
+    Subscribe,
+    /// Stop playing and shut the daemon down.
+    Quit,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(tag = "notification", content = "data", rename_all = "kebab-case")]
+pub enum Notification {
+    Ok,
+    Error(String),
+    Status(Status),
+    Player(PlayerEvent),
+    /// The files of the playlist, after it changed.
+    Playlist(Vec<String>),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum State {
+    Playing,
+    Paused,
+    Stopped,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Status {
+    pub state: State,
+    pub file: Option<String>,
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub position: Duration,
+    /// Zero while the player hasn't reported it.
+    pub duration: Duration,
+    pub volume: i32,
+    pub loop_mode: Loop,
+    pub playlist_len: usize,
+}
@@ -0,0 +1,427 @@
+//! Tags of the songs in the music library, kept in `library.json` so they don't have to be
+//! read from every file again.
+//!
+//! Each file is recorded with its modification time and size, and read again only once one
+//! of them changed.
+use crate::config::get_app_config_path;
+use crate::song::{ReplayGain, Song};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::fs::{self, Metadata};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, UNIX_EPOCH};
+
+/// What the database knows of a file.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct Track {
+    pub file: String,
+    /// Modification time in seconds since the epoch.
+    pub mtime: u64,
+    pub size: u64,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    pub title: Option<String>,
+    pub album_artist: Option<String>,
+    pub genre: Option<String>,
+    pub duration: Duration,
+    /// Number of the song on its disc.
+    pub number: Option<u32>,
+    pub disc: Option<u32>,
+    pub year: Option<i32>,
+    /// From 0 to 5 stars.
+    #[serde(default)]
+    pub rating: Option<u8>,
+    #[serde(default)]
+    pub replaygain: ReplayGain,
+    /// When the song came to the library: the modification time of the file when it was
+    /// first recorded, in seconds since the epoch.
+    #[serde(default)]
+    pub added: u64,
+    /// How many times the song was started.
+    #[serde(default)]
+    pub plays: u32,
+}
+
+impl Track {
+    fn new(song: &Song, file: &str, metadata: &Metadata) -> Self {
+        Self {
+            file: file.to_string(),
+            mtime: mtime(metadata),
+            size: metadata.len(),
+            artist: song.artist().map(ToString::to_string),
+            album: song.album().map(ToString::to_string),
+            title: song.title().map(ToString::to_string),
+            album_artist: song.album_artist().map(ToString::to_string),
+            genre: song.genre().map(ToString::to_string),
+            duration: song.duration(),
+            number: song.track(),
+            disc: song.disc(),
+            year: song.year(),
+            rating: song.rating(),
+            replaygain: *song.replaygain(),
+            added: mtime(metadata),
+            plays: 0,
+        }
+    }
+
+    fn is_current(&self, metadata: &Metadata) -> bool {
+        self.mtime == mtime(metadata) && self.size == metadata.len()
+    }
+}
+
+fn mtime(metadata: &Metadata) -> u64 {
+    metadata
+        .modified()
+        .ok()
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .map_or(0, |since| since.as_secs())
+}
+
+/// Whether `path` looks like a song, by its extension.
+pub fn is_song(path: &Path) -> bool {
+    lofty::FileType::from_path(path).is_some()
+}
+
+#[derive(Default, Deserialize, Serialize)]
+struct Store {
+    #[serde(default)]
+    tracks: Vec<Track>,
+}
+
+#[derive(Default)]
+pub struct LibraryDb {
+    /// By file, so the files of a folder come one after the other.
+    tracks: BTreeMap<String, Track>,
+    changed: bool,
+}
+
+impl LibraryDb {
+    fn path() -> Result<PathBuf> {
+        let mut path = get_app_config_path()?;
+        path.push("library.json");
+        Ok(path)
+    }
+
+    pub fn load() -> Self {
+        let store: Store = Self::path()
+            .ok()
+            .and_then(|path| fs::read_to_string(path).ok())
+            .and_then(|string| serde_json::from_str(&string).ok())
+            .unwrap_or_default();
+        Self {
+            tracks: store
+                .tracks
+                .into_iter()
+                .map(|mut track| {
+                    // recorded before the database kept it
+                    if track.added == 0 {
+                        track.added = track.mtime;
+                    }
+                    (track.file.clone(), track)
+                })
+                .collect(),
+            changed: false,
+        }
+    }
+
+    /// Writes the database when it changed since it was loaded or last saved.
+    pub fn save(&mut self) -> Result<()> {
+        if self.changed {
+            let store = Store {
+                tracks: self.tracks.values().cloned().collect(),
+            };
+            fs::write(Self::path()?, serde_json::to_string(&store)?)?;
+            self.changed = false;
+        }
+        Ok(())
+    }
+
+    /// The song of `file`, from the database while the file hasn't changed, read from it
+    /// and recorded otherwise. Lyrics and pictures aren't kept, `Song::read_from_path`
+    /// has them. Like `scan`, tags are read without holding the lock.
+    pub fn song(db: &Mutex<Self>, file: &str) -> Result<Song> {
+        let metadata = fs::metadata(file)?;
+        if let Some(track) = db.lock().unwrap().get_current(file, &metadata) {
+            return Ok(Song::from(track));
+        }
+        let song = Song::read_from_path(file)?;
+        db.lock()
+            .unwrap()
+            .insert(Track::new(&song, file, &metadata));
+        Ok(song)
+    }
+
+    /// Reads `file` again when it changed since it was recorded, true when it was.
+    pub fn refresh(db: &Mutex<Self>, file: &str) -> bool {
+        if let Ok(metadata) = fs::metadata(file) {
+            let current = db.lock().unwrap().get_current(file, &metadata).is_some();
+            if !current {
+                if let Ok(song) = Song::read_from_path(file) {
+                    db.lock()
+                        .unwrap()
+                        .insert(Track::new(&song, file, &metadata));
+                    return true;
+                }
+            }
+        }
+        false
+    }
+
+    pub fn get(&self, file: &str) -> Option<&Track> {
+        self.tracks.get(file)
+    }
+
+    fn get_current(&self, file: &str, metadata: &Metadata) -> Option<&Track> {
+        self.get(file).filter(|track| track.is_current(metadata))
+    }
+
+    /// The tracks in `dir` and the folders below it, by file.
+    pub fn tracks_in<'a>(&'a self, dir: &'a Path) -> impl Iterator<Item = &'a Track> + 'a {
+        let prefix = dir.to_string_lossy().into_owned();
+        self.tracks
+            .range(prefix.clone()..)
+            .take_while(move |(file, _)| file.starts_with(&prefix))
+            // "/music/a" is a prefix of "/music/ab/song.mp3" too
+            .filter(move |(file, _)| Path::new(file).starts_with(dir))
+            .map(|(_, track)| track)
+    }
+
+    /// The folders below `dir` with songs in them, at any depth.
+    pub fn folders_in<'a>(&'a self, dir: &'a Path) -> BTreeSet<&'a str> {
+        self.tracks_in(dir)
+            .flat_map(|track| {
+                Path::new(&track.file)
+                    .ancestors()
+                    .skip(1)
+                    .take_while(move |folder| *folder != dir)
+            })
+            .filter_map(Path::to_str)
+            .collect()
+    }
+
+    /// Records `track`, keeping when it was added and how often it was played when the file
+    /// was known already.
+    fn insert(&mut self, mut track: Track) {
+        if let Some(known) = self.tracks.get(&track.file) {
+            track.added = known.added;
+            track.plays = known.plays;
+        }
+        self.tracks.insert(track.file.clone(), track);
+        self.changed = true;
+    }
+
+    /// Counts a play of `file`.
+    pub fn played(&mut self, file: &str) {
+        if let Some(track) = self.tracks.get_mut(file) {
+            track.plays += 1;
+            self.changed = true;
+        }
+    }
+
+    pub fn remove(&mut self, file: &str) {
+        if self.tracks.remove(file).is_some() {
+            self.changed = true;
+        }
+    }
+
+    /// Forgets the song `path`, or the songs in the folder `path`. Returns how many.
+    pub fn forget(&mut self, path: &Path) -> usize {
+        let gone: Vec<String> = self
+            .tracks_in(path)
+            .map(|track| track.file.clone())
+            .collect();
+        for file in &gone {
+            self.remove(file);
+        }
+        gone.len()
+    }
+
+    /// Brings the tracks under `root` up to date: reads the songs that are new or changed
+    /// since the last scan and forgets the ones that are gone, then saves the database.
+    /// Tags are read without holding the lock, so the database stays usable meanwhile.
+    /// Returns how many songs were read or forgotten.
+    pub fn scan(db: &Mutex<Self>, root: &Path) -> Result<usize> {
+        let updated = Self::update(db, root);
+        db.lock().unwrap().save().map(|()| updated)
+    }
+
+    /// `scan` without saving.
+    fn update(db: &Mutex<Self>, root: &Path) -> usize {
+        let mut found = HashSet::new();
+        let mut read = 0;
+        for entry in walkdir::WalkDir::new(root)
+            .follow_links(true)
+            .into_iter()
+            .filter_map(std::result::Result::ok)
+            .filter(|entry| entry.file_type().is_file() && is_song(entry.path()))
+        {
+            let file = entry.path().to_string_lossy().into_owned();
+            if let Ok(metadata) = entry.metadata() {
+                let current = db.lock().unwrap().get_current(&file, &metadata).is_some();
+                if !current {
+                    if let Ok(song) = Song::read_from_path(&file) {
+                        db.lock()
+                            .unwrap()
+                            .insert(Track::new(&song, &file, &metadata));
+                        read += 1;
+                    }
+                }
+            }
+            found.insert(file);
+        }
+
+        let mut db = db.lock().unwrap();
+        let gone: Vec<String> = db
+            .tracks_in(root)
+            .filter(|track| !found.contains(&track.file))
+            .map(|track| track.file.clone())
+            .collect();
+        for file in &gone {
+            db.remove(file);
+        }
+        drop(db);
+        read + gone.len()
+    }
+}
+
+#[cfg(test)]
+impl FromIterator<Track> for LibraryDb {
+    fn from_iter<I: IntoIterator<Item = Track>>(tracks: I) -> Self {
+        let mut db = Self::default();
+        for track in tracks {
+            db.insert(track);
+        }
+        db
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::File;
+
+    fn track(file: &str) -> Track {
+        Track {
+            file: file.to_string(),
+            ..Track::default()
+        }
+    }
+
+    /// `secs` of silence, 16 bit mono.
+    fn write_wav(path: &Path, secs: u32) {
+        let data_len = secs * 16000;
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(b"RIFF");
+        bytes.extend_from_slice(&(36 + data_len).to_le_bytes());
+        bytes.extend_from_slice(b"WAVEfmt ");
+        bytes.extend_from_slice(&16_u32.to_le_bytes());
+        bytes.extend_from_slice(&1_u16.to_le_bytes());
+        bytes.extend_from_slice(&1_u16.to_le_bytes());
+        bytes.extend_from_slice(&8000_u32.to_le_bytes());
+        bytes.extend_from_slice(&16000_u32.to_le_bytes());
+        bytes.extend_from_slice(&2_u16.to_le_bytes());
+        bytes.extend_from_slice(&16_u16.to_le_bytes());
+        bytes.extend_from_slice(b"data");
+        bytes.extend_from_slice(&data_len.to_le_bytes());
+        bytes.resize(bytes.len() + data_len as usize, 0);
+        fs::write(path, bytes).unwrap();
+    }
+
+    #[test]
+    fn test_tracks_in() {
+        let db: LibraryDb = [
+            "/music/a/one.mp3",
+            "/music/a/sub/two.mp3",
+            "/music/ab/three.mp3",
+            "/music/a.mp3",
+            "/other/four.mp3",
+        ]
+        .into_iter()
+        .map(track)
+        .collect();
+        let files = |dir: &str| -> Vec<String> {
+            db.tracks_in(Path::new(dir))
+                .map(|track| track.file.clone())
+                .collect()
+        };
+        // a folder named like the start of another doesn't take its songs
+        assert_eq!(
+            files("/music/a"),
+            ["/music/a/one.mp3", "/music/a/sub/two.mp3"]
+        );
+        assert_eq!(files("/music/a/"), files("/music/a"));
+        assert_eq!(files("/music/ab"), ["/music/ab/three.mp3"]);
+        assert_eq!(files("/music").len(), 4);
+        assert!(files("/mus").is_empty());
+        assert_eq!(files("/music/a.mp3"), ["/music/a.mp3"]);
+
+        let folders: Vec<&str> = db.folders_in(Path::new("/music")).into_iter().collect();
+        assert_eq!(folders, ["/music/a", "/music/a/sub", "/music/ab"]);
+    }
+
+    #[test]
+    fn test_rescan() {
+        let root = std::env::temp_dir().join(format!("termusic-library-{}", std::process::id()));
+        fs::create_dir_all(root.join("album")).unwrap();
+        let one = root.join("album").join("one.wav");
+        let two = root.join("two.wav");
+        write_wav(&one, 1);
+        write_wav(&two, 1);
+        let db = Mutex::new(LibraryDb::default());
+
+        assert_eq!(LibraryDb::update(&db, &root), 2);
+        assert_eq!(LibraryDb::update(&db, &root), 0);
+        db.lock().unwrap().played(&two.to_string_lossy());
+        // a fresh database has the songs added when their files were written
+        let added = db
+            .lock()
+            .unwrap()
+            .get(&two.to_string_lossy())
+            .unwrap()
+            .added;
+        assert_eq!(added, mtime(&fs::metadata(&two).unwrap()));
+
+        // a new size
+        write_wav(&two, 2);
+        assert_eq!(LibraryDb::update(&db, &root), 1);
+        let track = db
+            .lock()
+            .unwrap()
+            .get(&two.to_string_lossy())
+            .cloned()
+            .unwrap();
+        assert_eq!(track.duration.as_secs(), 2);
+        assert_eq!(track.plays, 1);
+        assert_eq!(track.added, added);
+
+        // the same size, touched later
+        let mtime = db
+            .lock()
+            .unwrap()
+            .get(&one.to_string_lossy())
+            .unwrap()
+            .mtime;
+        File::options()
+            .write(true)
+            .open(&one)
+            .unwrap()
+            .set_modified(UNIX_EPOCH + Duration::from_secs(mtime + 60))
+            .unwrap();
+        assert_eq!(LibraryDb::update(&db, &root), 1);
+        assert_eq!(LibraryDb::update(&db, &root), 0);
+
+        fs::remove_file(&one).unwrap();
+        assert_eq!(LibraryDb::update(&db, &root), 1);
+        let files: Vec<String> = db
+            .lock()
+            .unwrap()
+            .tracks_in(&root)
+            .map(|track| track.file.clone())
+            .collect();
+        assert_eq!(files, [two.to_string_lossy()]);
+
+        fs::remove_dir_all(&root).ok();
+    }
+}
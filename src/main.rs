@@ -27,13 +27,20 @@
  * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
  * SOFTWARE.
  */
+mod bookmarks;
 mod config;
+mod ctl;
+mod daemon;
 mod invidious;
+mod library_db;
 mod player;
+mod query;
+mod session;
+mod smart_playlists;
 mod song;
 mod songtag;
-// #[cfg(feature = "mpris")]
-// mod souvlaki;
+#[cfg(feature = "mpris")]
+mod souvlaki;
 #[cfg(feature = "cover")]
 mod ueberzug;
 mod ui;
@@ -51,6 +58,14 @@ fn main() {
 
     args.remove(0);
 
+    if args.first().map(String::as_str) == Some("ctl") {
+        if let Err(e) = ctl::run(&args[1..]) {
+            eprintln!("Error: {}", e);
+            process::exit(1);
+        }
+        process::exit(0);
+    }
+
     if args.iter().any(|arg| arg == "-h" || arg == "--help") {
         display_help();
     }
@@ -60,6 +75,29 @@ fn main() {
         process::exit(0);
     }
 
+    if let Some(index) = args.iter().position(|arg| arg == "--backend") {
+        args.remove(index);
+        if index >= args.len() {
+            eprintln!("Error: --backend needs a value, one of rusty, mpv or gst");
+            process::exit(1);
+        }
+        match args.remove(index).parse() {
+            Ok(backend) => config.backend_from_cli = Some(backend),
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                process::exit(1);
+            }
+        }
+    }
+
+    if args.first().map(String::as_str) == Some("daemon") {
+        if let Err(e) = daemon::run(&config) {
+            eprintln!("Error: {}", e);
+            process::exit(1);
+        }
+        process::exit(0);
+    }
+
     if let Some(dir) = args.first() {
         let mut path = Path::new(dir).to_path_buf();
 
@@ -90,13 +128,24 @@ fn display_help() {
 Termusic help:
 
 Usage: termusic [OPTIONS] [MUSIC_DIRECTORY]
+       termusic [OPTIONS] daemon
+       termusic ctl <COMMAND>
 
 With no MUSIC_DIRECTORY, use `~/.config/termusic/config.toml`
 
+Commands:
+    daemon            Play in the background. termusic started afterwards attaches to it,
+                      and quitting it leaves the music playing.
+    ctl <COMMAND>     Control the daemon, or the TUI playing by itself, see below.
+
 Options:
     -h, --help        Print this message and exit.
     -v, --version     Print version and exit.
-  "
+    --backend <NAME>  Play with the rusty, mpv or gst backend, instead of the one in the config.
+
+{}
+  ",
+        ctl::USAGE
     );
 
     process::exit(0);
@@ -21,7 +21,8 @@
  * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
  * SOFTWARE.
  */
-use super::GeneralP;
+use super::{GeneralP, PlayerEvent, MAX_SPEED, MIN_SPEED};
+use crate::config::{EqualizerBand, Resampler};
 use anyhow::{anyhow, bail, Result};
 use gst::ClockTime;
 use gstreamer as gst;
@@ -29,6 +30,8 @@ use gstreamer::prelude::*;
 // use gstreamer_pbutils as gst_pbutils;
 use gstreamer_player as gst_player;
 use std::cmp;
+use std::sync::mpsc::Sender;
+use std::time::Duration;
 // use std::sync::Arc;
 // use std::thread;
 // use std::marker::{Send, Sync};
@@ -37,24 +40,81 @@ pub struct GStreamer {
     player: gst_player::Player,
     paused: bool,
     volume: i32,
+    /// `ReplayGain` factor of the current song, folded into the player volume.
+    gain: f32,
+    /// Scales the volume while fading out, without changing it.
+    fade: f32,
+    events: Sender<PlayerEvent>,
 }
 
 // unsafe impl Send for GSTPlayer {}
 // unsafe impl Sync for GSTPlayer {}
-impl Default for GStreamer {
-    fn default() -> Self {
-        gst::init().expect("Couldn't initialize Gstreamer");
+impl GStreamer {
+    pub fn new(events: Sender<PlayerEvent>) -> Result<Self> {
+        gst::init()?;
         let dispatcher = gst_player::PlayerGMainContextSignalDispatcher::new(None);
         let player = gst_player::Player::new(
             None,
             Some(&dispatcher.upcast::<gst_player::PlayerSignalDispatcher>()),
         );
+        // seek to the exact position rather than the nearest key frame
+        let mut config = player.config();
+        config.set_seek_accurate(true);
+        config.set_position_update_interval(1000);
+        player.set_config(config).ok();
+        // keeps the pitch when the speed changes
+        if let Ok(scaletempo) = gst::ElementFactory::make("scaletempo", None) {
+            player.pipeline().set_property("audio-filter", &scaletempo);
+        }
+
+        // the signals get dispatched to the main context, which `update` runs
+        let tx = events.clone();
+        player.connect_end_of_stream(move |_| {
+            tx.send(PlayerEvent::TrackEnded).ok();
+        });
+        let tx = events.clone();
+        player.connect_position_updated(move |_, position| {
+            if let Some(position) = position {
+                tx.send(PlayerEvent::PositionChanged(Duration::from_nanos(
+                    position.nseconds(),
+                )))
+                .ok();
+            }
+        });
+        let tx = events.clone();
+        player.connect_duration_changed(move |_, duration| {
+            if let Some(duration) = duration {
+                tx.send(PlayerEvent::DurationKnown(Duration::from_nanos(
+                    duration.nseconds(),
+                )))
+                .ok();
+            }
+        });
+        let tx = events.clone();
+        player.connect_error(move |_, e| {
+            tx.send(PlayerEvent::Error(format!("Error in gstreamer: {}", e)))
+                .ok();
+        });
 
-        Self {
+        Ok(Self {
             player,
             paused: false,
             volume: 50,
-        }
+            gain: 1.0,
+            fade: 1.0,
+            events,
+        })
+    }
+
+    fn apply_volume(&self) {
+        self.player
+            .set_volume(f64::from(self.volume) / 100.0 * f64::from(self.gain * self.fade));
+    }
+
+    fn send_volume(&self) {
+        self.events
+            .send(PlayerEvent::VolumeChanged(self.volume))
+            .ok();
     }
 }
 
@@ -73,20 +133,32 @@ impl GeneralP for GStreamer {
     //     duration
     // }
 
-    fn add_and_play(&mut self, song_str: &str) {
+    fn add_and_play(&mut self, song_str: &str, gain: f32) {
+        self.gain = gain;
+        self.apply_volume();
         self.player.set_uri(Some(&format!("file:///{}", song_str)));
         self.paused = false;
         self.player.play();
+        self.events
+            .send(PlayerEvent::TrackStarted(song_str.to_string()))
+            .ok();
     }
 
     fn volume_up(&mut self) {
         self.volume = cmp::min(self.volume + 5, 100);
-        self.player.set_volume(f64::from(self.volume) / 100.0);
+        self.apply_volume();
+        self.send_volume();
+    }
+
+    fn set_fade(&mut self, fade: f32) {
+        self.fade = fade.clamp(0.0, 1.0);
+        self.apply_volume();
     }
 
     fn volume_down(&mut self) {
         self.volume = cmp::max(self.volume - 5, 0);
-        self.player.set_volume(f64::from(self.volume) / 100.0);
+        self.apply_volume();
+        self.send_volume();
     }
 
     fn volume(&self) -> i32 {
@@ -100,7 +172,8 @@ impl GeneralP for GStreamer {
             volume = 0;
         }
         self.volume = volume;
-        self.player.set_volume(f64::from(volume) / 100.0);
+        self.apply_volume();
+        self.send_volume();
     }
 
     fn pause(&mut self) {
@@ -119,17 +192,22 @@ impl GeneralP for GStreamer {
 
     #[allow(clippy::cast_sign_loss)]
     fn seek(&mut self, secs: i64) -> Result<()> {
-        if let Ok((_, time_pos, duration)) = self.get_progress() {
-            let mut seek_pos = time_pos + secs;
-            if seek_pos < 0 {
-                seek_pos = 0;
-            }
+        if let Ok((_, time_pos, _)) = self.get_progress() {
+            let seek_pos = cmp::max(time_pos + secs, 0);
+            self.seek_to(Duration::from_secs(seek_pos as u64))?;
+        }
+        Ok(())
+    }
 
-            if seek_pos.cmp(&duration) == std::cmp::Ordering::Greater {
+    #[allow(clippy::cast_possible_truncation)]
+    fn seek_to(&mut self, position: Duration) -> Result<()> {
+        if let Some(duration) = self.player.duration() {
+            if position.as_nanos() > u128::from(duration.nseconds()) {
                 bail! {"exceed max length"};
             }
-            self.player.seek(ClockTime::from_seconds(seek_pos as u64));
         }
+        self.player
+            .seek(ClockTime::from_nseconds(position.as_nanos() as u64));
         Ok(())
     }
 
@@ -151,4 +229,38 @@ impl GeneralP for GStreamer {
         }
         Ok((percent as f64, time_pos, duration))
     }
+
+    fn enqueue_next(&mut self, _new: &str, _gain: f32) -> Result<()> {
+        bail!("gapless playback is not supported by gstreamer backend")
+    }
+
+    fn update(&mut self) {
+        let context = gst::glib::MainContext::default();
+        while context.iteration(false) {}
+    }
+
+    fn set_speed(&mut self, speed: f32) {
+        self.player
+            .set_rate(f64::from(speed.clamp(MIN_SPEED, MAX_SPEED)));
+    }
+
+    fn set_crossfade(&mut self, _crossfade: Duration) {
+        // not supported, songs change gaplessly at best
+    }
+
+    fn set_output_device(&mut self, _name: Option<&str>) {
+        // not supported
+    }
+
+    fn set_bit_perfect(&mut self, _bit_perfect: bool) {
+        // not supported
+    }
+
+    fn set_resampler(&mut self, _resampler: Resampler) {
+        // not supported
+    }
+
+    fn set_equalizer(&mut self, _bands: &[EqualizerBand]) {
+        // not supported
+    }
 }
@@ -0,0 +1,345 @@
+//! Loudness measurement after EBU R128 / ITU-R BS.1770, used to compute `ReplayGain` 2.0 values.
+use super::rusty_backend::{Decoder, Source};
+use crate::song::ReplayGain;
+use anyhow::{bail, Result};
+use std::f64::consts::PI;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+/// `ReplayGain` 2.0 reference level, in LUFS.
+const REFERENCE_LUFS: f64 = -18.0;
+const ABSOLUTE_GATE_LUFS: f64 = -70.0;
+const RELATIVE_GATE_LU: f64 = -10.0;
+/// Gating blocks are 400ms long and overlap by 75%, so a new one starts every 100ms.
+const BLOCK_STEPS: usize = 4;
+/// Taps per phase of the true peak interpolation filter.
+const PEAK_TAPS: usize = 12;
+
+/// Measured loudness of one song, or of several songs merged into an album.
+#[derive(Default)]
+pub struct Loudness {
+    /// Mean square energy of the blocks passing the absolute gate.
+    blocks: Vec<f64>,
+    /// True peak as linear sample amplitude.
+    pub peak: f64,
+}
+
+impl Loudness {
+    /// Decodes the whole file at `path` and measures it.
+    pub fn measure(path: &Path) -> Result<Self> {
+        let file = File::open(path)?;
+        let decoder = Decoder::new_decoder(BufReader::new(file))?;
+        let channels = usize::from(decoder.channels());
+        let sample_rate = decoder.sample_rate();
+        if channels == 0 || sample_rate == 0 {
+            bail!("no audio in {}", path.display());
+        }
+
+        let mut meter = Meter::new(channels, sample_rate);
+        for (index, sample) in decoder.enumerate() {
+            meter.push(index % channels, f64::from(sample));
+        }
+        Ok(meter.finish())
+    }
+
+    /// Album loudness gates all blocks of the album together, rather than averaging the songs.
+    pub fn merge<'a, I: IntoIterator<Item = &'a Self>>(songs: I) -> Self {
+        let mut album = Self::default();
+        for song in songs {
+            album.blocks.extend_from_slice(&song.blocks);
+            album.peak = album.peak.max(song.peak);
+        }
+        album
+    }
+
+    /// Integrated loudness in LUFS, `None` for silence.
+    pub fn integrated(&self) -> Option<f64> {
+        let absolute = mean(self.blocks.iter().copied())?;
+        let threshold = energy(loudness(absolute) + RELATIVE_GATE_LU);
+        mean(self.blocks.iter().copied().filter(|e| *e > threshold)).map(loudness)
+    }
+
+    /// Gain in dB bringing the song to the reference level.
+    pub fn gain(&self) -> Option<f64> {
+        self.integrated().map(|lufs| REFERENCE_LUFS - lufs)
+    }
+}
+
+/// `ReplayGain` tags for `track`, with album values from `album` if given.
+#[allow(clippy::cast_possible_truncation)]
+pub fn replaygain(track: &Loudness, album: Option<&Loudness>) -> ReplayGain {
+    ReplayGain {
+        track_gain: track.gain().map(|g| g as f32),
+        track_peak: Some(track.peak as f32),
+        album_gain: album.and_then(Loudness::gain).map(|g| g as f32),
+        album_peak: album.map(|a| a.peak as f32),
+    }
+}
+
+fn mean<I: Iterator<Item = f64>>(energies: I) -> Option<f64> {
+    let (sum, count) = energies.fold((0.0, 0_u32), |(sum, count), e| (sum + e, count + 1));
+    if count == 0 {
+        None
+    } else {
+        Some(sum / f64::from(count))
+    }
+}
+
+#[allow(clippy::suboptimal_flops)]
+fn loudness(energy: f64) -> f64 {
+    -0.691 + 10.0 * energy.log10()
+}
+
+fn energy(loudness: f64) -> f64 {
+    10_f64.powf((loudness + 0.691) / 10.0)
+}
+
+#[derive(Clone, Copy)]
+struct Biquad {
+    b: [f64; 3],
+    a: [f64; 2],
+    z: [f64; 2],
+}
+
+impl Biquad {
+    const fn new(b: [f64; 3], a: [f64; 2]) -> Self {
+        Self { b, a, z: [0.0; 2] }
+    }
+
+    // transposed direct form II
+    #[allow(clippy::suboptimal_flops)]
+    fn process(&mut self, x: f64) -> f64 {
+        let y = self.b[0] * x + self.z[0];
+        self.z[0] = self.b[1] * x - self.a[0] * y + self.z[1];
+        self.z[1] = self.b[2] * x - self.a[1] * y;
+        y
+    }
+}
+
+// The K-weighting pre-filter (high shelf) and RLB filter (high pass) of BS.1770, recomputed for
+// the sample rate of the song.
+fn k_weighting(sample_rate: u32) -> [Biquad; 2] {
+    let rate = f64::from(sample_rate);
+
+    let f0 = 1_681.974_450_955_533;
+    let gain = 3.999_843_853_973_347;
+    let q = 0.707_175_236_955_419_6;
+    let k = (PI * f0 / rate).tan();
+    let vh = 10_f64.powf(gain / 20.0);
+    let vb = vh.powf(0.499_666_774_154_541_6);
+    let a0 = 1.0 + k / q + k * k;
+    let shelf = Biquad::new(
+        [
+            (vh + vb * k / q + k * k) / a0,
+            2.0 * (k * k - vh) / a0,
+            (vh - vb * k / q + k * k) / a0,
+        ],
+        [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+    );
+
+    let f0 = 38.135_470_876_024_44;
+    let q = 0.500_327_037_323_877_3;
+    let k = (PI * f0 / rate).tan();
+    let a0 = 1.0 + k / q + k * k;
+    let high_pass = Biquad::new(
+        [1.0, -2.0, 1.0],
+        [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+    );
+
+    [shelf, high_pass]
+}
+
+// BS.1770 channel weights for the usual L R C LFE Ls Rs order, the LFE doesn't count.
+const fn channel_weight(channel: usize, channels: usize) -> f64 {
+    match (channels, channel) {
+        (6, 3) => 0.0,
+        (6, 4 | 5) => 1.41,
+        _ => 1.0,
+    }
+}
+
+// Windowed sinc low pass for oversampling by `factor`, laid out for polyphase use.
+#[allow(clippy::cast_precision_loss, clippy::suboptimal_flops)]
+fn interpolation_filter(factor: usize) -> Vec<f64> {
+    let len = PEAK_TAPS * factor;
+    let center = (len - 1) as f64 / 2.0;
+    (0..len)
+        .map(|n| {
+            let t = (n as f64 - center) / factor as f64;
+            let sinc = if t == 0.0 {
+                1.0
+            } else {
+                (PI * t).sin() / (PI * t)
+            };
+            let window = 0.5 - 0.5 * (2.0 * PI * n as f64 / (len - 1) as f64).cos();
+            sinc * window
+        })
+        .collect()
+}
+
+struct Meter {
+    channels: usize,
+    filters: Vec<[Biquad; 2]>,
+    weights: Vec<f64>,
+    step_len: usize,
+    /// Samples of the current step, per channel.
+    step_pos: usize,
+    /// Sum of squares of the current step, per channel.
+    step_sums: Vec<f64>,
+    /// Weighted sums of the last steps, the newest last.
+    steps: Vec<f64>,
+    blocks: Vec<f64>,
+    oversampling: usize,
+    peak_filter: Vec<f64>,
+    /// Last input samples per channel for the interpolation, the newest first.
+    history: Vec<Vec<f64>>,
+    peak: f64,
+}
+
+impl Meter {
+    fn new(channels: usize, sample_rate: u32) -> Self {
+        // true peak needs at least 192kHz
+        let oversampling = match sample_rate {
+            0..=95_999 => 4,
+            96_000..=191_999 => 2,
+            _ => 1,
+        };
+        Self {
+            channels,
+            filters: vec![k_weighting(sample_rate); channels],
+            weights: (0..channels).map(|c| channel_weight(c, channels)).collect(),
+            step_len: (sample_rate as usize / 10).max(1),
+            step_pos: 0,
+            step_sums: vec![0.0; channels],
+            steps: Vec::with_capacity(BLOCK_STEPS),
+            blocks: Vec::new(),
+            oversampling,
+            peak_filter: interpolation_filter(oversampling),
+            history: vec![vec![0.0; PEAK_TAPS]; channels],
+            peak: 0.0,
+        }
+    }
+
+    fn push(&mut self, channel: usize, sample: f64) {
+        self.push_peak(channel, sample);
+
+        let [pre_filter, rlb_filter] = &mut self.filters[channel];
+        let filtered = rlb_filter.process(pre_filter.process(sample));
+        self.step_sums[channel] += filtered * filtered;
+
+        if channel + 1 == self.channels {
+            self.step_pos += 1;
+            if self.step_pos == self.step_len {
+                self.end_step();
+            }
+        }
+    }
+
+    fn push_peak(&mut self, channel: usize, sample: f64) {
+        self.peak = self.peak.max(sample.abs());
+        if self.oversampling == 1 {
+            return;
+        }
+        let history = &mut self.history[channel];
+        history.rotate_right(1);
+        history[0] = sample;
+        for phase in 0..self.oversampling {
+            let value: f64 = history
+                .iter()
+                .enumerate()
+                .map(|(k, x)| x * self.peak_filter[k * self.oversampling + phase])
+                .sum();
+            self.peak = self.peak.max(value.abs());
+        }
+    }
+
+    #[allow(clippy::cast_precision_loss)]
+    fn end_step(&mut self) {
+        let sum = self
+            .step_sums
+            .iter()
+            .zip(&self.weights)
+            .map(|(s, w)| s * w)
+            .sum();
+        self.step_sums.iter_mut().for_each(|s| *s = 0.0);
+        self.step_pos = 0;
+
+        if self.steps.len() == BLOCK_STEPS {
+            self.steps.remove(0);
+        }
+        self.steps.push(sum);
+        if self.steps.len() == BLOCK_STEPS {
+            let block_len = (self.step_len * BLOCK_STEPS) as f64;
+            let block = self.steps.iter().sum::<f64>() / block_len;
+            if block > energy(ABSOLUTE_GATE_LUFS) {
+                self.blocks.push(block);
+            }
+        }
+    }
+
+    fn finish(self) -> Loudness {
+        Loudness {
+            blocks: self.blocks,
+            peak: self.peak,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{replaygain, Loudness, Meter};
+    use std::f64::consts::PI;
+
+    // A sine of 997Hz, the BS.1770 test tone, at `amplitude` on every channel.
+    #[allow(clippy::cast_precision_loss)]
+    fn tone(channels: usize, sample_rate: u32, amplitude: f64, secs: u32) -> Loudness {
+        let mut meter = Meter::new(channels, sample_rate);
+        for n in 0..sample_rate * secs {
+            let sample =
+                amplitude * (2.0 * PI * 997.0 * f64::from(n) / f64::from(sample_rate)).sin();
+            for channel in 0..channels {
+                meter.push(channel, sample);
+            }
+        }
+        meter.finish()
+    }
+
+    #[test]
+    fn test_tone_loudness() {
+        // 0 dBFS on one channel reads -3.01 LUFS
+        let full = tone(1, 48_000, 1.0, 3).integrated().unwrap();
+        assert!((full + 3.01).abs() < 0.1, "{full}");
+        // -20 dBFS on both channels reads -20 LUFS, 2 dB below the reference
+        let stereo = tone(2, 44_100, 0.1, 3);
+        let lufs = stereo.integrated().unwrap();
+        assert!((lufs + 20.0).abs() < 0.1, "{lufs}");
+        assert!((stereo.gain().unwrap() - 2.0).abs() < 0.1);
+        assert!((stereo.peak - 0.1).abs() < 0.005, "{}", stereo.peak);
+    }
+
+    #[test]
+    fn test_silence() {
+        let silence = tone(2, 48_000, 0.0, 2);
+        assert_eq!(silence.integrated(), None);
+        assert_eq!(silence.gain(), None);
+        let tags = replaygain(&silence, None);
+        assert_eq!(tags.track_gain, None);
+        assert_eq!(tags.album_gain, None);
+    }
+
+    #[test]
+    fn test_album() {
+        let loud = tone(2, 48_000, 0.5, 2);
+        let quiet = tone(2, 48_000, 0.05, 2);
+        let album = Loudness::merge([&loud, &quiet]);
+        assert!((album.peak - loud.peak).abs() < f64::EPSILON);
+        // the quiet song is gated away relative to the loud one
+        let album_lufs = album.integrated().unwrap();
+        assert!((album_lufs - loud.integrated().unwrap()).abs() < 0.1);
+
+        let tags = replaygain(&quiet, Some(&album));
+        assert!(tags.track_gain.unwrap() > tags.album_gain.unwrap());
+        assert!(tags.album_peak.is_some());
+    }
+}
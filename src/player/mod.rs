@@ -1,50 +1,124 @@
 // mod internal_backend;
 // mod crossbeam;
 // mod symphonia_backend;
-#[cfg(all(feature = "gst", not(feature = "mpv")))]
+#[cfg(feature = "gst")]
 mod gstreamer_backend;
-#[cfg(not(any(feature = "mpv", feature = "gst")))]
-mod rusty_backend;
-// // #[cfg(all(feature = "gst", not(feature = "mpv")))]
+// the loudness scanner decodes with it, whatever backend plays
+pub mod loudness;
 #[cfg(feature = "mpv")]
 mod mpv_backend;
+mod rusty_backend;
 // #[cfg(not(any(feature = "mpv", feature = "gst")))]
 // mod rodio_backend;
-use anyhow::Result;
-#[cfg(feature = "mpv")]
-use mpv_backend::Mpv;
+use crate::config::{Backend, EqualizerBand, Resampler, Termusic};
+use crate::daemon;
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::time::Duration;
 // #[cfg(not(any(feature = "mpv", feature = "gst")))]
 // use rodio_backend::RodioPlayer;
 // use symphonia_backend::Symphonia;
 
+/// Range of the playback speed.
+pub const MIN_SPEED: f32 = 0.5;
+pub const MAX_SPEED: f32 = 3.0;
+
+/// What a backend reports while it plays.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PlayerEvent {
+    /// Playback of the file moved on to this one, by `add_and_play` or from the enqueued one.
+    TrackStarted(String),
+    /// The song played to its end and nothing is enqueued after it.
+    TrackEnded,
+    PositionChanged(Duration),
+    DurationKnown(Duration),
+    Error(String),
+    VolumeChanged(i32),
+}
+
 pub struct GeneralPl {
-    #[cfg(all(feature = "gst", not(feature = "mpv")))]
-    player: gstreamer_backend::GStreamer,
-    #[cfg(feature = "mpv")]
-    player: Mpv,
-    // player: RodioPlayer,
-    // player: Symphonia,
-    // player: crossbeam::Player,
-    // player: symphonia_backend::Symphonia,
-    #[cfg(not(any(feature = "mpv", feature = "gst")))]
-    player: rusty_backend::Player,
+    player: Box<dyn GeneralP>,
+    events: Receiver<PlayerEvent>,
+    /// Why the requested backend couldn't be started, if it wasn't.
+    fallback: Option<String>,
 }
 
-impl Default for GeneralPl {
-    fn default() -> Self {
-        #[cfg(all(feature = "gst", not(feature = "mpv")))]
-        let player = gstreamer_backend::GStreamer::default();
-        #[cfg(feature = "mpv")]
-        let player = Mpv::default();
-        #[cfg(not(any(feature = "mpv", feature = "gst")))]
-        let player = rusty_backend::Player::default();
-        Self { player }
+impl GeneralPl {
+    /// Starts `backend`, or the rusty backend when that fails.
+    pub fn new(backend: Backend) -> Self {
+        let (tx, events) = mpsc::channel();
+        match start(backend, tx.clone()) {
+            Ok(player) => Self {
+                player,
+                events,
+                fallback: None,
+            },
+            Err(e) => Self {
+                player: Box::new(rusty_backend::Player::new(tx)),
+                events,
+                fallback: Some(format!(
+                    "Couldn't start the {} backend: {}. Playing with the rusty backend instead.",
+                    backend, e
+                )),
+            },
+        }
+    }
+
+    /// Plays through the running daemon, if there is one.
+    pub fn attach() -> Result<(Self, daemon::Link)> {
+        let (tx, events) = mpsc::channel();
+        let (remote, link) = daemon::attach(tx)?;
+        Ok((
+            Self {
+                player: Box::new(remote),
+                events,
+                fallback: None,
+            },
+            link,
+        ))
+    }
+
+    /// Sets up the player the way `config` says.
+    pub fn apply_config(&mut self, config: &Termusic) {
+        self.set_volume(config.volume);
+        self.set_crossfade(Duration::from_secs(config.crossfade_secs));
+        self.set_equalizer(&config.equalizer_bands());
+        self.set_bit_perfect(config.bit_perfect);
+        self.set_resampler(config.resampler);
+        if config.output_device.is_some() {
+            self.set_output_device(config.output_device.as_deref());
+        }
+    }
+
+    /// The message explaining the fallback, once.
+    pub const fn take_fallback(&mut self) -> Option<String> {
+        self.fallback.take()
+    }
+
+    /// Events since the last call, oldest first.
+    pub fn events(&mut self) -> Vec<PlayerEvent> {
+        self.player.update();
+        self.events.try_iter().collect()
     }
 }
 
+fn start(backend: Backend, events: Sender<PlayerEvent>) -> Result<Box<dyn GeneralP>> {
+    Ok(match backend {
+        Backend::Rusty => Box::new(rusty_backend::Player::new(events)),
+        #[cfg(feature = "mpv")]
+        Backend::Mpv => Box::new(mpv_backend::Mpv::new(events)?),
+        #[cfg(feature = "gst")]
+        Backend::Gst => Box::new(gstreamer_backend::GStreamer::new(events)?),
+        #[allow(unreachable_patterns)]
+        _ => bail!("termusic was built without the {} backend", backend),
+    })
+}
+
 impl GeneralP for GeneralPl {
-    fn add_and_play(&mut self, new: &str) {
-        self.player.add_and_play(new);
+    fn add_and_play(&mut self, new: &str, gain: f32) {
+        self.player.add_and_play(new, gain);
     }
     fn volume(&self) -> i32 {
         self.player.volume()
@@ -58,6 +132,9 @@ impl GeneralP for GeneralPl {
     fn set_volume(&mut self, volume: i32) {
         self.player.set_volume(volume);
     }
+    fn set_fade(&mut self, fade: f32) {
+        self.player.set_fade(fade);
+    }
     fn pause(&mut self) {
         self.player.pause();
     }
@@ -70,20 +147,75 @@ impl GeneralP for GeneralPl {
     fn seek(&mut self, secs: i64) -> Result<()> {
         self.player.seek(secs)
     }
+    fn seek_to(&mut self, position: Duration) -> Result<()> {
+        self.player.seek_to(position)
+    }
     fn get_progress(&mut self) -> Result<(f64, i64, i64)> {
         self.player.get_progress()
     }
+    fn enqueue_next(&mut self, new: &str, gain: f32) -> Result<()> {
+        self.player.enqueue_next(new, gain)
+    }
+    fn update(&mut self) {
+        self.player.update();
+    }
+    fn set_crossfade(&mut self, crossfade: Duration) {
+        self.player.set_crossfade(crossfade);
+    }
+    fn set_equalizer(&mut self, bands: &[EqualizerBand]) {
+        self.player.set_equalizer(bands);
+    }
+    fn set_output_device(&mut self, name: Option<&str>) {
+        self.player.set_output_device(name);
+    }
+    fn set_bit_perfect(&mut self, bit_perfect: bool) {
+        self.player.set_bit_perfect(bit_perfect);
+    }
+    fn set_resampler(&mut self, resampler: Resampler) {
+        self.player.set_resampler(resampler);
+    }
+    fn set_speed(&mut self, speed: f32) {
+        self.player.set_speed(speed);
+    }
+}
+
+/// Names of the audio output devices to choose from.
+pub fn output_devices() -> Vec<String> {
+    rusty_backend::output_device_names()
 }
 
 pub trait GeneralP {
-    fn add_and_play(&mut self, new: &str);
+    /// Play `new` right away. `gain` is its linear `ReplayGain` factor, 1.0 leaves it as is.
+    fn add_and_play(&mut self, new: &str, gain: f32);
     fn volume(&self) -> i32;
     fn volume_up(&mut self);
     fn volume_down(&mut self);
     fn set_volume(&mut self, volume: i32);
+    /// Scale the volume by `fade`, from 0.0 to 1.0, for fading out without changing the volume.
+    fn set_fade(&mut self, fade: f32);
     fn pause(&mut self);
     fn resume(&mut self);
     fn is_paused(&mut self) -> bool;
+    /// Seek `secs` forward, or backward when negative.
     fn seek(&mut self, secs: i64) -> Result<()>;
+    /// Seek to `position` from the start of the song, exactly rather than to the nearest key frame.
+    fn seek_to(&mut self, position: Duration) -> Result<()>;
     fn get_progress(&mut self) -> Result<(f64, i64, i64)>;
+    /// Prepare `new` to start right after the current song, without a gap.
+    fn enqueue_next(&mut self, new: &str, gain: f32) -> Result<()>;
+    /// Work that has to happen on the thread owning the player, like collecting its events.
+    /// Called on every round of the UI loop.
+    fn update(&mut self);
+    /// Overlap between the end of a song and the start of the enqueued one.
+    fn set_crossfade(&mut self, crossfade: Duration);
+    fn set_equalizer(&mut self, bands: &[EqualizerBand]);
+    /// Play through the output device called `name`, the system default for `None`.
+    fn set_output_device(&mut self, name: Option<&str>);
+    /// Play each song in its own sample rate and channel count where the device supports it,
+    /// instead of converting everything to one format.
+    fn set_bit_perfect(&mut self, bit_perfect: bool);
+    fn set_resampler(&mut self, resampler: Resampler);
+    /// Play `speed` times as fast, within `MIN_SPEED..=MAX_SPEED`, keeping the pitch. Positions
+    /// stay in the time of the song.
+    fn set_speed(&mut self, speed: f32);
 }
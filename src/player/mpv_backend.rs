@@ -21,34 +21,138 @@
  * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
  * SOFTWARE.
  */
-use super::GeneralP;
+use super::{GeneralP, PlayerEvent, MAX_SPEED, MIN_SPEED};
+use crate::config::{EqualizerBand, EqualizerBandKind, Resampler};
 // use mpv::{MpvHandler, MpvHandlerBuilder};
 use anyhow::{anyhow, Result};
-use libmpv::Mpv as MpvBackend;
+use libmpv::events::{Event, PropertyData};
+use libmpv::{mpv_end_file_reason, Format, Mpv as MpvBackend};
 use std::cmp;
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
 
 pub struct Mpv {
-    player: MpvBackend,
+    player: Arc<MpvBackend>,
     volume: i32,
+    /// Scales the volume while fading out, without changing it.
+    fade: f32,
+    /// File and `ReplayGain` factor of the enqueued file, the gain gets applied once it starts.
+    next: Arc<Mutex<Option<(String, f32)>>>,
+    events: Sender<PlayerEvent>,
 }
 
-impl Default for Mpv {
-    fn default() -> Self {
-        let mpv = MpvBackend::new().expect("Couldn't initialize MpvHandlerBuilder");
+impl Mpv {
+    pub fn new(events: Sender<PlayerEvent>) -> Result<Self> {
+        let mpv = MpvBackend::new().map_err(|e| anyhow!("couldn't initialize libmpv: {:?}", e))?;
         mpv.set_property("vo", "null")
-            .expect("Couldn't set vo=null in libmpv");
-        Self {
-            player: mpv,
+            .map_err(|e| anyhow!("couldn't set vo=null in libmpv: {:?}", e))?;
+        let player = Arc::new(mpv);
+        let next = Arc::new(Mutex::new(None));
+
+        let watched = Arc::clone(&player);
+        let watched_next = Arc::clone(&next);
+        let watch_events = events.clone();
+        thread::spawn(move || watch(&watched, &watched_next, &watch_events));
+
+        Ok(Self {
+            player,
             volume: 50,
+            fade: 1.0,
+            next,
+            events,
+        })
+    }
+
+    fn apply_volume(&self) {
+        self.player
+            .set_property("volume", f64::from(self.volume) * f64::from(self.fade))
+            .ok();
+    }
+
+    fn send_volume(&self) {
+        self.events
+            .send(PlayerEvent::VolumeChanged(self.volume))
+            .ok();
+    }
+}
+
+// mpv takes the gain in dB, within -150..12
+fn set_gain(mpv: &MpvBackend, gain: f32) {
+    let db = (20.0 * f64::from(gain).log10()).clamp(-150.0, 12.0);
+    mpv.set_property("volume-gain", db).ok();
+}
+
+// Turns the events of mpv into player events, until it shuts down.
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+fn watch(mpv: &MpvBackend, next: &Mutex<Option<(String, f32)>>, events: &Sender<PlayerEvent>) {
+    let mut context = mpv.create_event_context();
+    context.disable_deprecated_events().ok();
+    context.observe_property("time-pos", Format::Double, 0).ok();
+    context.observe_property("duration", Format::Double, 1).ok();
+    // whole seconds of the position last reported
+    let mut position = None;
+    loop {
+        let event = match context.wait_event(600.0) {
+            Some(Ok(Event::Shutdown)) => break,
+            Some(Ok(Event::FileLoaded)) => {
+                position = None;
+                let path: String = mpv.get_property("path").unwrap_or_default();
+                let mut next = next.lock().unwrap();
+                if next.as_ref().map(|(file, _)| file) == Some(&path) {
+                    // drop the finished entry so the playlist holds the current file only
+                    mpv.command("playlist-remove", &["0"]).ok();
+                    if let Some((_, gain)) = next.take() {
+                        set_gain(mpv, gain);
+                    }
+                }
+                PlayerEvent::TrackStarted(path)
+            }
+            Some(Ok(Event::EndFile(mpv_end_file_reason::Eof))) => {
+                if next.lock().unwrap().is_some() {
+                    continue;
+                }
+                PlayerEvent::TrackEnded
+            }
+            Some(Ok(Event::PropertyChange {
+                name: "time-pos",
+                change: PropertyData::Double(secs),
+                ..
+            })) => {
+                let secs = secs.max(0.0);
+                if position == Some(secs as u64) {
+                    continue;
+                }
+                position = Some(secs as u64);
+                PlayerEvent::PositionChanged(Duration::from_secs_f64(secs))
+            }
+            Some(Ok(Event::PropertyChange {
+                name: "duration",
+                change: PropertyData::Double(secs),
+                ..
+            })) => PlayerEvent::DurationKnown(Duration::from_secs_f64(secs.max(0.0))),
+            Some(Err(e)) => PlayerEvent::Error(format!("Error in mpv: {}", e)),
+            _ => continue,
+        };
+        if events.send(event).is_err() {
+            break;
         }
     }
 }
 
 impl GeneralP for Mpv {
-    fn add_and_play(&mut self, new: &str) {
-        self.player
+    fn add_and_play(&mut self, new: &str, gain: f32) {
+        *self.next.lock().unwrap() = None;
+        set_gain(&self.player, gain);
+        if let Err(e) = self
+            .player
             .command("loadfile", &[&format!("\"{}\"", new), "replace"])
-            .expect("Error loading file");
+        {
+            self.events
+                .send(PlayerEvent::Error(format!("Error loading file: {}", e)))
+                .ok();
+        }
     }
 
     fn volume(&self) -> i32 {
@@ -57,16 +161,14 @@ impl GeneralP for Mpv {
 
     fn volume_up(&mut self) {
         self.volume = cmp::min(self.volume + 5, 100);
-        self.player
-            .set_property("volume", i64::from(self.volume))
-            .expect("Error increase volume");
+        self.apply_volume();
+        self.send_volume();
     }
 
     fn volume_down(&mut self) {
         self.volume = cmp::max(self.volume - 5, 0);
-        self.player
-            .set_property("volume", i64::from(self.volume))
-            .expect("Error decrease volume");
+        self.apply_volume();
+        self.send_volume();
     }
     fn set_volume(&mut self, mut volume: i32) {
         if volume > 100 {
@@ -75,10 +177,13 @@ impl GeneralP for Mpv {
             volume = 0;
         }
         self.volume = volume;
-        self.player
-            // .set_property("volume", 50_i64)
-            .set_property("volume", i64::from(self.volume))
-            .expect("Error setting volume");
+        self.apply_volume();
+        self.send_volume();
+    }
+
+    fn set_fade(&mut self, fade: f32) {
+        self.fade = fade.clamp(0.0, 1.0);
+        self.apply_volume();
     }
 
     fn pause(&mut self) {
@@ -102,13 +207,23 @@ impl GeneralP for Mpv {
     fn seek(&mut self, secs: i64) -> Result<()> {
         match self
             .player
-            .command("seek", &[&format!("\"{}\"", secs), "relative"])
+            .command("seek", &[&format!("\"{}\"", secs), "relative+exact"])
         {
             Ok(r) => Ok(r),
             Err(e) => Err(anyhow!(format!("Error in mpv: {}", e))),
         }
     }
 
+    fn seek_to(&mut self, position: Duration) -> Result<()> {
+        match self.player.command(
+            "seek",
+            &[&format!("\"{}\"", position.as_secs_f64()), "absolute+exact"],
+        ) {
+            Ok(r) => Ok(r),
+            Err(e) => Err(anyhow!(format!("Error in mpv: {}", e))),
+        }
+    }
+
     fn get_progress(&mut self) -> Result<(f64, i64, i64)> {
         let percent_pos = self
             .player
@@ -119,4 +234,72 @@ impl GeneralP for Mpv {
         let duration = self.player.get_property::<i64>("duration").unwrap_or(0);
         Ok((percent_pos, time_pos, duration))
     }
+
+    fn enqueue_next(&mut self, new: &str, gain: f32) -> Result<()> {
+        // mpv plays its internal playlist gaplessly, so just append after the current file.
+        // The watching thread has to know about it before mpv can get to it.
+        *self.next.lock().unwrap() = Some((new.to_string(), gain));
+        match self
+            .player
+            .command("loadfile", &[&format!("\"{}\"", new), "append"])
+        {
+            Ok(()) => Ok(()),
+            Err(e) => {
+                *self.next.lock().unwrap() = None;
+                Err(anyhow!(format!("Error in mpv: {}", e)))
+            }
+        }
+    }
+
+    fn update(&mut self) {
+        // the events come from a thread of their own
+    }
+
+    // mpv keeps the pitch while changing the speed by itself
+    fn set_speed(&mut self, speed: f32) {
+        self.player
+            .set_property("speed", f64::from(speed.clamp(MIN_SPEED, MAX_SPEED)))
+            .ok();
+    }
+
+    fn set_crossfade(&mut self, _crossfade: Duration) {
+        // not supported, songs change gaplessly at best
+    }
+
+    fn set_output_device(&mut self, _name: Option<&str>) {
+        // not supported
+    }
+
+    fn set_bit_perfect(&mut self, _bit_perfect: bool) {
+        // not supported
+    }
+
+    fn set_resampler(&mut self, _resampler: Resampler) {
+        // not supported
+    }
+
+    fn set_equalizer(&mut self, bands: &[EqualizerBand]) {
+        // mpv has no equalizer of its own, go through the ffmpeg filters
+        let filters: Vec<String> = bands
+            .iter()
+            .filter(|band| band.gain != 0.0)
+            .map(|band| {
+                let filter = match band.kind {
+                    EqualizerBandKind::LowShelf => "bass",
+                    EqualizerBandKind::Peaking => "equalizer",
+                    EqualizerBandKind::HighShelf => "treble",
+                };
+                format!(
+                    "{}=f={}:t=q:w={}:g={}",
+                    filter, band.freq, band.q, band.gain
+                )
+            })
+            .collect();
+        let af = if filters.is_empty() {
+            String::new()
+        } else {
+            format!("lavfi=[{}]", filters.join(","))
+        };
+        self.player.set_property("af", af.as_str()).ok();
+    }
 }
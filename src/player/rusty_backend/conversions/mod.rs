@@ -5,7 +5,7 @@
 pub use self::channels::ChannelCountConverter;
 pub use self::sample::DataConverter;
 pub use self::sample::Sample;
-pub use self::sample_rate::SampleRateConverter;
+pub use self::sample_rate::RateConverter;
 
 mod channels;
 mod sample;
@@ -1,5 +1,8 @@
 use super::Sample;
+use crate::config::Resampler;
 
+use cpal::Sample as CpalSample;
+use std::f64::consts::PI;
 use std::mem;
 
 /// Iterator that converts from a certain sample rate to another.
@@ -241,3 +244,475 @@ where
         }
     }
 }
+
+/// The sample rate converter picked by `Resampler`.
+#[allow(clippy::module_name_repetitions)]
+pub enum RateConverter<I>
+where
+    I: Iterator,
+{
+    Linear(SampleRateConverter<I>),
+    Sinc(SincRateConverter<I>),
+}
+
+impl<I> Clone for RateConverter<I>
+where
+    I: Iterator + Clone,
+    I::Item: Clone,
+{
+    fn clone(&self) -> Self {
+        match self {
+            Self::Linear(converter) => Self::Linear(converter.clone()),
+            Self::Sinc(converter) => Self::Sinc(converter.clone()),
+        }
+    }
+}
+
+impl<I> RateConverter<I>
+where
+    I: Iterator,
+    I::Item: Sample,
+{
+    #[inline]
+    pub fn new(
+        input: I,
+        from: cpal::SampleRate,
+        to: cpal::SampleRate,
+        num_channels: cpal::ChannelCount,
+        resampler: Resampler,
+    ) -> Self {
+        match resampler {
+            Resampler::Linear => {
+                Self::Linear(SampleRateConverter::new(input, from, to, num_channels))
+            }
+            Resampler::SincFast | Resampler::SincBest => Self::Sinc(SincRateConverter::new(
+                input,
+                from,
+                to,
+                num_channels,
+                resampler,
+            )),
+        }
+    }
+
+    /// Destroys this iterator and returns the underlying iterator.
+    #[inline]
+    pub fn into_inner(self) -> I {
+        match self {
+            Self::Linear(converter) => converter.into_inner(),
+            Self::Sinc(converter) => converter.into_inner(),
+        }
+    }
+}
+
+impl<I> Iterator for RateConverter<I>
+where
+    I: Iterator,
+    I::Item: Sample + Clone,
+{
+    type Item = I::Item;
+
+    #[inline]
+    fn next(&mut self) -> Option<I::Item> {
+        match self {
+            Self::Linear(converter) => converter.next(),
+            Self::Sinc(converter) => converter.next(),
+        }
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        match self {
+            Self::Linear(converter) => converter.size_hint(),
+            Self::Sinc(converter) => converter.size_hint(),
+        }
+    }
+}
+
+/// Phases of the sinc filter table at most, finer ones get interpolated between two of them.
+const MAX_PHASES: usize = 1024;
+
+/// Iterator that converts from a certain sample rate to another with a windowed sinc filter,
+/// which keeps the images and aliases of the linear interpolation out of the audible band.
+///
+/// Every output frame is a weighted sum of the input frames around its position. The weights
+/// come from a table holding the filter for a number of positions between two input frames.
+#[derive(Clone, Debug)]
+pub struct SincRateConverter<I>
+where
+    I: Iterator,
+{
+    input: I,
+    /// We convert chunks of `from` samples into chunks of `to` samples.
+    from: u64,
+    to: u64,
+    channels: usize,
+    /// Input frames on each side of an output position that the filter reaches.
+    half: usize,
+    phases: usize,
+    /// `phases + 1` rows of `2 * half` weights, the last row is one frame after the first.
+    table: Vec<f32>,
+    /// Interleaved input frames, the first one has the index `history_start`.
+    history: Vec<f32>,
+    history_start: i64,
+    /// Input frames read so far, the zeros after the end don't count.
+    input_frames: i64,
+    ended: bool,
+    /// Index of the next output frame.
+    output_frame: u64,
+    /// The rest of the current output frame.
+    output_buffer: Vec<f32>,
+    output_pos: usize,
+}
+
+impl<I> SincRateConverter<I>
+where
+    I: Iterator,
+    I::Item: Sample,
+{
+    ///
+    ///
+    /// # Panic
+    ///
+    /// Panics if `from` or `to` are equal to 0.
+    ///
+    #[allow(
+        clippy::cast_possible_truncation,
+        clippy::cast_possible_wrap,
+        clippy::cast_precision_loss,
+        clippy::cast_sign_loss
+    )]
+    pub fn new(
+        input: I,
+        from: cpal::SampleRate,
+        to: cpal::SampleRate,
+        num_channels: cpal::ChannelCount,
+        resampler: Resampler,
+    ) -> Self {
+        assert!(from.0 >= 1);
+        assert!(to.0 >= 1);
+        let gcd = gcd(from.0, to.0);
+        let from = u64::from(from.0 / gcd);
+        let to = u64::from(to.0 / gcd);
+
+        let (zero_crossings, rolloff, window): (f64, f64, fn(f64) -> f64) = match resampler {
+            Resampler::SincBest => (64.0, 0.95, blackman_harris),
+            _ => (16.0, 0.9, blackman),
+        };
+        // cutoff below the lower of both nyquist frequencies, in cycles per input sample
+        let cutoff = 0.5 * rolloff * (to as f64 / from as f64).min(1.0);
+        let half = (zero_crossings / (2.0 * cutoff)).ceil() as usize;
+        let phases = (to as usize).min(MAX_PHASES);
+        let table = sinc_table(half, phases, cutoff, window);
+
+        let channels = usize::from(num_channels);
+        Self {
+            input,
+            from,
+            to,
+            channels,
+            half,
+            phases,
+            table,
+            // zeros before the start, so the first output frame lands on the first input frame
+            history: vec![0.0; (half - 1) * channels],
+            history_start: 1 - half as i64,
+            input_frames: 0,
+            ended: false,
+            output_frame: 0,
+            output_buffer: Vec::with_capacity(channels),
+            output_pos: 0,
+        }
+    }
+
+    /// Destroys this iterator and returns the underlying iterator.
+    #[inline]
+    pub fn into_inner(self) -> I {
+        self.input
+    }
+
+    #[allow(clippy::cast_possible_wrap)]
+    fn history_end(&self) -> i64 {
+        self.history_start + (self.history.len() / self.channels.max(1)) as i64
+    }
+
+    // Reads one input frame into the history, or zeros once the input has run out.
+    fn push_input_frame(&mut self) {
+        let len = self.history.len();
+        if !self.ended {
+            for _ in 0..self.channels {
+                if let Some(sample) = self.input.next() {
+                    self.history.push(sample.to_f32());
+                } else {
+                    // a partial frame at the end is dropped
+                    self.history.truncate(len);
+                    self.ended = true;
+                    break;
+                }
+            }
+            if !self.ended {
+                self.input_frames += 1;
+                return;
+            }
+        }
+        self.history.resize(len + self.channels, 0.0);
+    }
+
+    #[allow(
+        clippy::cast_possible_truncation,
+        clippy::cast_possible_wrap,
+        clippy::cast_sign_loss,
+        clippy::cast_precision_loss
+    )]
+    fn next_output_frame(&mut self) -> bool {
+        // position of the output frame in input frames: `index + fraction / to`
+        let position = self.output_frame * self.from;
+        let index = (position / self.to) as i64;
+        if self.ended && index >= self.input_frames {
+            return false;
+        }
+        let fraction = ((position % self.to) * self.phases as u64) as f64 / self.to as f64;
+        let phase = fraction as usize;
+        let weight = fraction.fract() as f32;
+
+        let first = index + 1 - self.half as i64;
+        while self.history_end() <= index + self.half as i64 {
+            self.push_input_frame();
+        }
+        if self.ended && index >= self.input_frames {
+            return false;
+        }
+        // forget frames the filter won't reach again, in batches
+        let unused = (first - self.history_start) as usize;
+        if unused > 1024 {
+            self.history.drain(..unused * self.channels);
+            self.history_start = first;
+        }
+
+        let taps = 2 * self.half;
+        let row = &self.table[phase * taps..(phase + 1) * taps];
+        let next_row = &self.table[(phase + 1) * taps..(phase + 2) * taps];
+        let offset = (first - self.history_start) as usize;
+        self.output_buffer.clear();
+        for channel in 0..self.channels {
+            let frames = self.history[offset * self.channels + channel..]
+                .iter()
+                .step_by(self.channels);
+            let mut sum = 0.0;
+            let mut next_sum = 0.0;
+            for ((x, a), b) in frames.zip(row).zip(next_row) {
+                sum += x * a;
+                next_sum += x * b;
+            }
+            self.output_buffer
+                .push((next_sum - sum).mul_add(weight, sum));
+        }
+        self.output_pos = 0;
+        self.output_frame += 1;
+        true
+    }
+}
+
+impl<I> Iterator for SincRateConverter<I>
+where
+    I: Iterator,
+    I::Item: Sample,
+{
+    type Item = I::Item;
+
+    fn next(&mut self) -> Option<I::Item> {
+        if self.from == self.to {
+            return self.input.next();
+        }
+        if self.output_pos == self.output_buffer.len() && !self.next_output_frame() {
+            return None;
+        }
+        let sample = self.output_buffer[self.output_pos];
+        self.output_pos += 1;
+        Some(CpalSample::from(&sample))
+    }
+
+    #[inline]
+    #[allow(clippy::cast_possible_truncation)]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let pending = self.output_buffer.len() - self.output_pos;
+        if self.from == self.to {
+            return self.input.size_hint();
+        }
+        let apply = |samples: usize| pending + (samples as u64 * self.to / self.from) as usize;
+        let (min, max) = self.input.size_hint();
+        (apply(min), max.map(apply))
+    }
+}
+
+fn gcd(a: u32, b: u32) -> u32 {
+    if b == 0 {
+        a
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+// Filter weights for `phases + 1` positions between two input frames. Each row is normalized,
+// so a constant signal stays the same.
+#[allow(clippy::cast_precision_loss, clippy::cast_possible_truncation)]
+fn sinc_table(half: usize, phases: usize, cutoff: f64, window: fn(f64) -> f64) -> Vec<f32> {
+    let taps = 2 * half;
+    let mut table = Vec::with_capacity((phases + 1) * taps);
+    for phase in 0..=phases {
+        let fraction = phase as f64 / phases as f64;
+        let row: Vec<f64> = (0..taps)
+            .map(|tap| {
+                // distance of the input frame to the output position
+                let x = tap as f64 + 1.0 - half as f64 - fraction;
+                let sinc = if x == 0.0 {
+                    1.0
+                } else {
+                    (2.0 * PI * cutoff * x).sin() / (2.0 * PI * cutoff * x)
+                };
+                sinc * window(x / half as f64)
+            })
+            .collect();
+        let sum: f64 = row.iter().sum();
+        table.extend(row.iter().map(|w| (w / sum) as f32));
+    }
+    table
+}
+
+// Windows over -1..=1.
+#[allow(clippy::suboptimal_flops)]
+fn blackman(x: f64) -> f64 {
+    if x.abs() >= 1.0 {
+        return 0.0;
+    }
+    let t = PI * (x + 1.0);
+    0.42 - 0.5 * t.cos() + 0.08 * (2.0 * t).cos()
+}
+
+#[allow(clippy::suboptimal_flops)]
+fn blackman_harris(x: f64) -> f64 {
+    if x.abs() >= 1.0 {
+        return 0.0;
+    }
+    let t = PI * (x + 1.0);
+    0.358_75 - 0.488_29 * t.cos() + 0.141_28 * (2.0 * t).cos() - 0.011_68 * (3.0 * t).cos()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RateConverter;
+    use crate::config::Resampler;
+    use cpal::SampleRate;
+    use std::f64::consts::PI;
+
+    #[allow(clippy::cast_precision_loss, clippy::cast_possible_truncation)]
+    fn sine(freq: f64, rate: u32, frames: usize) -> Vec<f32> {
+        (0..frames)
+            .map(|n| (0.5 * (2.0 * PI * freq * n as f64 / f64::from(rate)).sin()) as f32)
+            .collect()
+    }
+
+    fn convert(input: Vec<f32>, from: u32, to: u32, resampler: Resampler) -> Vec<f32> {
+        RateConverter::new(
+            input.into_iter(),
+            SampleRate(from),
+            SampleRate(to),
+            1,
+            resampler,
+        )
+        .collect()
+    }
+
+    // Power of what isn't a sine of `freq` relative to the sine, in dB. The sine is fitted by
+    // least squares and the edges, where the filter reaches past the input, are left out.
+    #[allow(clippy::cast_precision_loss, clippy::suboptimal_flops)]
+    fn residual_db(output: &[f32], freq: f64, rate: u32) -> f64 {
+        let samples: Vec<(f64, f64, f64)> = output
+            .iter()
+            .enumerate()
+            .skip(2000)
+            .take(output.len() - 4000)
+            .map(|(n, y)| {
+                let t = 2.0 * PI * freq * n as f64 / f64::from(rate);
+                (f64::from(*y), t.sin(), t.cos())
+            })
+            .collect();
+        let (mut ss, mut cc, mut sc, mut ys, mut yc) = (0.0, 0.0, 0.0, 0.0, 0.0);
+        for (y, s, c) in &samples {
+            ss += s * s;
+            cc += c * c;
+            sc += s * c;
+            ys += y * s;
+            yc += y * c;
+        }
+        let det = ss.mul_add(cc, -sc * sc);
+        let a = (ys * cc - yc * sc) / det;
+        let b = (yc * ss - ys * sc) / det;
+        let (mut signal, mut error) = (0.0, 0.0);
+        for (y, s, c) in &samples {
+            let fit = a * s + b * c;
+            signal += fit * fit;
+            error += (y - fit) * (y - fit);
+        }
+        10.0 * (error / signal).log10()
+    }
+
+    fn power(samples: &[f32]) -> f64 {
+        samples[2000..samples.len() - 2000]
+            .iter()
+            .map(|y| f64::from(*y) * f64::from(*y))
+            .sum()
+    }
+
+    #[test]
+    fn sinc_upsampling_sweep() {
+        for freq in [100.0, 1000.0, 5000.0, 10_000.0, 15_000.0, 19_000.0] {
+            let input = sine(freq, 44100, 44100);
+            let linear = residual_db(
+                &convert(input.clone(), 44100, 48000, Resampler::Linear),
+                freq,
+                48000,
+            );
+            let fast = residual_db(
+                &convert(input.clone(), 44100, 48000, Resampler::SincFast),
+                freq,
+                48000,
+            );
+            let best = residual_db(
+                &convert(input, 44100, 48000, Resampler::SincBest),
+                freq,
+                48000,
+            );
+            assert!(best < -70.0, "sinc-best at {freq} Hz: {best:.1} dB");
+            assert!(fast < -45.0, "sinc-fast at {freq} Hz: {fast:.1} dB");
+            if freq >= 5000.0 {
+                assert!(
+                    fast < linear - 10.0,
+                    "sinc-fast at {freq} Hz: {fast:.1} dB, linear {linear:.1} dB"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn sinc_length() {
+        for resampler in [Resampler::SincFast, Resampler::SincBest] {
+            let output = convert(sine(1000.0, 44100, 44100), 44100, 48000, resampler);
+            assert_eq!(output.len(), 48000);
+            let output = convert(sine(1000.0, 48000, 48000), 48000, 44100, resampler);
+            assert_eq!(output.len(), 44100);
+        }
+    }
+
+    #[test]
+    fn sinc_downsampling_removes_aliases() {
+        // above the nyquist frequency of 44.1 kHz, it would fold back to 20.6 kHz
+        let input = sine(23500.0, 48000, 48000);
+        let reference = power(&sine(1000.0, 44100, 44100));
+        for (resampler, limit) in [(Resampler::SincFast, -40.0), (Resampler::SincBest, -70.0)] {
+            let output = convert(input.clone(), 48000, 44100, resampler);
+            let db = 10.0 * (power(&output) / reference).log10();
+            assert!(db < limit, "{resampler:?}: {db:.1} dB");
+        }
+    }
+}
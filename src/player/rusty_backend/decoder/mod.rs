@@ -7,7 +7,8 @@ use std::time::Duration;
 
 use super::Source;
 
-use self::{read_seek_source::ReadSeekSource, symphonia::SymphoniaDecoder};
+use self::read_seek_source::ReadSeekSource;
+pub use self::symphonia::SymphoniaDecoder;
 use ::symphonia::core::io::{MediaSource, MediaSourceStream};
 mod read_seek_source;
 mod symphonia;
@@ -79,10 +80,10 @@ impl fmt::Display for Mp4Type {
 }
 
 impl Iterator for Decoder {
-    type Item = i16;
+    type Item = f32;
 
     #[inline]
-    fn next(&mut self) -> Option<i16> {
+    fn next(&mut self) -> Option<f32> {
         self.decoder.next()
     }
 
@@ -161,3 +162,59 @@ impl fmt::Display for DecoderError {
     }
 }
 impl Error for DecoderError {}
+
+#[cfg(test)]
+mod tests {
+    use super::Decoder;
+    use crate::player::rusty_backend::Source;
+    use std::io::Cursor;
+
+    // A 24 bit PCM wav file holding `samples` interleaved over `channels`.
+    #[allow(clippy::cast_possible_truncation)]
+    fn wav_24(channels: u16, sample_rate: u32, samples: &[i32]) -> Vec<u8> {
+        let data_len = samples.len() as u32 * 3;
+        let block_align = channels * 3;
+        let mut wav = Vec::new();
+        wav.extend_from_slice(b"RIFF");
+        wav.extend_from_slice(&(36 + data_len).to_le_bytes());
+        wav.extend_from_slice(b"WAVEfmt ");
+        wav.extend_from_slice(&16_u32.to_le_bytes());
+        wav.extend_from_slice(&1_u16.to_le_bytes());
+        wav.extend_from_slice(&channels.to_le_bytes());
+        wav.extend_from_slice(&sample_rate.to_le_bytes());
+        wav.extend_from_slice(&(sample_rate * u32::from(block_align)).to_le_bytes());
+        wav.extend_from_slice(&block_align.to_le_bytes());
+        wav.extend_from_slice(&24_u16.to_le_bytes());
+        wav.extend_from_slice(b"data");
+        wav.extend_from_slice(&data_len.to_le_bytes());
+        for sample in samples {
+            wav.extend_from_slice(&sample.to_le_bytes()[..3]);
+        }
+        wav
+    }
+
+    #[test]
+    #[allow(clippy::cast_precision_loss)]
+    fn decode_24_bit_exactly() {
+        const FULL_SCALE: f32 = 8_388_608.0;
+        // the smallest steps, which i16 would round away, and both ends of the range
+        let mut samples: Vec<i32> = (-300..300).collect();
+        samples.extend_from_slice(&[-8_388_608, 8_388_607, 0x12_3456, -0x65_4321, 1, -1]);
+        for step in 0..10_000 {
+            samples.push((step * 1_677) % 8_388_607 - 4_194_304);
+        }
+
+        let source =
+            Decoder::new_decoder(Cursor::new(wav_24(2, 96_000, &samples))).expect("decodes");
+        assert_eq!(source.channels(), 2);
+        assert_eq!(source.sample_rate(), 96_000);
+        let values: Vec<f32> = source.collect();
+        assert_eq!(values.len(), samples.len());
+        for (sample, value) in samples.iter().zip(values) {
+            assert!(
+                value.mul_add(FULL_SCALE, -(*sample as f32)).abs() < 0.5,
+                "{sample} decoded to {value}"
+            );
+        }
+    }
+}
@@ -8,7 +8,7 @@ use symphonia::{
         io::MediaSourceStream,
         // meta::MetadataOptions,
         probe::Hint,
-        units::{Time, TimeBase},
+        units::{Time, TimeBase, TimeStamp},
     },
     default::get_probe,
 };
@@ -22,10 +22,12 @@ pub struct SymphoniaDecoder {
     decoder: Box<dyn Decoder>,
     current_frame_offset: usize,
     format: Box<dyn FormatReader>,
-    buffer: SampleBuffer<i16>,
+    buffer: SampleBuffer<f32>,
     spec: SignalSpec,
     total_duration: Duration,
     elapsed: Duration,
+    track_id: u32,
+    time_base: Option<TimeBase>,
 }
 
 #[allow(unused)]
@@ -68,6 +70,7 @@ impl SymphoniaDecoder {
             None => return Ok(None),
         };
 
+        let track_id = stream.id;
         let mut decoder = symphonia::default::get_codecs()
             .make(&stream.codec_params, &DecoderOptions { verify: true })?;
 
@@ -104,17 +107,56 @@ impl SymphoniaDecoder {
             spec,
             total_duration,
             elapsed: Duration::from_secs(0),
+            track_id,
+            time_base: tb,
         }))
     }
 
+    // Samples are kept as f32, which holds up to 24 bit integers exactly, so hi-res sources keep
+    // their precision on the way to the mixer.
     #[inline]
     #[allow(clippy::trivially_copy_pass_by_ref)]
-    fn get_buffer(decoded: AudioBufferRef, spec: &SignalSpec) -> SampleBuffer<i16> {
+    fn get_buffer(decoded: AudioBufferRef, spec: &SignalSpec) -> SampleBuffer<f32> {
         let duration = decoded.capacity() as u64;
-        let mut buffer = SampleBuffer::<i16>::new(duration, *spec);
+        let mut buffer = SampleBuffer::<f32>::new(duration, *spec);
         buffer.copy_interleaved_ref(decoded);
         buffer
     }
+
+    fn time(&self, ts: TimeStamp) -> Duration {
+        self.time_base.map_or(Duration::ZERO, |tb| {
+            let t = tb.calc_time(ts);
+            Duration::from_secs(t.seconds) + Duration::from_secs_f64(t.frac)
+        })
+    }
+
+    // The format reader lands on the packet containing `required_ts`, the samples of that packet
+    // before it are decoded and dropped so playback resumes at the exact position.
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    fn skip_to(&mut self, required_ts: TimeStamp) -> symphonia::core::errors::Result<()> {
+        self.decoder.reset();
+        loop {
+            let packet = self.format.next_packet()?;
+            if packet.track_id() != self.track_id {
+                continue;
+            }
+            let decoded = match self.decoder.decode(&packet) {
+                Ok(decoded) => decoded,
+                Err(Error::DecodeError(_)) => continue,
+                Err(e) => return Err(e),
+            };
+            self.spec = *decoded.spec();
+            self.buffer = Self::get_buffer(decoded, &self.spec);
+            if packet.ts() + packet.dur() > required_ts {
+                let skip = self.time(required_ts.saturating_sub(packet.ts()));
+                let frames = (skip.as_secs_f64() * f64::from(self.spec.rate)).round() as usize;
+                self.current_frame_offset =
+                    (frames * self.spec.channels.count()).min(self.buffer.len());
+                self.elapsed = self.time(required_ts);
+                return Ok(());
+            }
+        }
+    }
 }
 
 #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
@@ -147,34 +189,29 @@ impl Source for SymphoniaDecoder {
     #[inline]
     fn seek(&mut self, time: Duration) -> Result<Duration, ()> {
         let nanos_per_sec = 1_000_000_000.0;
-        match self.format.seek(
-            SeekMode::Coarse,
-            SeekTo::Time {
-                time: Time::new(
-                    time.as_secs(),
-                    f64::from(time.subsec_nanos()) / nanos_per_sec,
-                ),
-                track_id: None,
-            },
-        ) {
-            Ok(seeked_to) => {
-                let base = TimeBase::new(1, self.sample_rate());
-                let time = base.calc_time(seeked_to.actual_ts);
-
-                Ok(Duration::from_millis(
-                    time.seconds * 1000 + ((time.frac * 60. * 1000.).round() as u64),
-                ))
-            }
-            Err(_) => Err(()),
-        }
+        let seeked_to = self
+            .format
+            .seek(
+                SeekMode::Accurate,
+                SeekTo::Time {
+                    time: Time::new(
+                        time.as_secs(),
+                        f64::from(time.subsec_nanos()) / nanos_per_sec,
+                    ),
+                    track_id: Some(self.track_id),
+                },
+            )
+            .map_err(|_| ())?;
+        self.skip_to(seeked_to.required_ts).map_err(|_| ())?;
+        Ok(self.elapsed)
     }
 }
 
 impl Iterator for SymphoniaDecoder {
-    type Item = i16;
+    type Item = f32;
 
     #[inline]
-    fn next(&mut self) -> Option<i16> {
+    fn next(&mut self) -> Option<f32> {
         if self.current_frame_offset == self.buffer.len() {
             match self.format.next_packet() {
                 Ok(packet) => match self.decoder.decode(&packet) {
@@ -182,20 +219,7 @@ impl Iterator for SymphoniaDecoder {
                         self.spec = *decoded.spec();
                         self.buffer = Self::get_buffer(decoded, &self.spec);
 
-                        let ts = packet.ts();
-                        let tb = self
-                            .format
-                            .tracks()
-                            .first()
-                            .unwrap()
-                            .codec_params
-                            .time_base
-                            .unwrap();
-
-                        let t = tb.calc_time(ts);
-
-                        self.elapsed =
-                            Duration::from_secs(t.seconds) + Duration::from_secs_f64(t.frac);
+                        self.elapsed = self.time(packet.ts());
                     }
                     Err(_) => return None,
                 },
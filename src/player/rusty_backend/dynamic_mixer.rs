@@ -6,6 +6,7 @@ use std::time::Duration;
 
 use super::source::{Source, UniformSourceIterator};
 use super::Sample;
+use crate::config::Resampler;
 
 /// Builds a new mixer.
 ///
@@ -25,6 +26,7 @@ where
         pending_sources: Mutex::new(Vec::new()),
         channels,
         sample_rate,
+        resampler: Mutex::new(Resampler::default()),
     });
 
     let output = DynamicMixer {
@@ -45,6 +47,8 @@ pub struct DynamicMixerController<S> {
     pending_sources: Mutex<Vec<Box<dyn Source<Item = S> + Send>>>,
     channels: u16,
     sample_rate: u32,
+    /// Used for the sounds added from now on.
+    resampler: Mutex<Resampler>,
 }
 
 impl<S> DynamicMixerController<S>
@@ -57,13 +61,33 @@ where
     where
         T: Source<Item = S> + Send + 'static,
     {
-        let uniform_source = UniformSourceIterator::new(source, self.channels, self.sample_rate);
+        let resampler = *self.resampler.lock().unwrap();
+        let uniform_source =
+            UniformSourceIterator::new(source, self.channels, self.sample_rate, resampler);
         self.pending_sources
             .lock()
             .unwrap()
             .push(Box::new(uniform_source) as Box<_>);
         self.has_pending.store(true, Ordering::SeqCst); // TODO: can we relax this ordering?
     }
+
+    /// Changes how the sounds added from now on get converted to the output sample rate.
+    #[inline]
+    pub fn set_resampler(&self, resampler: Resampler) {
+        *self.resampler.lock().unwrap() = resampler;
+    }
+
+    /// Channel count of the mixed output.
+    #[inline]
+    pub const fn channels(&self) -> u16 {
+        self.channels
+    }
+
+    /// Sample rate of the mixed output.
+    #[inline]
+    pub const fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
 }
 
 /// The output of the mixer. Implements `Source`.
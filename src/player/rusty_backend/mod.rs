@@ -15,48 +15,98 @@ pub mod queue;
 pub mod source;
 
 pub use conversions::Sample;
-pub use decoder::Decoder;
+pub use decoder::{Decoder, SymphoniaDecoder};
 pub use sink::Sink;
 pub use source::Source;
-pub use stream::{OutputStream, OutputStreamHandle, PlayError, StreamError};
+pub use stream::{output_device_names, OutputStream, OutputStreamHandle, PlayError, StreamError};
 
-use std::path::Path;
-use std::time::Duration;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{Receiver, Sender, TryRecvError};
+use std::time::{Duration, Instant};
 use std::{fs::File, io::BufReader};
 
-use super::GeneralP;
-use anyhow::Result;
+use super::{GeneralP, PlayerEvent, MAX_SPEED, MIN_SPEED};
+use crate::config::{EqualizerBand, Resampler};
+use anyhow::{bail, Result};
 
 static VOLUME_STEP: u16 = 5;
+/// How often to look for an output device again, while there is none.
+const RECOVER_INTERVAL: Duration = Duration::from_secs(2);
 
 pub struct Player {
-    _stream: OutputStream,
+    /// `None` while no output device works, the sinks play into nothing then.
+    stream: Option<OutputStream>,
+    /// Format the stream was opened for, which the device may have refused for another one.
+    requested_format: Option<(u16, u32)>,
     handle: OutputStreamHandle,
+    /// Name of the chosen output device, `None` for the system default.
+    device: Option<String>,
+    last_recover: Instant,
+    /// Song and gain of the current source and the enqueued one, to restart them on another
+    /// device.
+    current: Option<(PathBuf, f32)>,
+    next: Option<(PathBuf, f32)>,
     sink: Sink,
     total_duration: Option<Duration>,
     volume: u16,
-    safe_guard: bool,
+    /// Scales the volume while fading out, without changing it.
+    fade: f32,
+    /// How fast songs play, the pitch stays.
+    speed: f32,
+    /// Signalled by the sink when the current source runs out.
+    current_end: Option<Receiver<()>>,
+    /// Duration of the source appended by `enqueue_next`, while it waits in the queue.
+    next_duration: Option<Duration>,
+    crossfade: Duration,
+    /// Source waiting for the crossfade point, it gets a sink of its own then.
+    next_source: Option<SymphoniaDecoder>,
+    /// `ReplayGain` factor of the enqueued source.
+    next_gain: f32,
+    /// Sink of the previous song while it fades out.
+    fading_sink: Option<Sink>,
+    equalizer: Vec<EqualizerBand>,
+    /// Reopen the output in the native format of each song.
+    bit_perfect: bool,
+    resampler: Resampler,
+    events: Sender<PlayerEvent>,
+    /// Whole seconds of the position last reported.
+    position: Option<u64>,
 }
-impl Default for Player {
-    fn default() -> Self {
-        let (stream, handle) = OutputStream::try_default().unwrap();
-        let sink = Sink::try_new(&handle).unwrap();
+
+#[allow(unused)]
+impl Player {
+    pub fn new(events: Sender<PlayerEvent>) -> Self {
+        let (stream, handle) = open_stream(None, None);
+        let sink = new_sink(&handle);
         let volume = 50;
         sink.set_volume(f32::from(volume) / 100.0);
 
         Self {
-            _stream: stream,
+            stream,
+            requested_format: None,
             handle,
+            device: None,
+            last_recover: Instant::now(),
+            current: None,
+            next: None,
             sink,
             total_duration: None,
             volume,
-            safe_guard: true,
+            fade: 1.0,
+            speed: 1.0,
+            current_end: None,
+            next_duration: None,
+            crossfade: Duration::from_secs(0),
+            next_source: None,
+            next_gain: 1.0,
+            fading_sink: None,
+            equalizer: Vec::new(),
+            bit_perfect: false,
+            resampler: Resampler::default(),
+            events,
+            position: None,
         }
     }
-}
-
-#[allow(unused)]
-impl Player {
     pub const fn set_volume_inside(mut self, volume: u16) -> Self {
         self.volume = volume;
         self
@@ -72,56 +122,260 @@ impl Player {
             self.volume = 100;
         }
 
-        self.sink.set_volume(f32::from(self.volume) / 100.0);
+        self.sink.set_volume(self.sink_volume());
+    }
+    fn sink_volume(&self) -> f32 {
+        f32::from(self.volume) / 100.0 * self.fade
+    }
+    fn apply_fade(&self) {
+        self.sink.set_volume(self.sink_volume());
+        if let Some(sink) = &self.fading_sink {
+            sink.set_volume(self.sink_volume());
+        }
     }
     pub fn sleep_until_end(&self) {
         self.sink.sleep_until_end();
     }
-    pub fn play(&mut self, path: &Path) {
+    pub fn play(&mut self, path: &Path, gain: f32) {
+        if let Err(e) = self.play_from(path, gain, Duration::ZERO) {
+            self.send(PlayerEvent::Error(format!(
+                "Couldn't play {}: {}",
+                path.display(),
+                e
+            )));
+            return;
+        }
+        self.send(PlayerEvent::TrackStarted(
+            path.to_string_lossy().to_string(),
+        ));
+        if let Some(duration) = self.total_duration {
+            self.send(PlayerEvent::DurationKnown(duration));
+        }
+    }
+    fn play_from(&mut self, path: &Path, gain: f32, position: Duration) -> Result<()> {
         self.stop();
-        let file = File::open(path).unwrap();
-        let decoder = Decoder::new_decoder(BufReader::new(file)).unwrap();
+        let file = File::open(path)?;
+        let mut decoder = Decoder::new_decoder(BufReader::new(file))?;
+        if self.bit_perfect {
+            self.match_format(decoder.channels(), decoder.sample_rate());
+        }
+        if position > Duration::ZERO {
+            decoder.seek(position).ok();
+        }
         self.total_duration = decoder.total_duration();
-        self.sink.append(decoder);
+        self.sink.append_with_gain(decoder, gain);
+        self.current_end = self.sink.get_current_receiver();
+        self.current = Some((path.to_path_buf(), gain));
+        Ok(())
+    }
+    /// Appends `path` to the running sink, so it plays sample-for-sample after the current
+    /// source instead of through a new sink.
+    ///
+    /// With a crossfade set, the source is held back until the crossfade point instead.
+    pub fn play_next(&mut self, path: &Path, gain: f32) -> Result<()> {
+        let file = File::open(path)?;
+        let decoder = Decoder::new_decoder(BufReader::new(file))?;
+        if self.bit_perfect && !self.plays_in(decoder.channels(), decoder.sample_rate()) {
+            bail!("the output has to be reopened for the format of the next song");
+        }
+        self.next_duration = decoder.total_duration();
+        self.next_gain = gain;
+        self.next = Some((path.to_path_buf(), gain));
+        // without a known length there is no crossfade point to wait for
+        let known_length = self.total_duration.unwrap_or_default() > Duration::from_secs(0);
+        if self.crossfade > Duration::from_secs(0) && known_length {
+            self.next_source = Some(decoder);
+        } else {
+            self.sink.append_with_gain(decoder, gain);
+        }
+        Ok(())
     }
     pub fn stop(&mut self) {
         self.sink.destroy();
-        self.sink = Sink::try_new(&self.handle).unwrap();
-        self.sink.set_volume(f32::from(self.volume) / 100.0);
+        self.sink = new_sink(&self.handle);
+        self.current = None;
+        self.next = None;
+        self.sink.set_volume(self.sink_volume());
+        self.sink.set_equalizer(&self.equalizer);
+        self.sink.set_speed(self.speed);
+        self.current_end = None;
+        self.next_duration = None;
+        self.next_source = None;
+        self.position = None;
+        if let Some(mut sink) = self.fading_sink.take() {
+            sink.destroy();
+        }
+    }
+    // Starts the held back source in a new sink, fading in while the current sink fades out.
+    // Both sinks feed the same mixer, so the two songs are summed for the overlap.
+    fn start_crossfade(&mut self, fade: Duration) {
+        if let Some(source) = self.next_source.take() {
+            let sink = new_sink(&self.handle);
+            sink.set_volume(self.sink_volume());
+            sink.set_equalizer(&self.equalizer);
+            sink.set_speed(self.speed);
+            sink.append_with_gain(source.fade_in(fade), self.next_gain);
+
+            let old_sink = std::mem::replace(&mut self.sink, sink);
+            old_sink.fade_out(fade);
+            if let Some(mut sink) = self.fading_sink.replace(old_sink) {
+                sink.destroy();
+            }
+            self.hand_over();
+        }
+    }
+    // The enqueued source has become the current one: take over its duration and end signal.
+    fn hand_over(&mut self) {
+        self.total_duration = self.next_duration.take();
+        self.current_end = self.sink.get_current_receiver();
+        self.current = self.next.take();
+        self.position = None;
+        if let Some((path, _)) = &self.current {
+            self.send(PlayerEvent::TrackStarted(
+                path.to_string_lossy().to_string(),
+            ));
+        }
+        if let Some(duration) = self.total_duration {
+            self.send(PlayerEvent::DurationKnown(duration));
+        }
+    }
+    // Starts the crossfade when its time has come, or hands over once the current source has
+    // ended and the enqueued one follows in the same sink.
+    fn check_next_started(&mut self) {
+        if let Some(sink) = &mut self.fading_sink {
+            if sink.is_empty() {
+                sink.destroy();
+                self.fading_sink = None;
+            }
+        }
+        if self.next_source.is_some() {
+            // the position is in song time, the crossfade in real time
+            let remaining = self
+                .total_duration
+                .unwrap_or_default()
+                .saturating_sub(self.elapsed())
+                .div_f32(self.speed);
+            if remaining <= self.crossfade {
+                self.start_crossfade(remaining);
+            }
+            return;
+        }
+        if self.next.is_none() {
+            return;
+        }
+        let running = matches!(
+            self.current_end.as_ref().map(Receiver::try_recv),
+            Some(Err(TryRecvError::Empty))
+        );
+        if !running {
+            self.hand_over();
+        }
+    }
+    // Reports the end of the current source, when nothing was enqueued after it.
+    fn check_ended(&mut self) {
+        let ended = matches!(
+            self.current_end.as_ref().map(Receiver::try_recv),
+            Some(Ok(()))
+        );
+        if ended {
+            self.current_end = None;
+            self.current = None;
+            self.send(PlayerEvent::TrackEnded);
+        }
+    }
+    fn send(&self, event: PlayerEvent) {
+        // the receiving end only goes away on exit
+        self.events.send(event).ok();
+    }
+    // Rebuilds stream and sink, then starts the current song again at the same position.
+    fn reopen_stream(&mut self) {
+        let position = self.elapsed();
+        let paused = self.is_paused();
+        let current = self.current.take();
+        let next = self.next.take();
+
+        // the old stream has to go first, some hosts only open a device once
+        self.stop();
+        self.stream = None;
+        // in bit perfect mode, the current song opens it in its own format
+        if !self.bit_perfect || current.is_none() {
+            self.open_stream(None);
+            self.stop();
+        }
+
+        if let Some((path, gain)) = current {
+            if let Err(e) = self.play_from(&path, gain, position) {
+                if self.stream.is_none() {
+                    self.open_stream(None);
+                    self.stop();
+                }
+                self.send(PlayerEvent::Error(format!(
+                    "Couldn't play {}: {}",
+                    path.display(),
+                    e
+                )));
+                return;
+            }
+            if paused {
+                self.sink.pause();
+            }
+            if let Some((path, gain)) = next {
+                self.play_next(&path, gain).ok();
+            }
+        }
+    }
+    fn open_stream(&mut self, format: Option<(u16, u32)>) {
+        self.stream = None;
+        let (stream, handle) = open_stream(self.device.as_deref(), format);
+        handle.set_resampler(self.resampler);
+        self.stream = stream;
+        self.requested_format = format;
+        self.handle = handle;
+    }
+    fn format(&self) -> Option<(u16, u32)> {
+        self.stream.as_ref().map(OutputStream::format)
+    }
+    // Whether the stream is in that format, or was opened for it and the device gave another.
+    fn plays_in(&self, channels: u16, sample_rate: u32) -> bool {
+        let format = Some((channels, sample_rate));
+        self.stream.is_some() && (self.format() == format || self.requested_format == format)
+    }
+    // Reopens the stream in the format of the song about to play, so it doesn't go through the
+    // resampler. Stays with whatever format the device picks if it can't do that one, and
+    // doesn't ask again while the songs keep coming in that format.
+    fn match_format(&mut self, channels: u16, sample_rate: u32) {
+        if self.plays_in(channels, sample_rate) {
+            return;
+        }
+        self.sink.destroy();
+        self.open_stream(Some((channels, sample_rate)));
+        self.stop();
+    }
+    // Switches to another device when the one in use has gone, or looks for one when there was
+    // none.
+    fn recover_stream(&mut self) {
+        let lost = !matches!(&self.stream, Some(stream) if !stream.is_lost());
+        if lost && self.last_recover.elapsed() >= RECOVER_INTERVAL {
+            self.last_recover = Instant::now();
+            self.reopen_stream();
+        }
     }
     pub fn elapsed(&self) -> Duration {
         self.sink.elapsed()
     }
     pub fn duration(&self) -> Option<f64> {
-        self.total_duration
-            .map(|duration| duration.as_secs_f64() - 0.29)
+        self.total_duration.map(|duration| duration.as_secs_f64())
     }
     pub fn toggle_playback(&self) {
         self.sink.toggle_playback();
-    }
-    pub fn is_paused(&self) -> bool {
-        self.sink.is_paused()
-    }
-    pub fn seek_fw(&mut self) {
-        let seek = self.elapsed().as_secs_f64() + 5.0;
-        if let Some(duration) = self.duration() {
-            if seek > duration {
-                self.safe_guard = true;
-            } else {
-                self.seek_to(Duration::from_secs_f64(seek));
+        if let Some(sink) = &self.fading_sink {
+            if sink.is_paused() != self.sink.is_paused() {
+                sink.toggle_playback();
             }
         }
     }
-    pub fn seek_bw(&mut self) {
-        let mut seek = self.elapsed().as_secs_f64() - 5.0;
-        if seek < 0.0 {
-            seek = 0.0;
-        }
-
-        self.seek_to(Duration::from_secs_f64(seek));
-    }
-    pub fn seek_to(&self, time: Duration) {
-        self.sink.seek(time);
+    pub fn is_paused(&self) -> bool {
+        self.sink.is_paused()
     }
     pub fn seeker(&self) -> f64 {
         self.duration().map_or(0.0, |duration| {
@@ -129,29 +383,15 @@ impl Player {
             elapsed.as_secs_f64() / duration
         })
     }
-    pub fn trigger_next(&mut self) -> bool {
-        if let Some(duration) = self.duration() {
-            if self.elapsed().as_secs_f64() > duration {
-                self.safe_guard = true;
-            }
-        }
-
-        if self.safe_guard {
-            self.safe_guard = false;
-            true
-        } else {
-            false
-        }
-    }
     pub const fn volume_percent(&self) -> u16 {
         self.volume
     }
 }
 
 impl GeneralP for Player {
-    fn add_and_play(&mut self, song: &str) {
+    fn add_and_play(&mut self, song: &str, gain: f32) {
         let p = Path::new(song);
-        self.play(p);
+        self.play(p, gain);
     }
 
     fn volume(&self) -> i32 {
@@ -176,7 +416,13 @@ impl GeneralP for Player {
             volume = 0;
         }
         self.volume = volume as u16;
-        self.sink.set_volume(f32::from(self.volume) / 100.0);
+        self.apply_fade();
+        self.send(PlayerEvent::VolumeChanged(volume));
+    }
+
+    fn set_fade(&mut self, fade: f32) {
+        self.fade = fade.clamp(0.0, 1.0);
+        self.apply_fade();
     }
 
     fn pause(&mut self) {
@@ -191,13 +437,22 @@ impl GeneralP for Player {
         false
     }
 
+    #[allow(clippy::cast_precision_loss)]
     fn seek(&mut self, secs: i64) -> Result<()> {
-        if secs.is_positive() {
-            self.seek_fw();
-            return Ok(());
-        }
+        let position = (self.elapsed().as_secs_f64() + secs as f64).max(0.0);
+        self.seek_to(Duration::from_secs_f64(position))
+    }
 
-        self.seek_bw();
+    fn seek_to(&mut self, position: Duration) -> Result<()> {
+        // the decoder can't seek past the end, the song is over then
+        if let Some(duration) = self.total_duration {
+            if position >= duration {
+                self.stop();
+                self.send(PlayerEvent::TrackEnded);
+                return Ok(());
+            }
+        }
+        self.sink.seek(position);
         Ok(())
     }
 
@@ -215,4 +470,80 @@ impl GeneralP for Player {
         }
         Ok((percent, position, duration))
     }
+
+    fn enqueue_next(&mut self, new: &str, gain: f32) -> Result<()> {
+        self.play_next(Path::new(new), gain)
+    }
+
+    fn update(&mut self) {
+        self.recover_stream();
+        self.check_next_started();
+        self.check_ended();
+        if self.current.is_some() {
+            let elapsed = self.elapsed();
+            if self.position != Some(elapsed.as_secs()) {
+                self.position = Some(elapsed.as_secs());
+                self.send(PlayerEvent::PositionChanged(elapsed));
+            }
+        }
+    }
+
+    fn set_crossfade(&mut self, crossfade: Duration) {
+        self.crossfade = crossfade;
+    }
+
+    fn set_bit_perfect(&mut self, bit_perfect: bool) {
+        self.bit_perfect = bit_perfect;
+    }
+
+    // Songs already playing keep the one they started with.
+    fn set_resampler(&mut self, resampler: Resampler) {
+        self.resampler = resampler;
+        self.handle.set_resampler(resampler);
+    }
+
+    // The current song carries on from where it was on the new device.
+    fn set_output_device(&mut self, name: Option<&str>) {
+        self.device = name.map(ToString::to_string);
+        self.reopen_stream();
+    }
+
+    fn set_equalizer(&mut self, bands: &[EqualizerBand]) {
+        self.equalizer = bands.to_vec();
+        self.sink.set_equalizer(bands);
+        if let Some(sink) = &self.fading_sink {
+            sink.set_equalizer(bands);
+        }
+    }
+
+    fn set_speed(&mut self, speed: f32) {
+        self.speed = speed.clamp(MIN_SPEED, MAX_SPEED);
+        self.sink.set_speed(self.speed);
+        if let Some(sink) = &self.fading_sink {
+            sink.set_speed(self.speed);
+        }
+    }
+}
+
+// Opens the output device called `name`, else the default one. Without any working device,
+// the handle doesn't lead anywhere.
+//
+// With a `format`, the stream gets opened with exactly those channels and sample rate if the
+// device supports them, otherwise in the format of its choice.
+fn open_stream(
+    name: Option<&str>,
+    format: Option<(u16, u32)>,
+) -> (Option<OutputStream>, OutputStreamHandle) {
+    let native = format.and_then(|(channels, sample_rate)| {
+        OutputStream::try_from_name_with_format(name, channels, sample_rate).ok()
+    });
+    match native.map_or_else(|| OutputStream::try_from_name(name), Ok) {
+        Ok((stream, handle)) => (Some(stream), handle),
+        Err(_) => (None, OutputStreamHandle::default()),
+    }
+}
+
+// A sink on `handle`, or an idle one that plays nothing when the stream is gone.
+fn new_sink(handle: &OutputStreamHandle) -> Sink {
+    Sink::try_new(handle).unwrap_or_else(|_| Sink::new_idle().0)
 }
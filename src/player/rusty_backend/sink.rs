@@ -8,6 +8,7 @@ use std::{
 
 use super::{queue, source::Done, Sample, Source};
 use super::{OutputStreamHandle, PlayError};
+use crate::config::EqualizerBand;
 
 /// Handle to an device that outputs sounds.
 ///
@@ -29,6 +30,9 @@ struct Controls {
     pause: AtomicBool,
     volume: Mutex<f32>,
     seek: Mutex<Option<Duration>>,
+    fade_out: Mutex<Option<Duration>>,
+    equalizer: Mutex<Vec<EqualizerBand>>,
+    speed: Mutex<f32>,
     stopped: AtomicBool,
 }
 
@@ -55,6 +59,9 @@ impl Sink {
                 volume: Mutex::new(1.0),
                 stopped: AtomicBool::new(false),
                 seek: Mutex::new(None),
+                fade_out: Mutex::new(None),
+                equalizer: Mutex::new(Vec::new()),
+                speed: Mutex::new(1.0),
             }),
             sound_count: Arc::new(AtomicUsize::new(0)),
             detached: false,
@@ -70,13 +77,30 @@ impl Sink {
         S: Source + Send + 'static,
         S::Item: Sample + Send,
         // S::Item: Send,
+    {
+        self.append_with_gain(source, 1.0);
+    }
+
+    /// Appends a sound to the queue of sounds to play, with its samples multiplied by `gain`
+    /// on top of the sink volume.
+    #[inline]
+    pub fn append_with_gain<S>(&self, source: S, gain: f32)
+    where
+        S: Source + Send + 'static,
+        S::Item: Sample + Send,
     {
         let controls = self.controls.clone();
 
         let elapsed = self.elapsed.clone();
+        let bands = self.controls.equalizer.lock().unwrap().clone();
+        let speed = *self.controls.speed.lock().unwrap();
         let source = source
+            .amplify(gain)
+            .speed(speed)
+            .equalizer(&bands)
             .pausable(false)
             .amplify(1.0)
+            .fadeable()
             .stoppable()
             .periodic_access(Duration::from_millis(50), move |src| {
                 if controls.stopped.load(Ordering::SeqCst) {
@@ -85,11 +109,28 @@ impl Sink {
                     if let Some(seek_time) = controls.seek.lock().unwrap().take() {
                         src.seek(seek_time).unwrap();
                     }
+                    if let Some(fade_time) = controls.fade_out.lock().unwrap().take() {
+                        src.inner_mut().start(fade_time);
+                    }
                     *elapsed.write().unwrap() = src.elapsed();
-                    src.inner_mut().set_factor(*controls.volume.lock().unwrap());
                     src.inner_mut()
+                        .inner_mut()
+                        .set_factor(*controls.volume.lock().unwrap());
+                    src.inner_mut()
+                        .inner_mut()
                         .inner_mut()
                         .set_paused(controls.pause.load(Ordering::SeqCst));
+                    src.inner_mut()
+                        .inner_mut()
+                        .inner_mut()
+                        .inner_mut()
+                        .set_bands(&controls.equalizer.lock().unwrap());
+                    src.inner_mut()
+                        .inner_mut()
+                        .inner_mut()
+                        .inner_mut()
+                        .inner_mut()
+                        .set_speed(*controls.speed.lock().unwrap());
                 }
             })
             .convert_samples();
@@ -119,6 +160,18 @@ impl Sink {
         *self.controls.volume.lock().unwrap() = value;
     }
 
+    /// Changes the equalizer bands, also for the sounds already in the queue.
+    #[inline]
+    pub fn set_equalizer(&self, bands: &[EqualizerBand]) {
+        *self.controls.equalizer.lock().unwrap() = bands.to_vec();
+    }
+
+    /// Changes how fast the sounds play, also the ones already in the queue. The pitch stays.
+    #[inline]
+    pub fn set_speed(&self, speed: f32) {
+        *self.controls.speed.lock().unwrap() = speed;
+    }
+
     /// Resumes playback of a paused sink.
     ///
     /// No effect if not paused.
@@ -149,6 +202,11 @@ impl Sink {
         *self.controls.seek.lock().unwrap() = Some(seek_time);
     }
 
+    /// Fades the playing sound out to silence over `duration`, after which it ends.
+    pub fn fade_out(&self, duration: Duration) {
+        *self.controls.fade_out.lock().unwrap() = Some(duration);
+    }
+
     /// Gets if a sink is paused
     ///
     /// Sinks can be paused and resumed using `pause()` and `play()`. This returns `true` if the
@@ -0,0 +1,254 @@
+use cpal::Sample as CpalSample;
+use std::f32::consts::PI;
+use std::time::Duration;
+
+use super::{Sample, Source};
+use crate::config::{EqualizerBand, EqualizerBandKind};
+
+/// Internal function that builds a `Equalizer` object.
+pub fn equalizer<I>(input: I, bands: &[EqualizerBand]) -> Equalizer<I>
+where
+    I: Source,
+    I::Item: Sample,
+{
+    let mut equalizer = Equalizer {
+        input,
+        bands: bands.to_vec(),
+        filters: Vec::new(),
+        channel: 0,
+        channels: 0,
+        sample_rate: 0,
+    };
+    equalizer.update_filters();
+    equalizer
+}
+
+/// Filter that runs each channel through a chain of peaking and shelf biquads.
+pub struct Equalizer<I> {
+    input: I,
+    bands: Vec<EqualizerBand>,
+    /// One filter per band and channel, grouped by band.
+    filters: Vec<Biquad>,
+    /// Channel of the next sample.
+    channel: usize,
+    channels: u16,
+    sample_rate: u32,
+}
+
+#[allow(clippy::missing_const_for_fn, unused)]
+impl<I> Equalizer<I>
+where
+    I: Source,
+    I::Item: Sample,
+{
+    /// Changes the bands. Filters only get recomputed when they differ from the current ones.
+    #[inline]
+    pub fn set_bands(&mut self, bands: &[EqualizerBand]) {
+        if self.bands != bands {
+            self.bands = bands.to_vec();
+            self.update_filters();
+        }
+    }
+
+    /// Returns a reference to the inner source.
+    #[inline]
+    pub fn inner(&self) -> &I {
+        &self.input
+    }
+
+    /// Returns a mutable reference to the inner source.
+    #[inline]
+    pub fn inner_mut(&mut self) -> &mut I {
+        &mut self.input
+    }
+
+    /// Returns the inner source.
+    #[inline]
+    pub fn into_inner(self) -> I {
+        self.input
+    }
+
+    // Keeps the filter state when only the gains change, so moving a band doesn't click.
+    fn update_filters(&mut self) {
+        let channels = usize::from(self.input.channels());
+        let sample_rate = self.input.sample_rate();
+        let rebuild = channels != usize::from(self.channels)
+            || sample_rate != self.sample_rate
+            || self.filters.len() != self.bands.len() * channels;
+        self.channels = self.input.channels();
+        self.sample_rate = sample_rate;
+        if rebuild {
+            self.channel = 0;
+            self.filters = self
+                .bands
+                .iter()
+                .flat_map(|band| vec![Biquad::new(band, sample_rate); channels])
+                .collect();
+        } else {
+            for (index, filter) in self.filters.iter_mut().enumerate() {
+                filter.set_band(&self.bands[index / channels], sample_rate);
+            }
+        }
+    }
+
+    fn is_flat(&self) -> bool {
+        self.bands.iter().all(|band| band.gain == 0.0)
+    }
+}
+
+impl<I> Iterator for Equalizer<I>
+where
+    I: Source,
+    I::Item: Sample,
+{
+    type Item = I::Item;
+
+    #[inline]
+    fn next(&mut self) -> Option<I::Item> {
+        // the format can only change between frames
+        if self.channel == 0
+            && (self.input.channels() != self.channels
+                || self.input.sample_rate() != self.sample_rate)
+        {
+            self.update_filters();
+        }
+        let sample = self.input.next()?;
+        let channel = self.channel;
+        self.channel = (self.channel + 1) % usize::from(self.channels.max(1));
+        if self.is_flat() {
+            return Some(sample);
+        }
+
+        let mut value = sample.to_f32();
+        for filter in self
+            .filters
+            .iter_mut()
+            .skip(channel)
+            .step_by(usize::from(self.channels.max(1)))
+        {
+            value = filter.process(value);
+        }
+        Some(CpalSample::from(&value))
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.input.size_hint()
+    }
+}
+
+impl<I> ExactSizeIterator for Equalizer<I>
+where
+    I: Source + ExactSizeIterator,
+    I::Item: Sample,
+{
+}
+
+impl<I> Source for Equalizer<I>
+where
+    I: Source,
+    I::Item: Sample,
+{
+    #[inline]
+    fn current_frame_len(&self) -> Option<usize> {
+        self.input.current_frame_len()
+    }
+
+    #[inline]
+    fn channels(&self) -> u16 {
+        self.input.channels()
+    }
+
+    #[inline]
+    fn sample_rate(&self) -> u32 {
+        self.input.sample_rate()
+    }
+
+    #[inline]
+    fn total_duration(&self) -> Option<Duration> {
+        self.input.total_duration()
+    }
+
+    #[inline]
+    fn elapsed(&mut self) -> Duration {
+        self.input.elapsed()
+    }
+
+    fn seek(&mut self, time: Duration) -> Result<Duration, ()> {
+        self.input.seek(time)
+    }
+}
+
+/// Biquad with the coefficients of the Audio EQ Cookbook, normalized by `a0`.
+#[derive(Clone)]
+struct Biquad {
+    b: [f32; 3],
+    a: [f32; 2],
+    z: [f32; 2],
+}
+
+#[allow(clippy::suboptimal_flops)]
+impl Biquad {
+    fn new(band: &EqualizerBand, sample_rate: u32) -> Self {
+        let mut biquad = Self {
+            b: [1.0, 0.0, 0.0],
+            a: [0.0, 0.0],
+            z: [0.0, 0.0],
+        };
+        biquad.set_band(band, sample_rate);
+        biquad
+    }
+
+    #[allow(clippy::cast_precision_loss)]
+    fn set_band(&mut self, band: &EqualizerBand, sample_rate: u32) {
+        let rate = sample_rate.max(1) as f32;
+        // stay clear of nyquist
+        let freq = band.freq.clamp(10.0, rate * 0.45);
+        let amp = 10_f32.powf(band.gain / 40.0);
+        let w0 = 2.0 * PI * freq / rate;
+        let (sin, cos) = w0.sin_cos();
+        let alpha = sin / (2.0 * band.q.max(0.01));
+        let beta = 2.0 * amp.sqrt() * alpha;
+
+        let (b, a) = match band.kind {
+            EqualizerBandKind::Peaking => (
+                [1.0 + alpha * amp, -2.0 * cos, 1.0 - alpha * amp],
+                [1.0 + alpha / amp, -2.0 * cos, 1.0 - alpha / amp],
+            ),
+            EqualizerBandKind::LowShelf => (
+                [
+                    amp * ((amp + 1.0) - (amp - 1.0) * cos + beta),
+                    2.0 * amp * ((amp - 1.0) - (amp + 1.0) * cos),
+                    amp * ((amp + 1.0) - (amp - 1.0) * cos - beta),
+                ],
+                [
+                    (amp + 1.0) + (amp - 1.0) * cos + beta,
+                    -2.0 * ((amp - 1.0) + (amp + 1.0) * cos),
+                    (amp + 1.0) + (amp - 1.0) * cos - beta,
+                ],
+            ),
+            EqualizerBandKind::HighShelf => (
+                [
+                    amp * ((amp + 1.0) + (amp - 1.0) * cos + beta),
+                    -2.0 * amp * ((amp - 1.0) + (amp + 1.0) * cos),
+                    amp * ((amp + 1.0) + (amp - 1.0) * cos - beta),
+                ],
+                [
+                    (amp + 1.0) - (amp - 1.0) * cos + beta,
+                    2.0 * ((amp - 1.0) - (amp + 1.0) * cos),
+                    (amp + 1.0) - (amp - 1.0) * cos - beta,
+                ],
+            ),
+        };
+        self.b = [b[0] / a[0], b[1] / a[0], b[2] / a[0]];
+        self.a = [a[1] / a[0], a[2] / a[0]];
+    }
+
+    // transposed direct form II
+    fn process(&mut self, x: f32) -> f32 {
+        let y = self.b[0] * x + self.z[0];
+        self.z[0] = self.b[1] * x - self.a[0] * y + self.z[1];
+        self.z[1] = self.b[2] * x - self.a[1] * y;
+        y
+    }
+}
@@ -0,0 +1,116 @@
+use std::time::Duration;
+
+use super::{Sample, Source};
+
+/// Internal function that builds a `FadeOut` object.
+pub const fn fadeout<I>(input: I) -> FadeOut<I> {
+    FadeOut {
+        input,
+        remaining_ns: None,
+        total_ns: 0.0,
+    }
+}
+
+/// Filter that lowers the volume to silence over a time period once started, then ends the
+/// sound.
+#[derive(Clone, Debug)]
+pub struct FadeOut<I> {
+    input: I,
+    remaining_ns: Option<f32>,
+    total_ns: f32,
+}
+
+#[allow(unused, clippy::missing_const_for_fn)]
+impl<I> FadeOut<I> {
+    /// Starts fading out, reaching silence after `duration`.
+    #[inline]
+    #[allow(clippy::cast_precision_loss)]
+    pub fn start(&mut self, duration: Duration) {
+        let duration = duration.as_nanos() as f32;
+        self.remaining_ns = Some(duration);
+        self.total_ns = duration;
+    }
+
+    /// Returns a reference to the inner source.
+    #[inline]
+    pub fn inner(&self) -> &I {
+        &self.input
+    }
+
+    /// Returns a mutable reference to the inner source.
+    #[inline]
+    pub fn inner_mut(&mut self) -> &mut I {
+        &mut self.input
+    }
+
+    /// Returns the inner source.
+    #[inline]
+    pub fn into_inner(self) -> I {
+        self.input
+    }
+}
+
+impl<I> Iterator for FadeOut<I>
+where
+    I: Source,
+    I::Item: Sample,
+{
+    type Item = I::Item;
+
+    #[inline]
+    #[allow(clippy::cast_precision_loss)]
+    fn next(&mut self) -> Option<I::Item> {
+        let remaining_ns = match self.remaining_ns {
+            None => return self.input.next(),
+            Some(remaining_ns) if remaining_ns <= 0.0 => return None,
+            Some(remaining_ns) => remaining_ns,
+        };
+
+        let factor = remaining_ns / self.total_ns;
+        self.remaining_ns = Some(
+            remaining_ns
+                - 1_000_000_000.0 / (self.input.sample_rate() as f32 * f32::from(self.channels())),
+        );
+        self.input.next().map(|value| value.amplify(factor))
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.input.size_hint()
+    }
+}
+
+impl<I> Source for FadeOut<I>
+where
+    I: Source,
+    I::Item: Sample,
+{
+    #[inline]
+    fn current_frame_len(&self) -> Option<usize> {
+        self.input.current_frame_len()
+    }
+
+    #[inline]
+    fn channels(&self) -> u16 {
+        self.input.channels()
+    }
+
+    #[inline]
+    fn sample_rate(&self) -> u32 {
+        self.input.sample_rate()
+    }
+
+    #[inline]
+    fn total_duration(&self) -> Option<Duration> {
+        self.input.total_duration()
+    }
+
+    #[inline]
+    fn elapsed(&mut self) -> Duration {
+        self.input.elapsed()
+    }
+
+    fn seek(&mut self, time: Duration) -> Result<Duration, ()> {
+        self.input.seek(time)
+    }
+}
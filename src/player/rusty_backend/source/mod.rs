@@ -3,14 +3,18 @@
 use std::time::Duration;
 
 use super::Sample;
+use crate::config::EqualizerBand;
 
 pub use self::amplify::Amplify;
 pub use self::done::Done;
 pub use self::empty::Empty;
+pub use self::equalizer::Equalizer;
 pub use self::fadein::FadeIn;
+pub use self::fadeout::FadeOut;
 pub use self::pausable::Pausable;
 pub use self::periodic::PeriodicAccess;
 pub use self::samples_converter::SamplesConverter;
+pub use self::speed::Speed;
 pub use self::stoppable::Stoppable;
 pub use self::take::TakeDuration;
 pub use self::uniform::UniformSourceIterator;
@@ -19,10 +23,13 @@ pub use self::zero::Zero;
 mod amplify;
 mod done;
 mod empty;
+mod equalizer;
 mod fadein;
+mod fadeout;
 mod pausable;
 mod periodic;
 mod samples_converter;
+mod speed;
 mod stoppable;
 mod take;
 mod uniform;
@@ -144,6 +151,24 @@ where
         amplify::amplify(self, value)
     }
 
+    /// Runs the sound through the given equalizer bands.
+    #[inline]
+    fn equalizer(self, bands: &[EqualizerBand]) -> Equalizer<Self>
+    where
+        Self: Sized,
+    {
+        equalizer::equalizer(self, bands)
+    }
+
+    /// Plays the sound `speed` times as fast, keeping its pitch.
+    #[inline]
+    fn speed(self, speed: f32) -> Speed<Self>
+    where
+        Self: Sized,
+    {
+        speed::speed(self, speed)
+    }
+
     /// Fades in the sound.
     #[inline]
     fn fade_in(self, duration: Duration) -> FadeIn<Self>
@@ -153,6 +178,15 @@ where
         fadein::fadein(self, duration)
     }
 
+    /// Makes the sound able to fade out on request. See `FadeOut::start`.
+    #[inline]
+    fn fadeable(self) -> FadeOut<Self>
+    where
+        Self: Sized,
+    {
+        fadeout::fadeout(self)
+    }
+
     /// Calls the `access` closure on `Self` the first time the source is iterated and every
     /// time `period` elapses.
     ///
@@ -0,0 +1,326 @@
+use cpal::Sample as CpalSample;
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use super::{Sample, Source};
+
+/// Length of the pieces the input gets cut into.
+const SEQUENCE: Duration = Duration::from_millis(40);
+/// How long consecutive pieces overlap, they are crossfaded over it.
+const OVERLAP: Duration = Duration::from_millis(10);
+/// How far a piece may move from where it would start, to line up with the previous one.
+const SEEK_WINDOW: Duration = Duration::from_millis(15);
+
+/// Internal function that builds a `Speed` object.
+pub const fn speed<I>(input: I, speed: f32) -> Speed<I>
+where
+    I: Source,
+    I::Item: Sample,
+{
+    Speed {
+        input,
+        rate: speed,
+        buffer: VecDeque::new(),
+        output: VecDeque::new(),
+        overlap: Vec::new(),
+        skip: 0.0,
+    }
+}
+
+/// Filter that plays the input faster or slower while keeping its pitch, by waveform
+/// similarity overlap-add (WSOLA): pieces of the input are laid one after the other, each
+/// moved a little to where it matches the end of the previous one best, and crossfaded.
+///
+/// At a speed of 1.0 the samples pass through untouched.
+pub struct Speed<I> {
+    input: I,
+    rate: f32,
+    /// Input waiting to be stretched, interleaved.
+    buffer: VecDeque<f32>,
+    /// Stretched samples waiting to be played.
+    output: VecDeque<f32>,
+    /// What would have followed the last piece, the next one gets crossfaded with it.
+    overlap: Vec<f32>,
+    /// Part of a frame the input should have advanced by already.
+    skip: f32,
+}
+
+#[allow(clippy::missing_const_for_fn, unused)]
+impl<I> Speed<I>
+where
+    I: Source,
+    I::Item: Sample,
+{
+    /// Changes the speed, 1.0 plays the input as it is.
+    #[inline]
+    pub fn set_speed(&mut self, speed: f32) {
+        self.rate = speed;
+    }
+
+    /// Returns a reference to the inner source.
+    #[inline]
+    pub fn inner(&self) -> &I {
+        &self.input
+    }
+
+    /// Returns a mutable reference to the inner source.
+    #[inline]
+    pub fn inner_mut(&mut self) -> &mut I {
+        &mut self.input
+    }
+
+    /// Returns the inner source.
+    #[inline]
+    pub fn into_inner(self) -> I {
+        self.input
+    }
+
+    fn stretching(&self) -> bool {
+        (self.rate - 1.0).abs() > 0.001
+    }
+
+    #[allow(
+        clippy::cast_possible_truncation,
+        clippy::cast_sign_loss,
+        clippy::cast_precision_loss
+    )]
+    fn frames(&self, duration: Duration) -> usize {
+        ((self.input.sample_rate() as f32 * duration.as_secs_f32()) as usize).max(1)
+    }
+
+    // Reads the input until `samples` are buffered, false when it ends first.
+    fn fill(&mut self, samples: usize) -> bool {
+        while self.buffer.len() < samples {
+            match self.input.next() {
+                Some(sample) => self.buffer.push_back(sample.to_f32()),
+                None => return false,
+            }
+        }
+        true
+    }
+
+    // Drops `samples` of input, reading past the buffer when it holds fewer.
+    fn discard(&mut self, samples: usize) {
+        let buffered = samples.min(self.buffer.len());
+        self.buffer.drain(..buffered);
+        for _ in buffered..samples {
+            if self.input.next().is_none() {
+                break;
+            }
+        }
+    }
+
+    // Where in the seek window the buffer lines up best with the overlap, by normalized
+    // cross-correlation of the channels summed up.
+    #[allow(clippy::suboptimal_flops)]
+    fn best_offset(&self, seek: usize, overlap: usize, channels: usize) -> usize {
+        let mono = |samples: &mut dyn Iterator<Item = &f32>, frames: usize| -> Vec<f32> {
+            (0..frames)
+                .map(|_| samples.take(channels).sum::<f32>())
+                .collect()
+        };
+        let previous = mono(&mut self.overlap.iter(), overlap);
+        let current = mono(&mut self.buffer.iter(), seek + overlap);
+
+        let mut energy: f32 = current[..overlap].iter().map(|x| x * x).sum();
+        let mut best = (0, f32::MIN);
+        for offset in 0..seek {
+            if offset > 0 {
+                let gone = current[offset - 1];
+                let new = current[offset + overlap - 1];
+                energy += new * new - gone * gone;
+            }
+            let correlation: f32 = previous
+                .iter()
+                .zip(&current[offset..])
+                .map(|(a, b)| a * b)
+                .sum();
+            let score = correlation / energy.max(1e-9).sqrt();
+            if score > best.1 {
+                best = (offset, score);
+            }
+        }
+        best.0
+    }
+
+    // Adds the next piece to the output, false when the input ran out for it.
+    #[allow(
+        clippy::cast_possible_truncation,
+        clippy::cast_sign_loss,
+        clippy::cast_precision_loss,
+        clippy::suboptimal_flops
+    )]
+    fn stretch(&mut self) -> bool {
+        let channels = usize::from(self.input.channels().max(1));
+        let sequence = self.frames(SEQUENCE);
+        let overlap = self.frames(OVERLAP).min(sequence / 2);
+        let seek = self.frames(SEEK_WINDOW);
+        if !self.fill((seek + sequence) * channels) {
+            return false;
+        }
+        let offset = if self.overlap.is_empty() {
+            0
+        } else {
+            self.best_offset(seek, overlap, channels)
+        };
+
+        let start = offset * channels;
+        let fade = overlap * channels;
+        let end = (offset + sequence - overlap) * channels;
+        for index in 0..fade {
+            let sample = self.buffer[start + index];
+            let value = self.overlap.get(index).map_or(sample, |previous| {
+                let t = (index / channels) as f32 / overlap as f32;
+                previous * (1.0 - t) + sample * t
+            });
+            self.output.push_back(value);
+        }
+        self.output
+            .extend(self.buffer.range(start + fade..end).copied());
+        self.overlap = self.buffer.range(end..end + fade).copied().collect();
+
+        self.skip += (sequence - overlap) as f32 * self.rate;
+        let frames = self.skip as usize;
+        self.skip -= frames as f32;
+        self.discard(frames * channels);
+        true
+    }
+}
+
+impl<I> Iterator for Speed<I>
+where
+    I: Source,
+    I::Item: Sample,
+{
+    type Item = I::Item;
+
+    #[inline]
+    fn next(&mut self) -> Option<I::Item> {
+        loop {
+            if let Some(value) = self.output.pop_front() {
+                return Some(CpalSample::from(&value));
+            }
+            if !self.stretching() {
+                // what was read ahead for stretching plays first
+                self.overlap.clear();
+                return match self.buffer.pop_front() {
+                    Some(value) => Some(CpalSample::from(&value)),
+                    None => self.input.next(),
+                };
+            }
+            if !self.stretch() {
+                // too little is left for another piece, it plays as it is
+                self.overlap.clear();
+                self.output.extend(self.buffer.drain(..));
+                if self.output.is_empty() {
+                    return None;
+                }
+            }
+        }
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, None)
+    }
+}
+
+impl<I> Source for Speed<I>
+where
+    I: Source,
+    I::Item: Sample,
+{
+    // the input is read ahead, so its frames don't line up with the output
+    #[inline]
+    fn current_frame_len(&self) -> Option<usize> {
+        if self.stretching() || !self.buffer.is_empty() || !self.output.is_empty() {
+            None
+        } else {
+            self.input.current_frame_len()
+        }
+    }
+
+    #[inline]
+    fn channels(&self) -> u16 {
+        self.input.channels()
+    }
+
+    #[inline]
+    fn sample_rate(&self) -> u32 {
+        self.input.sample_rate()
+    }
+
+    #[inline]
+    fn total_duration(&self) -> Option<Duration> {
+        self.input.total_duration()
+    }
+
+    #[inline]
+    fn elapsed(&mut self) -> Duration {
+        self.input.elapsed()
+    }
+
+    fn seek(&mut self, time: Duration) -> Result<Duration, ()> {
+        self.buffer.clear();
+        self.output.clear();
+        self.overlap.clear();
+        self.skip = 0.0;
+        self.input.seek(time)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Source;
+    use crate::player::rusty_backend::buffer::SamplesBuffer;
+    use std::f64::consts::PI;
+
+    const RATE: u32 = 44100;
+
+    #[allow(clippy::cast_precision_loss, clippy::cast_possible_truncation)]
+    fn sine(freq: f64, frames: usize) -> Vec<f32> {
+        (0..frames)
+            .map(|n| (0.5 * (2.0 * PI * freq * n as f64 / f64::from(RATE)).sin()) as f32)
+            .collect()
+    }
+
+    fn stretch(input: Vec<f32>, speed: f32) -> Vec<f32> {
+        SamplesBuffer::new(1, RATE, input).speed(speed).collect()
+    }
+
+    // Upward zero crossings per second, which is the frequency of a sine.
+    #[allow(clippy::cast_precision_loss)]
+    fn frequency(output: &[f32]) -> f64 {
+        let crossings = output
+            .windows(2)
+            .filter(|pair| pair[0] < 0.0 && pair[1] >= 0.0)
+            .count();
+        crossings as f64 * f64::from(RATE) / output.len() as f64
+    }
+
+    #[test]
+    #[allow(clippy::cast_precision_loss)]
+    fn keeps_the_pitch() {
+        let input = sine(440.0, RATE as usize * 10);
+        for speed in [0.5, 0.75, 1.5, 2.0, 3.0] {
+            let output = stretch(input.clone(), speed);
+            let expected = input.len() as f32 / speed;
+            let length = output.len() as f32;
+            assert!(
+                (length - expected).abs() < expected * 0.02,
+                "{} samples at {}x, expected {}",
+                length,
+                speed,
+                expected
+            );
+            let freq = frequency(&output);
+            assert!((freq - 440.0).abs() < 5.0, "{} Hz at {}x", freq, speed);
+        }
+    }
+
+    #[test]
+    fn passes_through_at_normal_speed() {
+        let input = sine(440.0, 10000);
+        assert_eq!(stretch(input.clone(), 1.0), input);
+    }
+}
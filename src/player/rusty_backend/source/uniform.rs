@@ -1,8 +1,9 @@
 use std::cmp;
 use std::time::Duration;
 
-use super::super::conversions::{ChannelCountConverter, DataConverter, SampleRateConverter};
+use super::super::conversions::{ChannelCountConverter, DataConverter, RateConverter};
 use super::{Sample, Source};
+use crate::config::Resampler;
 
 /// An iterator that reads from a `Source` and converts the samples to a specific rate and
 /// channels count.
@@ -17,9 +18,10 @@ where
     I::Item: Sample,
     D: Sample,
 {
-    inner: Option<DataConverter<ChannelCountConverter<SampleRateConverter<Take<I>>>, D>>,
+    inner: Option<DataConverter<ChannelCountConverter<RateConverter<Take<I>>>, D>>,
     target_channels: u16,
     target_sample_rate: u32,
+    resampler: Resampler,
     total_duration: Option<Duration>,
 }
 
@@ -35,14 +37,17 @@ where
         input: I,
         target_channels: u16,
         target_sample_rate: u32,
+        resampler: Resampler,
     ) -> UniformSourceIterator<I, D> {
         let total_duration = input.total_duration();
-        let input = UniformSourceIterator::bootstrap(input, target_channels, target_sample_rate);
+        let input =
+            UniformSourceIterator::bootstrap(input, target_channels, target_sample_rate, resampler);
 
         UniformSourceIterator {
             inner: Some(input),
             target_channels,
             target_sample_rate,
+            resampler,
             total_duration,
         }
     }
@@ -52,7 +57,8 @@ where
         input: I,
         target_channels: u16,
         target_sample_rate: u32,
-    ) -> DataConverter<ChannelCountConverter<SampleRateConverter<Take<I>>>, D> {
+        resampler: Resampler,
+    ) -> DataConverter<ChannelCountConverter<RateConverter<Take<I>>>, D> {
         // Limit the frame length to something reasonable
         let frame_len = input.current_frame_len().map(|x| x.min(32768));
 
@@ -62,12 +68,15 @@ where
         let input = Take {
             iter: input,
             n: frame_len,
+            channels: from_channels,
+            sample_rate: from_sample_rate,
         };
-        let input = SampleRateConverter::new(
+        let input = RateConverter::new(
             input,
             cpal::SampleRate(from_sample_rate),
             cpal::SampleRate(target_sample_rate),
             from_channels,
+            resampler,
         );
         let input = ChannelCountConverter::new(input, from_channels, target_channels);
 
@@ -98,7 +107,12 @@ where
             .into_inner()
             .iter;
 
-        let mut input = Self::bootstrap(input, self.target_channels, self.target_sample_rate);
+        let mut input = Self::bootstrap(
+            input,
+            self.target_channels,
+            self.target_sample_rate,
+            self.resampler,
+        );
 
         let value = input.next();
         self.inner = Some(input);
@@ -150,37 +164,48 @@ where
             .into_inner()
             .iter;
         let ret = input.seek(time);
-        let input = Self::bootstrap(input, self.target_channels, self.target_sample_rate);
+        let input = Self::bootstrap(
+            input,
+            self.target_channels,
+            self.target_sample_rate,
+            self.resampler,
+        );
 
         self.inner = Some(input);
         ret
     }
 }
 
+// Ends where the format of the source changes. Frames of the same format are read on, so the
+// converters keep their state from one frame to the next.
 #[derive(Clone, Debug)]
 struct Take<I> {
     iter: I,
     n: Option<usize>,
+    channels: u16,
+    sample_rate: u32,
 }
 
 impl<I> Iterator for Take<I>
 where
-    I: Iterator,
+    I: Source,
+    I::Item: Sample,
 {
     type Item = <I as Iterator>::Item;
 
     #[inline]
     fn next(&mut self) -> Option<<I as Iterator>::Item> {
-        if let Some(n) = &mut self.n {
-            if *n == 0 {
-                None
-            } else {
-                *n -= 1;
-                self.iter.next()
+        if self.n == Some(0) {
+            if self.iter.channels() != self.channels || self.iter.sample_rate() != self.sample_rate
+            {
+                return None;
             }
-        } else {
-            self.iter.next()
+            self.n = self.iter.current_frame_len().map(|x| x.clamp(1, 32768));
+        }
+        if let Some(n) = &mut self.n {
+            *n -= 1;
         }
+        self.iter.next()
     }
 
     #[inline]
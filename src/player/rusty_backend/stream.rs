@@ -1,5 +1,6 @@
 use std::io::{Read, Seek};
 use std::marker::Sync;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Weak};
 use std::{error, fmt};
 
@@ -7,6 +8,7 @@ use super::decoder;
 use super::dynamic_mixer::{self, DynamicMixerController};
 use super::sink::Sink;
 use super::source::Source;
+use crate::config::Resampler;
 use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
 use cpal::Sample;
 
@@ -16,11 +18,17 @@ use cpal::Sample;
 #[allow(clippy::module_name_repetitions)]
 pub struct OutputStream {
     mixer: Arc<DynamicMixerController<f32>>,
+    channels: u16,
+    sample_rate: u32,
     _stream: cpal::Stream,
+    /// Set by the stream when its device goes away.
+    lost: Arc<AtomicBool>,
 }
 
 /// More flexible handle to a `OutputStream` that provides playback.
-#[derive(Clone)]
+///
+/// The default handle is not attached to any stream.
+#[derive(Clone, Default)]
 pub struct OutputStreamHandle {
     mixer: Weak<DynamicMixerController<f32>>,
 }
@@ -30,11 +38,54 @@ impl OutputStream {
     pub fn try_from_device(
         device: &cpal::Device,
     ) -> Result<(Self, OutputStreamHandle), StreamError> {
-        let (mixer, stream) = device.try_new_output_stream()?;
+        let lost = Arc::new(AtomicBool::new(false));
+        let (mixer, stream) = device.try_new_output_stream(&lost)?;
+        Self::start(mixer, stream, lost)
+    }
+
+    /// Returns a new stream & handle using the given output device, with exactly `channels`
+    /// and `sample_rate`.
+    ///
+    /// Fails with `StreamError::UnsupportedFormat` if the device can't play that format.
+    pub fn try_from_device_with_format(
+        device: &cpal::Device,
+        channels: u16,
+        sample_rate: u32,
+    ) -> Result<(Self, OutputStreamHandle), StreamError> {
+        let rate = cpal::SampleRate(sample_rate);
+        let mut supported: Vec<_> = device
+            .supported_output_configs()?
+            .filter(|c| {
+                c.channels() == channels
+                    && c.min_sample_rate() <= rate
+                    && rate <= c.max_sample_rate()
+            })
+            .collect();
+        supported.sort_by(|a, b| b.cmp_default_heuristics(a));
+        let lost = Arc::new(AtomicBool::new(false));
+        let (mixer, stream) = supported
+            .into_iter()
+            .find_map(|c| {
+                device
+                    .new_output_stream_with_format(c.with_sample_rate(rate), &lost)
+                    .ok()
+            })
+            .ok_or(StreamError::UnsupportedFormat)?;
+        Self::start(mixer, stream, lost)
+    }
+
+    fn start(
+        mixer: Arc<DynamicMixerController<f32>>,
+        stream: cpal::Stream,
+        lost: Arc<AtomicBool>,
+    ) -> Result<(Self, OutputStreamHandle), StreamError> {
         stream.play()?;
         let out = Self {
+            channels: mixer.channels(),
+            sample_rate: mixer.sample_rate(),
             mixer,
             _stream: stream,
+            lost,
         };
         let handle = OutputStreamHandle {
             mixer: Arc::downgrade(&out.mixer),
@@ -64,10 +115,71 @@ impl OutputStream {
                 .ok_or(original_err)
         })
     }
+
+    /// Returns a new stream & handle using the output device called `name`.
+    ///
+    /// Falls back to `try_default` when there is no such device, or it doesn't work.
+    pub fn try_from_name(name: Option<&str>) -> Result<(Self, OutputStreamHandle), StreamError> {
+        let device = name.and_then(|name| {
+            cpal::default_host()
+                .output_devices()
+                .ok()?
+                .find(|d| d.name().ok().as_deref() == Some(name))
+        });
+        device
+            .and_then(|d| Self::try_from_device(&d).ok())
+            .map_or_else(Self::try_default, Ok)
+    }
+
+    /// Returns a new stream & handle using the output device called `name` (the default one for
+    /// `None`), with exactly `channels` and `sample_rate`.
+    pub fn try_from_name_with_format(
+        name: Option<&str>,
+        channels: u16,
+        sample_rate: u32,
+    ) -> Result<(Self, OutputStreamHandle), StreamError> {
+        let host = cpal::default_host();
+        let device = name
+            .map_or_else(
+                || host.default_output_device(),
+                |name| {
+                    host.output_devices()
+                        .ok()?
+                        .find(|d| d.name().ok().as_deref() == Some(name))
+                },
+            )
+            .ok_or(StreamError::NoDevice)?;
+        Self::try_from_device_with_format(&device, channels, sample_rate)
+    }
+
+    /// Channel count and sample rate everything played on the stream gets converted to.
+    pub const fn format(&self) -> (u16, u32) {
+        (self.channels, self.sample_rate)
+    }
+
+    /// Whether the device of the stream has disappeared, so nothing plays anymore.
+    pub fn is_lost(&self) -> bool {
+        self.lost.load(Ordering::Relaxed)
+    }
+}
+
+/// Names of the output devices of the default host.
+pub fn output_device_names() -> Vec<String> {
+    cpal::default_host()
+        .output_devices()
+        .map(|devices| devices.filter_map(|d| d.name().ok()).collect())
+        .unwrap_or_default()
 }
 
 #[allow(unused)]
 impl OutputStreamHandle {
+    /// Changes how the sounds played from now on get converted to the sample rate of the stream.
+    pub fn set_resampler(&self, resampler: Resampler) {
+        if let Some(mixer) = self.mixer.upgrade() {
+            mixer.set_resampler(resampler);
+        }
+    }
+
     /// Plays a source with a device until it ends.
     pub fn play_raw<S>(&self, source: S) -> Result<(), PlayError>
     where
@@ -131,6 +243,7 @@ pub enum StreamError {
     BuildStreamError(cpal::BuildStreamError),
     SupportedStreamConfigsError(cpal::SupportedStreamConfigsError),
     NoDevice,
+    UnsupportedFormat,
 }
 
 impl From<cpal::DefaultStreamConfigError> for StreamError {
@@ -165,6 +278,7 @@ impl fmt::Display for StreamError {
             Self::DefaultStreamConfigError(e) => e.fmt(f),
             Self::SupportedStreamConfigsError(e) => e.fmt(f),
             Self::NoDevice => write!(f, "NoDevice"),
+            Self::UnsupportedFormat => write!(f, "UnsupportedFormat"),
         }
     }
 }
@@ -176,7 +290,7 @@ impl error::Error for StreamError {
             Self::BuildStreamError(e) => Some(e),
             Self::DefaultStreamConfigError(e) => Some(e),
             Self::SupportedStreamConfigsError(e) => Some(e),
-            Self::NoDevice => None,
+            Self::NoDevice | Self::UnsupportedFormat => None,
         }
     }
 }
@@ -186,10 +300,12 @@ pub trait CpalDeviceExt {
     fn new_output_stream_with_format(
         &self,
         format: cpal::SupportedStreamConfig,
+        lost: &Arc<AtomicBool>,
     ) -> Result<(Arc<DynamicMixerController<f32>>, cpal::Stream), cpal::BuildStreamError>;
 
     fn try_new_output_stream(
         &self,
+        lost: &Arc<AtomicBool>,
     ) -> Result<(Arc<DynamicMixerController<f32>>, cpal::Stream), StreamError>;
 }
 
@@ -197,11 +313,18 @@ impl CpalDeviceExt for cpal::Device {
     fn new_output_stream_with_format(
         &self,
         format: cpal::SupportedStreamConfig,
+        lost: &Arc<AtomicBool>,
     ) -> Result<(Arc<DynamicMixerController<f32>>, cpal::Stream), cpal::BuildStreamError> {
         let (mixer_tx, mut mixer_rx) =
             dynamic_mixer::mixer::<f32>(format.channels(), format.sample_rate().0);
 
-        let error_callback = |err| eprintln!("an error occurred on output stream: {}", err);
+        let lost = Arc::clone(lost);
+        let error_callback = move |err| match err {
+            cpal::StreamError::DeviceNotAvailable => lost.store(true, Ordering::Relaxed),
+            cpal::StreamError::BackendSpecific { .. } => {
+                eprintln!("an error occurred on output stream: {}", err);
+            }
+        };
 
         match format.sample_format() {
             cpal::SampleFormat::F32 => self.build_output_stream::<f32, _, _>(
@@ -235,15 +358,16 @@ impl CpalDeviceExt for cpal::Device {
 
     fn try_new_output_stream(
         &self,
+        lost: &Arc<AtomicBool>,
     ) -> Result<(Arc<DynamicMixerController<f32>>, cpal::Stream), StreamError> {
         // Determine the format to use for the new stream.
         let default_format = self.default_output_config()?;
 
-        self.new_output_stream_with_format(default_format)
+        self.new_output_stream_with_format(default_format, lost)
             .or_else(|err| {
                 // look through all supported formats to see if another works
                 supported_output_formats(self)?
-                    .find_map(|format| self.new_output_stream_with_format(format).ok())
+                    .find_map(|format| self.new_output_stream_with_format(format, lost).ok())
                     // return original error if nothing works
                     .ok_or(StreamError::BuildStreamError(err))
             })
@@ -0,0 +1,672 @@
+//! Search queries over the tags of songs, like
+//! `artist:radiohead year:>=2000 -genre:live duration:<5m "exact phrase"`.
+//!
+//! Terms next to each other must all match, and `OR` (or `|`) between two lets either of
+//! them match. `-` or `NOT` in front of a term or a group in parentheses turns it around.
+//! `AND` (or `&`) may be written out too.
+//!
+//! A `field:value` term looks at one tag: `artist`, `album`, `title`, `albumartist`, `genre`
+//! and `file` hold text, `year`, `track`, `disc`, `duration`, `rating` (0 to 5 stars),
+//! `plays` and `added` (days ago) numbers. A bare word looks at the artist, title, album and
+//! file name.
+//!
+//! Words match anywhere in the text regardless of case, with `*` and `?` as wildcards. Bare
+//! words that aren't negated also match with a letter wrong in words of four letters and two
+//! in words of eight, and CJK text by its pinyin or the initials of it. A quoted phrase
+//! matches only as written, regardless of case.
+//!
+//! Numbers compare with `=`, `<`, `<=`, `>` and `>=`, or fall in a range like `1990..1999`.
+//! Durations are in seconds, or like `5m`, `3m30s`, `1h` or `3:30`.
+use crate::library_db::Track;
+use crate::song::Song;
+use anyhow::{bail, Result};
+use pinyin::ToPinyin;
+use std::iter::Peekable;
+use std::path::Path;
+use std::str::Chars;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use wildmatch::WildMatch;
+
+/// The tags a query looks at, of a library track or a playlist song.
+pub struct Fields<'a> {
+    pub file: &'a str,
+    pub artist: Option<&'a str>,
+    pub album: Option<&'a str>,
+    pub title: Option<&'a str>,
+    pub album_artist: Option<&'a str>,
+    pub genre: Option<&'a str>,
+    pub duration: Duration,
+    pub track: Option<u32>,
+    pub disc: Option<u32>,
+    pub year: Option<i32>,
+    pub rating: Option<u8>,
+    /// When the song came to the library, in seconds since the epoch.
+    pub added: Option<u64>,
+    pub plays: Option<u32>,
+}
+
+impl<'a> From<&'a Track> for Fields<'a> {
+    fn from(track: &'a Track) -> Self {
+        Self {
+            file: &track.file,
+            artist: track.artist.as_deref(),
+            album: track.album.as_deref(),
+            title: track.title.as_deref(),
+            album_artist: track.album_artist.as_deref(),
+            genre: track.genre.as_deref(),
+            duration: track.duration,
+            track: track.number,
+            disc: track.disc,
+            year: track.year,
+            rating: track.rating,
+            added: Some(track.added),
+            plays: Some(track.plays),
+        }
+    }
+}
+
+impl<'a> From<&'a Song> for Fields<'a> {
+    fn from(song: &'a Song) -> Self {
+        Self {
+            file: song.file().unwrap_or_default(),
+            artist: song.artist(),
+            album: song.album(),
+            title: song.title(),
+            album_artist: song.album_artist(),
+            genre: song.genre(),
+            duration: song.duration(),
+            track: song.track(),
+            disc: song.disc(),
+            year: song.year(),
+            rating: song.rating(),
+            added: None,
+            plays: None,
+        }
+    }
+}
+
+impl<'a> Fields<'a> {
+    /// A folder of the library, which only bare words find, by its name.
+    pub const fn folder(path: &'a str) -> Self {
+        Self {
+            file: path,
+            artist: None,
+            album: None,
+            title: None,
+            album_artist: None,
+            genre: None,
+            duration: Duration::ZERO,
+            track: None,
+            disc: None,
+            year: None,
+            rating: None,
+            added: None,
+            plays: None,
+        }
+    }
+
+    const fn text(&self, field: TextField) -> Option<&str> {
+        match field {
+            TextField::Artist => self.artist,
+            TextField::Album => self.album,
+            TextField::Title => self.title,
+            TextField::AlbumArtist => self.album_artist,
+            TextField::Genre => self.genre,
+            TextField::File => Some(self.file),
+        }
+    }
+
+    fn number(&self, field: NumberField) -> Option<i64> {
+        match field {
+            NumberField::Year => self.year.map(i64::from),
+            NumberField::Track => self.track.map(i64::from),
+            NumberField::Disc => self.disc.map(i64::from),
+            NumberField::Duration => i64::try_from(self.duration.as_secs()).ok(),
+            NumberField::Rating => self.rating.map(i64::from),
+            NumberField::Plays => self.plays.map(i64::from),
+            NumberField::Added => self.added.and_then(|added| {
+                let now = SystemTime::now().duration_since(UNIX_EPOCH).ok()?.as_secs();
+                i64::try_from(now.saturating_sub(added) / 86400).ok()
+            }),
+        }
+    }
+
+    /// What bare words look at.
+    fn any_text(&self) -> impl Iterator<Item = &str> {
+        let file_name = Path::new(self.file)
+            .file_name()
+            .and_then(std::ffi::OsStr::to_str);
+        [self.artist, self.title, self.album, file_name]
+            .into_iter()
+            .flatten()
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum TextField {
+    Artist,
+    Album,
+    Title,
+    AlbumArtist,
+    Genre,
+    File,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum NumberField {
+    Year,
+    Track,
+    Disc,
+    /// In seconds.
+    Duration,
+    Rating,
+    Plays,
+    /// Days ago.
+    Added,
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Field {
+    Text(TextField),
+    Number(NumberField),
+}
+
+impl Field {
+    fn from_name(name: &str) -> Option<Self> {
+        Some(match name.to_lowercase().as_str() {
+            "artist" => Self::Text(TextField::Artist),
+            "album" => Self::Text(TextField::Album),
+            "title" => Self::Text(TextField::Title),
+            "albumartist" | "album_artist" => Self::Text(TextField::AlbumArtist),
+            "genre" => Self::Text(TextField::Genre),
+            "file" | "path" => Self::Text(TextField::File),
+            "year" => Self::Number(NumberField::Year),
+            "track" => Self::Number(NumberField::Track),
+            "disc" => Self::Number(NumberField::Disc),
+            "duration" | "length" => Self::Number(NumberField::Duration),
+            "rating" => Self::Number(NumberField::Rating),
+            "plays" | "playcount" => Self::Number(NumberField::Plays),
+            "added" => Self::Number(NumberField::Added),
+            _ => return None,
+        })
+    }
+}
+
+/// Text to look for.
+#[derive(Debug)]
+pub struct Text {
+    /// In lower case.
+    pattern: String,
+    /// Whether a near miss or the pinyin matches too.
+    fuzzy: bool,
+    wildcard: Option<WildMatch>,
+}
+
+impl Text {
+    /// A `quoted` pattern has no wildcards and is never `fuzzy`.
+    fn new(pattern: &str, quoted: bool, fuzzy: bool) -> Self {
+        let pattern = pattern.to_lowercase();
+        let wildcard = (!quoted && pattern.contains(['*', '?']))
+            .then(|| WildMatch::new(&format!("*{pattern}*")));
+        Self {
+            pattern,
+            fuzzy: fuzzy && !quoted,
+            wildcard,
+        }
+    }
+
+    fn matches(&self, text: &str) -> bool {
+        let lower = text.to_lowercase();
+        if let Some(wildcard) = &self.wildcard {
+            return wildcard.matches(&lower);
+        }
+        lower.contains(&self.pattern)
+            || self.fuzzy
+                && (is_near(&self.pattern, &lower) || pinyin_contains(text, &self.pattern))
+    }
+}
+
+/// Whether a word of `text` is `word` with a letter or two wrong, or starts like that.
+fn is_near(word: &str, text: &str) -> bool {
+    let word: Vec<char> = word.chars().collect();
+    let allowed = match word.len() {
+        0..=3 => return false,
+        4..=7 => 1,
+        _ => 2,
+    };
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|token| !token.is_empty())
+        .any(|token| {
+            let token: Vec<char> = token.chars().collect();
+            let start = &token[..token.len().min(word.len())];
+            edit_distance(&word, &token) <= allowed || edit_distance(&word, start) <= allowed
+        })
+}
+
+/// How many letters to insert, delete or replace to get from `a` to `b`.
+fn edit_distance(a: &[char], b: &[char]) -> usize {
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.iter().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let above = row[j + 1];
+            row[j + 1] = if ca == cb {
+                diagonal
+            } else {
+                1 + diagonal.min(above).min(row[j])
+            };
+            diagonal = above;
+        }
+    }
+    row[b.len()]
+}
+
+/// Whether the pinyin of the CJK characters in `text`, or their initials, contain `pattern`.
+fn pinyin_contains(text: &str, pattern: &str) -> bool {
+    if !text.chars().any(|c| c.to_pinyin().is_some()) {
+        return false;
+    }
+    let mut full = String::new();
+    let mut initials = String::new();
+    for c in text.chars().filter(|c| !c.is_whitespace()) {
+        if let Some(pinyin) = c.to_pinyin() {
+            full.push_str(pinyin.plain());
+            initials.push_str(pinyin.first_letter());
+        } else {
+            full.extend(c.to_lowercase());
+            initials.extend(c.to_lowercase());
+        }
+    }
+    full.contains(pattern) || initials.contains(pattern)
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Compare {
+    Eq(i64),
+    Lt(i64),
+    Le(i64),
+    Gt(i64),
+    Ge(i64),
+    /// Both ends included, open without them.
+    Range(Option<i64>, Option<i64>),
+}
+
+impl Compare {
+    fn parse(value: &str, field: NumberField) -> Option<Self> {
+        let number = |text: &str| match field {
+            NumberField::Duration => seconds(text),
+            _ => text.parse().ok(),
+        };
+        let optional = |text: &str| {
+            if text.is_empty() {
+                Some(None)
+            } else {
+                number(text).map(Some)
+            }
+        };
+        Some(if let Some(rest) = value.strip_prefix("<=") {
+            Self::Le(number(rest)?)
+        } else if let Some(rest) = value.strip_prefix(">=") {
+            Self::Ge(number(rest)?)
+        } else if let Some(rest) = value.strip_prefix('<') {
+            Self::Lt(number(rest)?)
+        } else if let Some(rest) = value.strip_prefix('>') {
+            Self::Gt(number(rest)?)
+        } else if let Some((from, to)) = value.split_once("..") {
+            Self::Range(optional(from)?, optional(to)?)
+        } else {
+            Self::Eq(number(value.strip_prefix('=').unwrap_or(value))?)
+        })
+    }
+
+    fn matches(&self, number: i64) -> bool {
+        match *self {
+            Self::Eq(value) => number == value,
+            Self::Lt(value) => number < value,
+            Self::Le(value) => number <= value,
+            Self::Gt(value) => number > value,
+            Self::Ge(value) => number >= value,
+            Self::Range(from, to) => {
+                from.is_none_or(|from| number >= from) && to.is_none_or(|to| number <= to)
+            }
+        }
+    }
+}
+
+/// Seconds of a duration like `200`, `5m`, `3m30s`, `1h` or `3:30`.
+fn seconds(text: &str) -> Option<i64> {
+    if text.contains(':') {
+        return text.split(':').try_fold(0_i64, |total, part| {
+            total.checked_mul(60)?.checked_add(part.parse().ok()?)
+        });
+    }
+    let mut total: i64 = 0;
+    let mut number = String::new();
+    for c in text.chars() {
+        let unit = match c {
+            '0'..='9' => {
+                number.push(c);
+                continue;
+            }
+            'h' => 3600,
+            'm' => 60,
+            's' => 1,
+            _ => return None,
+        };
+        let part = number.parse::<i64>().ok()?.checked_mul(unit)?;
+        total = total.checked_add(part)?;
+        number.clear();
+    }
+    if !number.is_empty() {
+        total = total.checked_add(number.parse().ok()?)?;
+    }
+    (!text.is_empty()).then_some(total)
+}
+
+#[derive(Debug)]
+enum Token {
+    Open,
+    Close,
+    And,
+    Or,
+    Not,
+    Term {
+        field: Option<(Field, String)>,
+        value: String,
+        quoted: bool,
+    },
+}
+
+fn tokenize(input: &str) -> Vec<Token> {
+    let mut tokens = vec![];
+    let mut chars = input.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '(' => tokens.push(Token::Open),
+            ')' => tokens.push(Token::Close),
+            '-' => tokens.push(Token::Not),
+            '"' => tokens.push(Token::Term {
+                field: None,
+                value: quoted(&mut chars),
+                quoted: true,
+            }),
+            c if c.is_whitespace() => {}
+            c => {
+                let mut word = c.to_string();
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() || matches!(c, '(' | ')' | '"') {
+                        break;
+                    }
+                    word.push(c);
+                    chars.next();
+                }
+                tokens.push(word_token(word, &mut chars));
+            }
+        }
+    }
+    tokens
+}
+
+/// The rest of a phrase after its opening quote. A phrase still being typed ends with the
+/// input.
+fn quoted(chars: &mut Peekable<Chars>) -> String {
+    chars.by_ref().take_while(|&c| c != '"').collect()
+}
+
+fn word_token(word: String, chars: &mut Peekable<Chars>) -> Token {
+    match word.as_str() {
+        "AND" | "&" => return Token::And,
+        "OR" | "|" => return Token::Or,
+        "NOT" => return Token::Not,
+        _ => {}
+    }
+    if let Some((name, value)) = word.split_once(':') {
+        if let Some(field) = Field::from_name(name) {
+            let field = Some((field, name.to_string()));
+            if value.is_empty() && chars.next_if_eq(&'"').is_some() {
+                return Token::Term {
+                    field,
+                    value: quoted(chars),
+                    quoted: true,
+                };
+            }
+            return Token::Term {
+                field,
+                value: value.to_string(),
+                quoted: false,
+            };
+        }
+    }
+    Token::Term {
+        field: None,
+        value: word,
+        quoted: false,
+    }
+}
+
+/// A parsed search query.
+#[derive(Debug)]
+pub enum Query {
+    Text(Option<TextField>, Text),
+    Number(NumberField, Compare),
+    Not(Box<Self>),
+    /// Matches everything when empty.
+    And(Vec<Self>),
+    Or(Vec<Self>),
+}
+
+impl Query {
+    pub fn parse(input: &str) -> Result<Self> {
+        let mut tokens = tokenize(input).into_iter().peekable();
+        let query = Self::parse_or(&mut tokens)?;
+        if tokens.next().is_some() {
+            bail!("unmatched )");
+        }
+        Ok(query)
+    }
+
+    pub fn matches(&self, fields: &Fields) -> bool {
+        match self {
+            Self::Text(Some(field), text) => fields.text(*field).is_some_and(|t| text.matches(t)),
+            Self::Text(None, text) => fields.any_text().any(|t| text.matches(t)),
+            Self::Number(field, compare) => {
+                fields.number(*field).is_some_and(|n| compare.matches(n))
+            }
+            Self::Not(query) => !query.matches(fields),
+            Self::And(all) => all.iter().all(|query| query.matches(fields)),
+            Self::Or(any) => any.iter().any(|query| query.matches(fields)),
+        }
+    }
+
+    const fn is_empty(&self) -> bool {
+        matches!(self, Self::And(all) if all.is_empty())
+    }
+
+    fn parse_or(tokens: &mut Peekable<impl Iterator<Item = Token>>) -> Result<Self> {
+        let mut any = vec![Self::parse_and(tokens)?];
+        while tokens.next_if(|token| matches!(token, Token::Or)).is_some() {
+            any.push(Self::parse_and(tokens)?);
+        }
+        // "a OR" while the rest is still being typed
+        any.retain(|query| !query.is_empty());
+        Ok(match any.len() {
+            0 => Self::And(vec![]),
+            1 => any.remove(0),
+            _ => Self::Or(any),
+        })
+    }
+
+    fn parse_and(tokens: &mut Peekable<impl Iterator<Item = Token>>) -> Result<Self> {
+        let mut all = vec![];
+        loop {
+            match tokens.peek() {
+                None | Some(Token::Or | Token::Close) => break,
+                Some(Token::And) => {
+                    tokens.next();
+                }
+                Some(_) => all.push(Self::parse_unary(tokens)?),
+            }
+        }
+        all.retain(|query| !query.is_empty());
+        Ok(if all.len() == 1 {
+            all.remove(0)
+        } else {
+            Self::And(all)
+        })
+    }
+
+    fn parse_unary(tokens: &mut Peekable<impl Iterator<Item = Token>>) -> Result<Self> {
+        match tokens.next() {
+            Some(Token::Not) => {
+                let query = match tokens.peek() {
+                    None | Some(Token::Or | Token::Close) => return Ok(Self::And(vec![])),
+                    Some(_) => Self::parse_unary(tokens)?,
+                };
+                Ok(if query.is_empty() {
+                    query
+                } else {
+                    Self::Not(Box::new(query.strict()))
+                })
+            }
+            Some(Token::Open) => {
+                let query = Self::parse_or(tokens)?;
+                // a group still being typed ends with the input
+                tokens.next_if(|token| matches!(token, Token::Close));
+                Ok(query)
+            }
+            Some(Token::Term {
+                field,
+                value,
+                quoted,
+            }) => Self::term(field, &value, quoted),
+            _ => Ok(Self::And(vec![])),
+        }
+    }
+
+    /// The query without near misses, for a negation: `-live` shouldn't drop Love.
+    fn strict(self) -> Self {
+        match self {
+            Self::Text(field, text) => Self::Text(
+                field,
+                Text {
+                    fuzzy: false,
+                    ..text
+                },
+            ),
+            Self::Not(query) => Self::Not(Box::new(query.strict())),
+            Self::And(all) => Self::And(all.into_iter().map(Self::strict).collect()),
+            Self::Or(any) => Self::Or(any.into_iter().map(Self::strict).collect()),
+            Self::Number(..) => self,
+        }
+    }
+
+    fn term(field: Option<(Field, String)>, value: &str, quoted: bool) -> Result<Self> {
+        if value.is_empty() {
+            return Ok(Self::And(vec![]));
+        }
+        Ok(match field {
+            None => Self::Text(None, Text::new(value, quoted, true)),
+            Some((Field::Text(field), _)) => {
+                Self::Text(Some(field), Text::new(value, quoted, false))
+            }
+            Some((Field::Number(field), name)) => match Compare::parse(value, field) {
+                Some(compare) => Self::Number(field, compare),
+                None => bail!("can't compare {name} with {value}"),
+            },
+        })
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::non_ascii_literal)]
+mod tests {
+    use super::*;
+
+    fn fields<'a>(artist: &'a str, title: &'a str, year: i32, secs: u64) -> Fields<'a> {
+        Fields {
+            file: "/music/song.mp3",
+            artist: Some(artist),
+            album: None,
+            title: Some(title),
+            album_artist: None,
+            genre: Some("Rock"),
+            duration: Duration::from_secs(secs),
+            track: Some(1),
+            disc: None,
+            year: Some(year),
+            rating: Some(4),
+            added: None,
+            plays: Some(0),
+        }
+    }
+
+    fn matches(input: &str, fields: &Fields) -> bool {
+        Query::parse(input).unwrap().matches(fields)
+    }
+
+    #[test]
+    fn test_fields_and_operators() {
+        let song = fields("Radiohead", "Karma Police", 1997, 264);
+        assert!(matches("", &song));
+        assert!(matches("artist:radiohead karma", &song));
+        assert!(!matches("artist:karma", &song));
+        assert!(matches("year:1990..1999 duration:<5m", &song));
+        assert!(!matches("year:>=2000", &song));
+        assert!(matches("year:>=2000 OR genre:rock", &song));
+        assert!(!matches("-genre:rock", &song));
+        assert!(matches("NOT (genre:jazz | year:<1990)", &song));
+        assert!(matches("duration:4:24 track:=1", &song));
+        assert!(matches("plays:0 rating:>=4", &song));
+        assert!(!matches("added:<=30", &song));
+        assert!(Query::parse("year:soon").is_err());
+        assert!(Query::parse("a)").is_err());
+    }
+
+    #[test]
+    fn test_text_matching() {
+        let song = fields("Radiohead", "Karma Police", 1997, 264);
+        assert!(matches("radiohaed", &song));
+        assert!(matches("kar*lice", &song));
+        assert!(matches("\"karma police\"", &song));
+        assert!(!matches("\"karma polise\"", &song));
+        assert!(matches("title:\"karma police\"", &song));
+
+        let song = fields("周杰伦", "晴天", 2003, 269);
+        assert!(matches("zjl", &song));
+        assert!(matches("qingtian", &song));
+        assert!(matches("artist:杰伦", &song));
+        // only bare words match by pinyin
+        assert!(!matches("artist:zjl", &song));
+    }
+
+    #[test]
+    fn test_fuzzy_only_for_bare_words() {
+        let mut song = fields("Radiohead", "Karma Police", 1997, 264);
+        song.genre = Some("Jive");
+        assert!(matches("radiohaed", &song));
+        assert!(!matches("artist:radiohaed", &song));
+        assert!(matches("artist:radio*", &song));
+        // a negation doesn't drop near misses
+        assert!(matches("-genre:live", &song));
+        assert!(matches("-polise", &song));
+        assert!(!matches("-police", &song));
+        assert!(matches("NOT (polise OR genre:live)", &song));
+        song.genre = Some("Live");
+        assert!(!matches("-genre:live", &song));
+    }
+
+    #[test]
+    fn test_seconds() {
+        assert_eq!(seconds("200"), Some(200));
+        assert_eq!(seconds("3m30s"), Some(210));
+        assert_eq!(seconds("1h"), Some(3600));
+        assert_eq!(seconds("1:02:03"), Some(3723));
+        assert_eq!(seconds("soon"), None);
+        assert_eq!(seconds(""), None);
+        assert_eq!(seconds("99999999999999999999"), None);
+        assert_eq!(seconds("9999999999999999h"), None);
+        assert_eq!(seconds("9223372036854775807s1s"), None);
+        assert_eq!(seconds("9999999999999999:00:00"), None);
+    }
+}
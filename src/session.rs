@@ -0,0 +1,173 @@
+//! What was playing when termusic last stopped, so the next start picks up from there.
+//!
+//! The playlist itself stays in `playlist.log`, the session only points into it.
+use crate::config::get_app_config_path;
+use crate::ui::Loop;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// How often the session is saved while playing, besides on quit.
+pub const SAVE_INTERVAL: Duration = Duration::from_secs(30);
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Session {
+    pub file: Option<String>,
+    /// Seconds into `file`.
+    pub position: u64,
+    /// Where `file` is in the playlist, `None` when it isn't in it.
+    pub index: Option<usize>,
+    pub loop_mode: Loop,
+    pub paused: bool,
+}
+
+impl Session {
+    /// The session of `file`, with `playlist` as it is while `file` plays.
+    pub fn new<'a>(
+        file: Option<&str>,
+        position: Duration,
+        paused: bool,
+        loop_mode: &Loop,
+        playlist: impl DoubleEndedIterator<Item = Option<&'a str>> + ExactSizeIterator,
+    ) -> Self {
+        let mut playlist = playlist;
+        // the playing song went to the back of the playlist, or stayed at the front
+        let index = file.and_then(|file| match loop_mode {
+            Loop::Playlist => playlist.rposition(|f| f == Some(file)),
+            Loop::Single => playlist.position(|f| f == Some(file)),
+            Loop::Queue => None,
+        });
+        Self {
+            file: file.map(ToString::to_string),
+            position: position.as_secs(),
+            index,
+            loop_mode: loop_mode.clone(),
+            paused,
+        }
+    }
+
+    fn path() -> Result<PathBuf> {
+        let mut path = get_app_config_path()?;
+        path.push("session.toml");
+        Ok(path)
+    }
+
+    /// The session saved last, unless its song is gone.
+    pub fn load() -> Option<Self> {
+        let session: Self = toml::from_str(&fs::read_to_string(Self::path().ok()?).ok()?).ok()?;
+        if let Some(file) = &session.file {
+            if !Path::new(file).exists() {
+                return None;
+            }
+        }
+        Some(session)
+    }
+
+    pub fn save(&self) -> Result<()> {
+        fs::write(Self::path()?, toml::to_string(self)?)?;
+        Ok(())
+    }
+
+    /// Turns `playlist` so the song of the session is where its loop mode keeps the playing
+    /// song, in case the playlist was saved at another point than the session.
+    pub fn align<T>(&self, playlist: &mut VecDeque<T>, file_of: impl Fn(&T) -> Option<&str>) {
+        let file = match &self.file {
+            Some(file) => Some(file.as_str()),
+            None => return,
+        };
+        let index = match self.index {
+            Some(index) if playlist.get(index).and_then(&file_of) == file => index,
+            _ => match playlist.iter().position(|song| file_of(song) == file) {
+                Some(index) => index,
+                None => return,
+            },
+        };
+        match self.loop_mode {
+            Loop::Playlist => playlist.rotate_left(index + 1),
+            Loop::Single => playlist.rotate_left(index),
+            Loop::Queue => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn session(file: Option<&str>, index: Option<usize>, loop_mode: Loop) -> Session {
+        Session {
+            file: file.map(ToString::to_string),
+            position: 42,
+            index,
+            loop_mode,
+            paused: false,
+        }
+    }
+
+    fn aligned(session: &Session, playlist: &[&'static str]) -> Vec<&'static str> {
+        let mut playlist: VecDeque<&str> = playlist.iter().copied().collect();
+        session.align(&mut playlist, |file| Some(*file));
+        playlist.into_iter().collect()
+    }
+
+    #[test]
+    fn test_align() {
+        let playlist = ["a", "b", "c", "d"];
+        // the playing song goes to the back in playlist mode, stays at the front in single
+        let looping = session(Some("c"), Some(2), Loop::Playlist);
+        assert_eq!(aligned(&looping, &playlist), ["d", "a", "b", "c"]);
+        let single = session(Some("c"), Some(2), Loop::Single);
+        assert_eq!(aligned(&single, &playlist), ["c", "d", "a", "b"]);
+        let queue = session(Some("c"), None, Loop::Queue);
+        assert_eq!(aligned(&queue, &playlist), playlist);
+
+        // an index that doesn't point at the song anymore falls back to looking for it
+        let stale = session(Some("c"), Some(0), Loop::Single);
+        assert_eq!(aligned(&stale, &playlist), ["c", "d", "a", "b"]);
+        // with the song in the playlist twice, the index tells which one it was
+        let twice = ["a", "c", "b", "c"];
+        let second = session(Some("c"), Some(3), Loop::Playlist);
+        assert_eq!(aligned(&second, &twice), twice);
+        let first = session(Some("c"), Some(1), Loop::Playlist);
+        assert_eq!(aligned(&first, &twice), ["b", "c", "a", "c"]);
+
+        let missing = session(Some("e"), Some(1), Loop::Playlist);
+        assert_eq!(aligned(&missing, &playlist), playlist);
+        let nothing = session(None, None, Loop::Playlist);
+        assert_eq!(aligned(&nothing, &playlist), playlist);
+    }
+
+    #[test]
+    fn test_new_then_align() {
+        // "b" just started, the playlist went on to "c" and was saved later than the session
+        let playing = ["c", "a", "b"];
+        let new = Session::new(
+            Some("b"),
+            Duration::from_secs(10),
+            true,
+            &Loop::Playlist,
+            playing.iter().map(|file| Some(*file)),
+        );
+        assert_eq!(new.index, Some(2));
+        assert_eq!(aligned(&new, &["a", "b", "c"]), playing);
+    }
+
+    #[test]
+    fn test_toml_round_trip() {
+        for saved in [
+            session(Some("/music/a song.flac"), Some(3), Loop::Single),
+            session(Some("/music/b.mp3"), None, Loop::Queue),
+            session(None, None, Loop::Playlist),
+        ] {
+            let loaded: Session = toml::from_str(&toml::to_string(&saved).unwrap()).unwrap();
+            assert_eq!(loaded.file, saved.file);
+            assert_eq!(loaded.position, saved.position);
+            assert_eq!(loaded.index, saved.index);
+            assert_eq!(loaded.loop_mode, saved.loop_mode);
+            assert_eq!(loaded.paused, saved.paused);
+        }
+    }
+}
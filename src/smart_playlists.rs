@@ -0,0 +1,191 @@
+//! Playlists saved as a search query, kept in `smart_playlists.toml` and filled from the
+//! library database each time they're opened, so they follow what's added and played.
+//!
+//! A playlist has a `name`, a `query` as in the search (see `query`), an `order` of
+//! `library`, `random`, `added`, `plays` or `rating`, and optionally a `limit` of songs:
+//!
+//! ```toml
+//! [[playlists]]
+//! name = "Quick 50"
+//! query = "duration:<4m"
+//! order = "random"
+//! limit = 50
+//! ```
+use crate::config::get_app_config_path;
+use crate::library_db::{LibraryDb, Track};
+use crate::query::{Fields, Query};
+use anyhow::Result;
+use rand::seq::SliceRandom;
+use rand::thread_rng;
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Order {
+    /// By file, as the library lists them.
+    #[default]
+    Library,
+    Random,
+    /// Newest first.
+    Added,
+    /// Most played first.
+    Plays,
+    /// Best rated first.
+    Rating,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct SmartPlaylist {
+    pub name: String,
+    pub query: String,
+    #[serde(default)]
+    pub order: Order,
+    /// At most this many songs, the first ones in `order`.
+    pub limit: Option<usize>,
+}
+
+impl SmartPlaylist {
+    fn new(name: &str, query: &str, order: Order, limit: Option<usize>) -> Self {
+        Self {
+            name: name.to_string(),
+            query: query.to_string(),
+            order,
+            limit,
+        }
+    }
+
+    /// The files of the songs under `root` that are in the playlist now.
+    pub fn files(&self, db: &LibraryDb, root: &Path) -> Result<Vec<String>> {
+        let query = Query::parse(&self.query)?;
+        let mut tracks: Vec<&Track> = db
+            .tracks_in(root)
+            .filter(|track| query.matches(&Fields::from(*track)))
+            .collect();
+        match self.order {
+            Order::Library => {}
+            Order::Random => tracks.shuffle(&mut thread_rng()),
+            Order::Added => tracks.sort_by_key(|track| Reverse(track.added)),
+            Order::Plays => tracks.sort_by_key(|track| Reverse(track.plays)),
+            Order::Rating => tracks.sort_by_key(|track| Reverse(track.rating)),
+        }
+        if let Some(limit) = self.limit {
+            tracks.truncate(limit);
+        }
+        Ok(tracks.into_iter().map(|track| track.file.clone()).collect())
+    }
+}
+
+#[derive(Default, Deserialize, Serialize)]
+struct Store {
+    #[serde(default)]
+    playlists: Vec<SmartPlaylist>,
+}
+
+fn path() -> Result<PathBuf> {
+    let mut path = get_app_config_path()?;
+    path.push("smart_playlists.toml");
+    Ok(path)
+}
+
+/// The smart playlists. The first time, a few examples are written to start from.
+pub fn load() -> Result<Vec<SmartPlaylist>> {
+    let path = path()?;
+    if !path.exists() {
+        let store = Store {
+            playlists: examples(),
+        };
+        fs::write(&path, toml::to_string(&store)?)?;
+        return Ok(store.playlists);
+    }
+    let store: Store = toml::from_str(&fs::read_to_string(path)?)?;
+    Ok(store.playlists)
+}
+
+fn examples() -> Vec<SmartPlaylist> {
+    vec![
+        SmartPlaylist::new("Recently added", "added:<=30", Order::Added, None),
+        SmartPlaylist::new("Never played", "plays:0", Order::Random, None),
+        SmartPlaylist::new(
+            "Jazz favourites",
+            "genre:jazz rating:>=4",
+            Order::Rating,
+            None,
+        ),
+        SmartPlaylist::new("Quick 50", "duration:<4m", Order::Random, Some(50)),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn track(file: &str, genre: &str, rating: u8, added: u64, plays: u32) -> Track {
+        Track {
+            file: file.to_string(),
+            genre: Some(genre.to_string()),
+            rating: Some(rating),
+            added,
+            plays,
+            ..Track::default()
+        }
+    }
+
+    fn files(query: &str, order: Order, limit: Option<usize>) -> Result<Vec<String>> {
+        let db: LibraryDb = [
+            track("/music/a.mp3", "Jazz", 3, 300, 2),
+            track("/music/b.mp3", "Rock", 5, 100, 9),
+            track("/music/c.mp3", "Jazz", 4, 200, 0),
+            track("/other/d.mp3", "Jazz", 5, 400, 5),
+        ]
+        .into_iter()
+        .collect();
+        SmartPlaylist::new("test", query, order, limit).files(&db, Path::new("/music"))
+    }
+
+    #[test]
+    fn test_query() {
+        let jazz = files("genre:jazz", Order::Library, None).unwrap();
+        assert_eq!(jazz, ["/music/a.mp3", "/music/c.mp3"]);
+        let rated = files("rating:>=4 -genre:rock", Order::Library, None).unwrap();
+        assert_eq!(rated, ["/music/c.mp3"]);
+        assert!(files("genre:blues", Order::Library, None)
+            .unwrap()
+            .is_empty());
+        assert!(files("year:soon", Order::Library, None).is_err());
+    }
+
+    #[test]
+    fn test_order() {
+        let all = |order| files("", order, None).unwrap();
+        assert_eq!(
+            all(Order::Library),
+            ["/music/a.mp3", "/music/b.mp3", "/music/c.mp3"]
+        );
+        assert_eq!(
+            all(Order::Added),
+            ["/music/a.mp3", "/music/c.mp3", "/music/b.mp3"]
+        );
+        assert_eq!(
+            all(Order::Plays),
+            ["/music/b.mp3", "/music/a.mp3", "/music/c.mp3"]
+        );
+        assert_eq!(
+            all(Order::Rating),
+            ["/music/b.mp3", "/music/c.mp3", "/music/a.mp3"]
+        );
+        let mut random = all(Order::Random);
+        random.sort();
+        assert_eq!(random, all(Order::Library));
+    }
+
+    #[test]
+    fn test_limit() {
+        let most_played = files("", Order::Plays, Some(2)).unwrap();
+        assert_eq!(most_played, ["/music/b.mp3", "/music/a.mp3"]);
+        assert_eq!(files("", Order::Random, Some(1)).unwrap().len(), 1);
+        assert_eq!(files("", Order::Library, Some(10)).unwrap().len(), 3);
+    }
+}
@@ -21,14 +21,19 @@
  * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
  * SOFTWARE.
  */
+use crate::config::ReplayGainMode;
+use crate::library_db::Track;
 use crate::songtag::lrc::Lyric;
 use anyhow::{bail, Result};
 use id3::frame::Lyrics;
-use lofty::id3::v2::{Frame, FrameFlags, FrameValue, Id3v2Tag, LanguageFrame, TextEncoding};
+use lofty::id3::v2::{
+    EncodedTextFrame, Frame, FrameFlags, FrameValue, Id3v2Tag, LanguageFrame, TextEncoding,
+};
 use lofty::{
     mp3::Mp3File, Accessor, AudioFile, FileType, ItemKey, ItemValue, Picture, PictureType, TagExt,
     TagItem,
 };
+use serde::{Deserialize, Serialize};
 use std::convert::From;
 use std::ffi::OsStr;
 use std::fs::rename;
@@ -46,6 +51,16 @@ pub struct Song {
     album: Option<String>,
     /// Title of the song
     title: Option<String>,
+    /// Artist of the whole album, for compilations
+    album_artist: Option<String>,
+    genre: Option<String>,
+    /// Number of the song on its disc
+    track: Option<u32>,
+    /// Number of the disc in the album
+    disc: Option<u32>,
+    year: Option<i32>,
+    /// From 0 to 5 stars
+    rating: Option<u8>,
     /// File path to the song
     file: Option<String>,
     /// Duration of the song
@@ -60,6 +75,210 @@ pub struct Song {
     parsed_lyric: Option<Lyric>,
     picture: Option<Picture>,
     file_type: Option<FileType>,
+    replaygain: ReplayGain,
+}
+
+/// `ReplayGain` values of a song, gains in dB and peaks as linear sample amplitude.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize)]
+pub struct ReplayGain {
+    pub track_gain: Option<f32>,
+    pub track_peak: Option<f32>,
+    pub album_gain: Option<f32>,
+    pub album_peak: Option<f32>,
+}
+
+impl ReplayGain {
+    fn read_item(&mut self, key: &str, value: &str) {
+        // mp4 freeform atoms come as "----:com.apple.iTunes:replaygain_track_gain"
+        let key = key.rsplit(':').next().unwrap_or(key).to_uppercase();
+        let value = value.trim();
+        match key.as_str() {
+            "REPLAYGAIN_TRACK_GAIN" => self.track_gain = parse_gain(value),
+            "REPLAYGAIN_TRACK_PEAK" => self.track_peak = value.parse().ok(),
+            "REPLAYGAIN_ALBUM_GAIN" => self.album_gain = parse_gain(value),
+            "REPLAYGAIN_ALBUM_PEAK" => self.album_peak = value.parse().ok(),
+            // Opus only has the R128 tags, ReplayGain ones win when both are present
+            "R128_TRACK_GAIN" if self.track_gain.is_none() => self.track_gain = parse_r128(value),
+            "R128_ALBUM_GAIN" if self.album_gain.is_none() => self.album_gain = parse_r128(value),
+            _ => {}
+        }
+    }
+
+    /// Linear volume factor for `mode`, lowered when needed so the peak doesn't clip.
+    /// Falls back to the other gain when the wanted one is missing.
+    pub fn factor(&self, mode: ReplayGainMode) -> f32 {
+        let (gain, peak) = match mode {
+            ReplayGainMode::Off => return 1.0,
+            ReplayGainMode::Track if self.track_gain.is_some() => {
+                (self.track_gain, self.track_peak)
+            }
+            ReplayGainMode::Album if self.album_gain.is_some() => {
+                (self.album_gain, self.album_peak)
+            }
+            ReplayGainMode::Track => (self.album_gain, self.album_peak),
+            ReplayGainMode::Album => (self.track_gain, self.track_peak),
+        };
+        let mut factor = gain.map_or(1.0, |db| 10_f32.powf(db / 20.0));
+        if let Some(peak) = peak {
+            if peak > 0.0 && factor * peak > 1.0 {
+                factor = 1.0 / peak;
+            }
+        }
+        factor
+    }
+
+    /// Writes the values that are set as `REPLAYGAIN_*` tags, keeping the other tags.
+    pub fn save_to_path(&self, path: &Path) -> Result<()> {
+        let mut items = Vec::new();
+        if let Some(gain) = self.track_gain {
+            items.push(("REPLAYGAIN_TRACK_GAIN", format!("{:.2} dB", gain)));
+        }
+        if let Some(peak) = self.track_peak {
+            items.push(("REPLAYGAIN_TRACK_PEAK", format!("{:.6}", peak)));
+        }
+        if let Some(gain) = self.album_gain {
+            items.push(("REPLAYGAIN_ALBUM_GAIN", format!("{:.2} dB", gain)));
+        }
+        if let Some(peak) = self.album_peak {
+            items.push(("REPLAYGAIN_ALBUM_PEAK", format!("{:.6}", peak)));
+        }
+
+        let mut tagged_file = lofty::Probe::open(path)?.read(true)?;
+        match tagged_file.file_type() {
+            FileType::MP3 => {
+                // TXXX frames need their description, which the generic tag drops
+                let mut reader = BufReader::new(File::open(path)?);
+                let file = Mp3File::read_from(&mut reader, false)?;
+                let old_tag = file.id3v2_tag().cloned().unwrap_or_default();
+                // TXXX frames are told apart by their exact description, other tools may have
+                // written the same ones in lower case
+                let mut tag = Id3v2Tag::default();
+                tag.set_flags(*old_tag.flags());
+                for frame in old_tag.iter() {
+                    let replaced = match frame.content() {
+                        FrameValue::UserText(text) => items
+                            .iter()
+                            .any(|(key, _)| text.description.eq_ignore_ascii_case(key)),
+                        _ => false,
+                    };
+                    if !replaced {
+                        tag.insert(frame.clone());
+                    }
+                }
+                for (key, value) in items {
+                    if let Ok(frame) = Frame::new(
+                        "TXXX",
+                        FrameValue::UserText(EncodedTextFrame {
+                            encoding: TextEncoding::UTF8,
+                            description: key.to_string(),
+                            content: value,
+                        }),
+                        FrameFlags::default(),
+                    ) {
+                        tag.insert(frame);
+                    }
+                }
+                tag.save_to_path(path)?;
+            }
+            file_type => {
+                let tag_type = file_type.primary_tag_type();
+                if tagged_file.primary_tag().is_none() {
+                    tagged_file.insert_tag(lofty::Tag::new(tag_type));
+                }
+                if let Some(tag) = tagged_file.primary_tag_mut() {
+                    for (key, value) in items {
+                        let key = if tag_type == lofty::TagType::Mp4Ilst {
+                            format!("----:com.apple.iTunes:{}", key.to_lowercase())
+                        } else {
+                            key.to_string()
+                        };
+                        // Vorbis comments are often written as "replaygain_track_gain"
+                        tag.retain_items(|item| match item.key() {
+                            ItemKey::Unknown(old) => !old.eq_ignore_ascii_case(&key),
+                            _ => true,
+                        });
+                        tag.insert_item_unchecked(TagItem::new(
+                            ItemKey::Unknown(key),
+                            ItemValue::Text(value),
+                        ));
+                    }
+                    tag.save_to_path(path)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+// "-6.48 dB"
+fn parse_gain(value: &str) -> Option<f32> {
+    value
+        .trim_end_matches(|c: char| c.is_ascii_alphabetic())
+        .trim()
+        .parse()
+        .ok()
+}
+
+// Q7.8 fixed point relative to -23 LUFS, ReplayGain targets -18 LUFS
+fn parse_r128(value: &str) -> Option<f32> {
+    value
+        .parse::<i16>()
+        .ok()
+        .map(|q| f32::from(q) / 256.0 + 5.0)
+}
+
+// "3/12" or "2001-05-03", the number at the start
+fn parse_number<T: FromStr>(value: &str) -> Option<T> {
+    let value = value.trim();
+    let end = value
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(value.len());
+    value[..end].parse().ok()
+}
+
+/// Stars out of 5 of a rating tag in text, which tools write out of 5, 10 or 100.
+fn stars(rating: u32) -> u8 {
+    let stars = match rating {
+        0..=5 => rating,
+        6..=10 => rating / 2,
+        _ => rating / 20,
+    };
+    u8::try_from(stars.min(5)).unwrap_or(5)
+}
+
+/// Stars out of 5 of an ID3 popularimeter: an email, a NUL, the rating from 1 to 255 and a
+/// play counter. Players write 1, 64, 128, 196 and 255, a rating of 0 is none.
+fn popularimeter_stars(popm: &[u8]) -> Option<u8> {
+    let email_end = popm.iter().position(|&byte| byte == 0)?;
+    Some(match *popm.get(email_end + 1)? {
+        0 => return None,
+        1..=31 => 1,
+        32..=95 => 2,
+        96..=159 => 3,
+        160..=223 => 4,
+        _ => 5,
+    })
+}
+
+impl From<&Track> for Song {
+    /// The song as the library database knows it, without lyrics or picture.
+    fn from(track: &Track) -> Self {
+        Self {
+            artist: track.artist.clone(),
+            album: track.album.clone(),
+            title: track.title.clone(),
+            album_artist: track.album_artist.clone(),
+            genre: track.genre.clone(),
+            duration: track.duration,
+            track: track.number,
+            disc: track.disc,
+            year: track.year,
+            rating: track.rating,
+            replaygain: track.replaygain,
+            file_type: lofty::FileType::from_path(&track.file),
+            ..Self::new(&track.file)
+        }
+    }
 }
 
 impl Song {
@@ -85,6 +304,30 @@ impl Song {
                 song.artist = tag.artist().map(str::to_string);
                 song.album = tag.album().map(str::to_string);
                 song.title = tag.title().map(str::to_string);
+                song.album_artist = tag.get_string(&ItemKey::AlbumArtist).map(str::to_string);
+                song.genre = tag.genre().map(str::to_string);
+                song.track = tag.get_string(&ItemKey::TrackNumber).and_then(parse_number);
+                song.disc = tag.get_string(&ItemKey::DiscNumber).and_then(parse_number);
+                song.year = tag
+                    .get_string(&ItemKey::Year)
+                    .or_else(|| tag.get_string(&ItemKey::RecordingDate))
+                    .and_then(parse_number);
+                // lofty keeps ID3 POPM frames as they are
+                song.rating = tag.get_binary(&ItemKey::Popularimeter, false).map_or_else(
+                    || {
+                        tag.get_string(&ItemKey::Popularimeter)
+                            .and_then(parse_number)
+                            .map(stars)
+                    },
+                    popularimeter_stars,
+                );
+
+                for item in tag.items() {
+                    if let (ItemKey::Unknown(key), Some(value)) = (item.key(), item.value().text())
+                    {
+                        song.replaygain.read_item(key, value);
+                    }
+                }
 
                 // Get all of the lyrics tags
                 let mut lyric_frames: Vec<Lyrics> = Vec::new();
@@ -94,6 +337,17 @@ impl Song {
                         let file = Mp3File::read_from(&mut reader, false)?;
 
                         if let Some(id3v2_tag) = file.id3v2_tag() {
+                            // TXXX frames lose their description in the generic tag
+                            for frame in id3v2_tag.iter() {
+                                if let FrameValue::UserText(EncodedTextFrame {
+                                    description,
+                                    content,
+                                    ..
+                                }) = frame.content()
+                                {
+                                    song.replaygain.read_item(description, content);
+                                }
+                            }
                             for lyrics_frame in id3v2_tag.unsync_text() {
                                 lyric_frames.push(Lyrics {
                                     lang: lyrics_frame.language.clone(),
@@ -151,6 +405,12 @@ impl Song {
             artist,
             album,
             title,
+            album_artist: None,
+            genre: None,
+            track: None,
+            disc: None,
+            year: None,
+            rating: None,
             file,
             duration,
             name,
@@ -158,6 +418,7 @@ impl Song {
             lyric_frames,
             lyric_selected_index: 0,
             picture,
+            replaygain: ReplayGain::default(),
         }
     }
 
@@ -288,6 +549,30 @@ impl Song {
         self.title = Some(title.to_string());
     }
 
+    pub fn album_artist(&self) -> Option<&str> {
+        self.album_artist.as_deref()
+    }
+
+    pub fn genre(&self) -> Option<&str> {
+        self.genre.as_deref()
+    }
+
+    pub const fn track(&self) -> Option<u32> {
+        self.track
+    }
+
+    pub const fn disc(&self) -> Option<u32> {
+        self.disc
+    }
+
+    pub const fn year(&self) -> Option<i32> {
+        self.year
+    }
+
+    pub const fn rating(&self) -> Option<u8> {
+        self.rating
+    }
+
     pub fn file(&self) -> Option<&str> {
         match self.file.as_ref() {
             Some(file) => Some(file),
@@ -306,6 +591,10 @@ impl Song {
         self.duration
     }
 
+    pub const fn replaygain(&self) -> &ReplayGain {
+        &self.replaygain
+    }
+
     pub fn duration_formatted(&self) -> String {
         Self::duration_formatted_short(&self.duration)
     }
@@ -502,3 +791,101 @@ fn create_lyrics(tag: &mut lofty::Tag, lyric_frames: &mut Vec<Lyrics>) {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A few silent MPEG-1 layer III frames, 128kbps at 44.1kHz.
+    fn mp3() -> Vec<u8> {
+        let mut data = Vec::new();
+        for _ in 0..8 {
+            data.extend_from_slice(&[0xff, 0xfb, 0x90, 0x64]);
+            data.extend_from_slice(&[0; 413]);
+        }
+        data
+    }
+
+    fn user_text(description: &str, content: &str) -> Frame {
+        let value = FrameValue::UserText(EncodedTextFrame {
+            encoding: TextEncoding::UTF8,
+            description: description.to_string(),
+            content: content.to_string(),
+        });
+        Frame::new("TXXX", value, FrameFlags::default()).unwrap()
+    }
+
+    #[test]
+    fn test_replaygain_replaces_tags() {
+        let path = std::env::temp_dir().join(format!("termusic-rg-{}.mp3", std::process::id()));
+        std::fs::write(&path, mp3()).unwrap();
+        // as another tool left them
+        let mut tag = Id3v2Tag::default();
+        tag.insert(user_text("replaygain_track_gain", "-1.00 dB"));
+        tag.insert(user_text("CATALOG", "X1"));
+        tag.save_to_path(&path).unwrap();
+
+        let mut gain = ReplayGain {
+            track_gain: Some(-3.0),
+            track_peak: Some(0.9),
+            ..ReplayGain::default()
+        };
+        gain.save_to_path(&path).unwrap();
+        gain.track_gain = Some(-4.5);
+        gain.save_to_path(&path).unwrap();
+
+        let file = Mp3File::read_from(&mut BufReader::new(File::open(&path).unwrap()), false);
+        let tag = file.unwrap().id3v2_tag().cloned().unwrap();
+        let descriptions: Vec<String> = tag
+            .iter()
+            .filter_map(|frame| match frame.content() {
+                FrameValue::UserText(text) => Some(text.description.clone()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            descriptions,
+            ["CATALOG", "REPLAYGAIN_TRACK_GAIN", "REPLAYGAIN_TRACK_PEAK"]
+        );
+        let song = Song::read_from_path(&path).unwrap();
+        assert_eq!(song.replaygain().track_gain, Some(-4.5));
+        std::fs::remove_file(path).ok();
+    }
+
+    #[test]
+    fn test_stars() {
+        assert_eq!(stars(0), 0);
+        assert_eq!(stars(4), 4);
+        assert_eq!(stars(7), 3);
+        assert_eq!(stars(10), 5);
+        assert_eq!(stars(60), 3);
+        assert_eq!(stars(64), 3);
+        assert_eq!(stars(100), 5);
+        assert_eq!(stars(255), 5);
+
+        let popm = |rating: u8| [b"me@example.org\0".as_slice(), &[rating, 0, 0, 0, 7]].concat();
+        let ratings: Vec<Option<u8>> = [0, 1, 64, 128, 196, 255]
+            .into_iter()
+            .map(|rating| popularimeter_stars(&popm(rating)))
+            .collect();
+        assert_eq!(ratings, [None, Some(1), Some(2), Some(3), Some(4), Some(5)]);
+        // without an email, or cut short
+        assert_eq!(popularimeter_stars(&[0, 196]), Some(4));
+        assert_eq!(popularimeter_stars(b"me@example.org\0"), None);
+        assert_eq!(popularimeter_stars(b"me@example.org"), None);
+    }
+
+    #[test]
+    fn test_read_popularimeter() {
+        let path = std::env::temp_dir().join(format!("termusic-popm-{}.mp3", std::process::id()));
+        std::fs::write(&path, mp3()).unwrap();
+        let mut tag = Id3v2Tag::default();
+        let popm = FrameValue::Binary(b"me@example.org\0\x40\0\0\0\x07".to_vec());
+        tag.insert(Frame::new("POPM", popm, FrameFlags::default()).unwrap());
+        tag.save_to_path(&path).unwrap();
+
+        let song = Song::read_from_path(&path).unwrap();
+        assert_eq!(song.rating(), Some(2));
+        std::fs::remove_file(path).ok();
+    }
+}
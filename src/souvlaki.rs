@@ -0,0 +1,583 @@
+//! The MPRIS service, taken over from the Linux part of souvlaki 0.5.1 (MIT, Sinono3) to add
+//! what it leaves out: volume, loop status, shuffle, the playback rate and the `Seeked` signal.
+use crate::player::{MAX_SPEED, MIN_SPEED};
+use dbus::arg::{RefArg, Variant};
+use dbus::blocking::Connection;
+use dbus::channel::{MatchingReceiver, Sender};
+use dbus::ffidisp::stdintf::org_freedesktop_dbus::PropertiesPropertiesChanged;
+use dbus::message::SignalArgs;
+use dbus::{Message, MethodErr, Path};
+use dbus_crossroads::{Crossroads, IfaceBuilder};
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+const OBJECT_PATH: &str = "/org/mpris/MediaPlayer2";
+const PLAYER_INTERFACE: &str = "org.mpris.MediaPlayer2.Player";
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum MediaPlayback {
+    Stopped,
+    Paused,
+    Playing,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct MediaMetadata<'a> {
+    pub title: Option<&'a str>,
+    pub album: Option<&'a str>,
+    pub artist: Option<&'a str>,
+    pub cover_url: Option<&'a str>,
+    pub duration: Option<Duration>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum LoopStatus {
+    None,
+    Track,
+    Playlist,
+}
+
+impl LoopStatus {
+    const fn name(self) -> &'static str {
+        match self {
+            Self::None => "None",
+            Self::Track => "Track",
+            Self::Playlist => "Playlist",
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum MediaControlEvent {
+    Play,
+    Pause,
+    Toggle,
+    Next,
+    Previous,
+    Stop,
+    /// Seek by the offset, backward when negative.
+    SeekBy(Duration, bool),
+    SetPosition(Duration),
+    OpenUri(String),
+    /// Between 0.0 and 1.0.
+    SetVolume(f64),
+    /// Between `MIN_SPEED` and `MAX_SPEED`.
+    SetRate(f64),
+    SetLoopStatus(LoopStatus),
+    SetShuffle(bool),
+    Raise,
+    Quit,
+}
+
+pub struct PlatformConfig<'a> {
+    pub dbus_name: &'a str,
+    pub display_name: &'a str,
+}
+
+/// A platform-specific error.
+#[derive(Debug)]
+pub struct Error;
+
+/// A handle to OS media controls.
+pub struct MediaControls {
+    thread: Option<ServiceThreadHandle>,
+    dbus_name: String,
+    friendly_name: String,
+}
+
+struct ServiceThreadHandle {
+    event_channel: mpsc::Sender<InternalEvent>,
+    thread: JoinHandle<()>,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+enum InternalEvent {
+    ChangeMetadata(OwnedMetadata),
+    ChangePlayback(MediaPlayback),
+    ChangeVolume(f64),
+    ChangeRate(f64),
+    ChangeLoopStatus(LoopStatus),
+    /// Playback went on to here, clients ask for it when they need it.
+    ChangePosition(Duration),
+    /// Playback jumped to here.
+    Seeked(Duration),
+    Kill,
+}
+
+#[derive(Debug)]
+struct ServiceState {
+    metadata: OwnedMetadata,
+    playback_status: MediaPlayback,
+    position: Duration,
+    volume: f64,
+    rate: f64,
+    loop_status: LoopStatus,
+}
+
+impl ServiceState {
+    const fn playback_status(&self) -> &'static str {
+        match self.playback_status {
+            MediaPlayback::Playing => "Playing",
+            MediaPlayback::Paused => "Paused",
+            MediaPlayback::Stopped => "Stopped",
+        }
+    }
+}
+
+fn create_metadata_dict(metadata: &OwnedMetadata) -> HashMap<String, Variant<Box<dyn RefArg>>> {
+    let mut dict = HashMap::<String, Variant<Box<dyn RefArg>>>::new();
+
+    let mut insert = |k: &str, v| dict.insert(k.to_string(), Variant(v));
+
+    // there is one track at a time, it always has this id
+    insert(
+        "mpris:trackid",
+        Box::new(Path::new("/org/termusic/track").unwrap()),
+    );
+    if let Some(length) = metadata.duration {
+        insert("mpris:length", Box::new(length));
+    }
+    if let Some(cover_url) = &metadata.cover_url {
+        insert("mpris:artUrl", Box::new(cover_url.clone()));
+    }
+    if let Some(title) = &metadata.title {
+        insert("xesam:title", Box::new(title.clone()));
+    }
+    if let Some(artist) = &metadata.artist {
+        insert("xesam:artist", Box::new(vec![artist.clone()]));
+    }
+    if let Some(album) = &metadata.album {
+        insert("xesam:album", Box::new(album.clone()));
+    }
+
+    dict
+}
+
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+struct OwnedMetadata {
+    title: Option<String>,
+    album: Option<String>,
+    artist: Option<String>,
+    cover_url: Option<String>,
+    /// In microseconds, like MPRIS has it.
+    duration: Option<i64>,
+}
+
+impl From<MediaMetadata<'_>> for OwnedMetadata {
+    fn from(other: MediaMetadata) -> Self {
+        Self {
+            title: other.title.map(ToString::to_string),
+            artist: other.artist.map(ToString::to_string),
+            album: other.album.map(ToString::to_string),
+            cover_url: other.cover_url.map(ToString::to_string),
+            duration: other.duration.map(micros),
+        }
+    }
+}
+
+fn micros(duration: Duration) -> i64 {
+    i64::try_from(duration.as_micros()).unwrap_or(i64::MAX)
+}
+
+impl MediaControls {
+    /// Create media controls with the specified config.
+    #[allow(clippy::unnecessary_wraps)]
+    pub fn new(config: &PlatformConfig) -> Result<Self, Error> {
+        Ok(Self {
+            thread: None,
+            dbus_name: config.dbus_name.to_string(),
+            friendly_name: config.display_name.to_string(),
+        })
+    }
+
+    /// Attach the media control events to a handler.
+    pub fn attach<F>(&mut self, event_handler: F) -> Result<(), Error>
+    where
+        F: Fn(MediaControlEvent) + Send + 'static,
+    {
+        self.detach()?;
+
+        let dbus_name = self.dbus_name.clone();
+        let friendly_name = self.friendly_name.clone();
+        let (event_channel, rx) = mpsc::channel();
+
+        self.thread = Some(ServiceThreadHandle {
+            event_channel,
+            thread: thread::spawn(move || {
+                run_service(&dbus_name, friendly_name, event_handler, &rx).ok();
+            }),
+        });
+        Ok(())
+    }
+
+    /// Detach the event handler.
+    #[allow(clippy::unnecessary_wraps)]
+    pub fn detach(&mut self) -> Result<(), Error> {
+        if let Some(ServiceThreadHandle {
+            event_channel,
+            thread,
+        }) = self.thread.take()
+        {
+            event_channel.send(InternalEvent::Kill).ok();
+            thread.join().ok();
+        }
+        Ok(())
+    }
+
+    pub fn set_playback(&self, playback: MediaPlayback) {
+        self.send_internal_event(InternalEvent::ChangePlayback(playback));
+    }
+
+    /// Set the metadata of the currently playing media item.
+    pub fn set_metadata(&self, metadata: MediaMetadata) {
+        self.send_internal_event(InternalEvent::ChangeMetadata(metadata.into()));
+    }
+
+    /// Between 0.0 and 1.0.
+    pub fn set_volume(&self, volume: f64) {
+        self.send_internal_event(InternalEvent::ChangeVolume(volume));
+    }
+
+    /// How many times as fast as normal it plays.
+    pub fn set_rate(&self, rate: f64) {
+        self.send_internal_event(InternalEvent::ChangeRate(rate));
+    }
+
+    pub fn set_loop_status(&self, loop_status: LoopStatus) {
+        self.send_internal_event(InternalEvent::ChangeLoopStatus(loop_status));
+    }
+
+    /// Where playback is now, as it goes on.
+    pub fn set_position(&self, position: Duration) {
+        self.send_internal_event(InternalEvent::ChangePosition(position));
+    }
+
+    /// Where playback is now, after a jump.
+    pub fn seeked(&self, position: Duration) {
+        self.send_internal_event(InternalEvent::Seeked(position));
+    }
+
+    fn send_internal_event(&self, event: InternalEvent) {
+        if let Some(thread) = &self.thread {
+            thread.event_channel.send(event).ok();
+        }
+    }
+}
+
+impl Drop for MediaControls {
+    fn drop(&mut self) {
+        self.detach().ok();
+    }
+}
+
+#[allow(clippy::too_many_lines)]
+fn run_service<F>(
+    dbus_name: &str,
+    friendly_name: String,
+    event_handler: F,
+    event_channel: &mpsc::Receiver<InternalEvent>,
+) -> Result<(), dbus::Error>
+where
+    F: Fn(MediaControlEvent) + Send + 'static,
+{
+    let event_handler = Arc::new(Mutex::new(event_handler));
+
+    let c = Connection::new_session()?;
+    c.request_name(
+        format!("org.mpris.MediaPlayer2.{}", dbus_name),
+        false,
+        true,
+        false,
+    )?;
+
+    let mut cr = Crossroads::new();
+
+    let app_interface = cr.register("org.mpris.MediaPlayer2", {
+        let event_handler = event_handler.clone();
+
+        move |b| {
+            b.property("Identity")
+                .get(move |_, ()| Ok(friendly_name.clone()));
+
+            register_method(b, &event_handler, "Raise", MediaControlEvent::Raise);
+            register_method(b, &event_handler, "Quit", MediaControlEvent::Quit);
+
+            b.property("CanQuit")
+                .get(|_, ()| Ok(true))
+                .emits_changed_true();
+            b.property("CanRaise")
+                .get(|_, ()| Ok(false))
+                .emits_changed_true();
+            b.property("HasTrackList")
+                .get(|_, ()| Ok(false))
+                .emits_changed_true();
+            b.property("SupportedUriSchemes")
+                .get(move |_, ()| Ok(vec!["file".to_string()]))
+                .emits_changed_true();
+            b.property("SupportedMimeTypes")
+                .get(move |_, ()| Ok(&[] as &[String]))
+                .emits_changed_true();
+        }
+    });
+
+    let state = Arc::new(Mutex::new(ServiceState {
+        metadata: OwnedMetadata::default(),
+        playback_status: MediaPlayback::Stopped,
+        position: Duration::ZERO,
+        volume: 1.0,
+        rate: 1.0,
+        loop_status: LoopStatus::None,
+    }));
+
+    let player_interface = cr.register(PLAYER_INTERFACE, |b| {
+        register_method(b, &event_handler, "Next", MediaControlEvent::Next);
+        register_method(b, &event_handler, "Previous", MediaControlEvent::Previous);
+        register_method(b, &event_handler, "Pause", MediaControlEvent::Pause);
+        register_method(b, &event_handler, "PlayPause", MediaControlEvent::Toggle);
+        register_method(b, &event_handler, "Stop", MediaControlEvent::Stop);
+        register_method(b, &event_handler, "Play", MediaControlEvent::Play);
+
+        b.method("Seek", ("Offset",), (), {
+            let event_handler = event_handler.clone();
+
+            move |_, (), (offset,): (i64,)| {
+                let offset_abs = Duration::from_micros(offset.unsigned_abs());
+                (event_handler.lock().unwrap())(MediaControlEvent::SeekBy(offset_abs, offset < 0));
+                Ok(())
+            }
+        });
+
+        b.method("SetPosition", ("TrackId", "Position"), (), {
+            let state = state.clone();
+            let event_handler = event_handler.clone();
+
+            move |_, (), (_trackid, position): (Path, i64)| {
+                // past the end or before the start, the call is ignored
+                let length = state.lock().unwrap().metadata.duration;
+                if !matches!(length, Some(length) if position > length) {
+                    if let Ok(position) = u64::try_from(position) {
+                        (event_handler.lock().unwrap())(MediaControlEvent::SetPosition(
+                            Duration::from_micros(position),
+                        ));
+                    }
+                }
+                Ok(())
+            }
+        });
+
+        b.method("OpenUri", ("Uri",), (), {
+            let event_handler = event_handler.clone();
+
+            move |_, (), (uri,): (String,)| {
+                (event_handler.lock().unwrap())(MediaControlEvent::OpenUri(uri));
+                Ok(())
+            }
+        });
+
+        b.signal::<(i64,), _>("Seeked", ("Position",));
+
+        b.property("PlaybackStatus")
+            .get({
+                let state = state.clone();
+                move |_, ()| Ok(state.lock().unwrap().playback_status().to_string())
+            })
+            .emits_changed_true();
+
+        b.property("LoopStatus")
+            .get({
+                let state = state.clone();
+                move |_, ()| Ok(state.lock().unwrap().loop_status.name().to_string())
+            })
+            .set({
+                let event_handler = event_handler.clone();
+                move |_, (), loop_status: String| {
+                    let status = match loop_status.as_str() {
+                        "None" => LoopStatus::None,
+                        "Track" => LoopStatus::Track,
+                        "Playlist" => LoopStatus::Playlist,
+                        _ => return Err(MethodErr::invalid_arg(&loop_status)),
+                    };
+                    (event_handler.lock().unwrap())(MediaControlEvent::SetLoopStatus(status));
+                    Ok(Some(loop_status))
+                }
+            })
+            .emits_changed_true();
+
+        // shuffling happens once, the playlist isn't kept shuffled
+        b.property("Shuffle")
+            .get(|_, ()| Ok(false))
+            .set({
+                let event_handler = event_handler.clone();
+                move |_, (), shuffle: bool| {
+                    (event_handler.lock().unwrap())(MediaControlEvent::SetShuffle(shuffle));
+                    Ok(Some(false))
+                }
+            })
+            .emits_changed_true();
+
+        b.property("Rate")
+            .get({
+                let state = state.clone();
+                move |_, ()| Ok(state.lock().unwrap().rate)
+            })
+            .set({
+                let event_handler = event_handler.clone();
+                move |_, (), rate: f64| {
+                    // the specification has a rate of 0 pause instead
+                    if rate == 0.0 {
+                        (event_handler.lock().unwrap())(MediaControlEvent::Pause);
+                        return Ok(None);
+                    }
+                    let rate = rate.clamp(f64::from(MIN_SPEED), f64::from(MAX_SPEED));
+                    (event_handler.lock().unwrap())(MediaControlEvent::SetRate(rate));
+                    Ok(Some(rate))
+                }
+            })
+            .emits_changed_true();
+
+        b.property("Metadata")
+            .get({
+                let state = state.clone();
+                move |_, ()| Ok(create_metadata_dict(&state.lock().unwrap().metadata))
+            })
+            .emits_changed_true();
+
+        b.property("Volume")
+            .get({
+                let state = state.clone();
+                move |_, ()| Ok(state.lock().unwrap().volume)
+            })
+            .set({
+                let event_handler = event_handler.clone();
+                move |_, (), volume: f64| {
+                    let volume = volume.clamp(0.0, 1.0);
+                    (event_handler.lock().unwrap())(MediaControlEvent::SetVolume(volume));
+                    Ok(Some(volume))
+                }
+            })
+            .emits_changed_true();
+
+        // clients follow the position by the clock and the `Seeked` signal
+        b.property("Position")
+            .get({
+                let state = state.clone();
+                move |_, ()| Ok(micros(state.lock().unwrap().position))
+            })
+            .emits_changed_false();
+
+        b.property("MinimumRate")
+            .get(|_, ()| Ok(f64::from(MIN_SPEED)))
+            .emits_changed_const();
+        b.property("MaximumRate")
+            .get(|_, ()| Ok(f64::from(MAX_SPEED)))
+            .emits_changed_const();
+
+        b.property("CanGoNext")
+            .get(|_, ()| Ok(true))
+            .emits_changed_true();
+        b.property("CanGoPrevious")
+            .get(|_, ()| Ok(true))
+            .emits_changed_true();
+        b.property("CanPlay")
+            .get(|_, ()| Ok(true))
+            .emits_changed_true();
+        b.property("CanPause")
+            .get(|_, ()| Ok(true))
+            .emits_changed_true();
+        b.property("CanSeek")
+            .get(|_, ()| Ok(true))
+            .emits_changed_true();
+        b.property("CanControl")
+            .get(|_, ()| Ok(true))
+            .emits_changed_const();
+    });
+
+    cr.insert(OBJECT_PATH, &[app_interface, player_interface], ());
+
+    c.start_receive(
+        dbus::message::MatchRule::new_method_call(),
+        Box::new(move |msg, conn| {
+            cr.handle_message(msg, conn).ok();
+            true
+        }),
+    );
+
+    let path = Path::new(OBJECT_PATH).unwrap();
+    loop {
+        if let Ok(event) = event_channel.recv_timeout(Duration::from_millis(10)) {
+            let mut changed_properties = HashMap::new();
+            {
+                let mut state = state.lock().unwrap();
+                let mut changed = |name: &str, value: Box<dyn RefArg>| {
+                    changed_properties.insert(name.to_string(), Variant(value));
+                };
+                match event {
+                    InternalEvent::ChangeMetadata(metadata) => {
+                        changed("Metadata", Box::new(create_metadata_dict(&metadata)));
+                        state.metadata = metadata;
+                    }
+                    InternalEvent::ChangePlayback(playback) => {
+                        state.playback_status = playback;
+                        changed(
+                            "PlaybackStatus",
+                            Box::new(state.playback_status().to_string()),
+                        );
+                    }
+                    InternalEvent::ChangeVolume(volume) => {
+                        state.volume = volume;
+                        changed("Volume", Box::new(volume));
+                    }
+                    InternalEvent::ChangeRate(rate) => {
+                        state.rate = rate;
+                        changed("Rate", Box::new(rate));
+                    }
+                    InternalEvent::ChangeLoopStatus(loop_status) => {
+                        state.loop_status = loop_status;
+                        changed("LoopStatus", Box::new(loop_status.name().to_string()));
+                    }
+                    InternalEvent::ChangePosition(position) => state.position = position,
+                    InternalEvent::Seeked(position) => {
+                        state.position = position;
+                        let seeked =
+                            Message::signal(&path, &PLAYER_INTERFACE.into(), &"Seeked".into())
+                                .append1(micros(position));
+                        c.send(seeked).ok();
+                    }
+                    InternalEvent::Kill => break,
+                }
+                drop(state);
+            }
+
+            if !changed_properties.is_empty() {
+                let properties_changed = PropertiesPropertiesChanged {
+                    interface_name: PLAYER_INTERFACE.to_owned(),
+                    changed_properties,
+                    invalidated_properties: Vec::new(),
+                };
+                c.send(properties_changed.to_emit_message(&path)).ok();
+            }
+        }
+        c.process(Duration::from_millis(100))?;
+    }
+
+    Ok(())
+}
+
+fn register_method<F>(
+    b: &mut IfaceBuilder<()>,
+    event_handler: &Arc<Mutex<F>>,
+    name: &'static str,
+    event: MediaControlEvent,
+) where
+    F: Fn(MediaControlEvent) + Send + 'static,
+{
+    let event_handler = event_handler.clone();
+
+    b.method(name, (), (), move |_, (), ()| {
+        (event_handler.lock().unwrap())(event.clone());
+        Ok(())
+    });
+}
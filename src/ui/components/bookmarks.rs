@@ -0,0 +1,288 @@
+use crate::bookmarks::{self, Bookmark};
+use crate::player::GeneralP;
+use crate::song::Song;
+use crate::ui::{BMMsg, Id, Model, Msg};
+use std::time::Duration;
+use tui_realm_stdlib::{Input, Table};
+use tuirealm::command::{Cmd, CmdResult, Direction, Position};
+use tuirealm::event::{Key, KeyEvent, KeyModifiers, NoUserEvent};
+use tuirealm::props::{Alignment, BorderType, Borders, Color, InputType, TableBuilder, TextSpan};
+use tuirealm::{AttrValue, Attribute, Component, Event, MockComponent, State, StateValue};
+
+#[derive(MockComponent)]
+pub struct BookmarksPopup {
+    component: Table,
+}
+
+impl Default for BookmarksPopup {
+    fn default() -> Self {
+        Self {
+            component: Table::default()
+                .borders(
+                    Borders::default()
+                        .modifiers(BorderType::Rounded)
+                        .color(Color::Green),
+                )
+                .background(Color::Reset)
+                .title("Bookmarks", Alignment::Left)
+                .scroll(true)
+                .highlighted_color(Color::LightBlue)
+                .highlighted_str("\u{1f680}")
+                .rewind(true)
+                .step(4)
+                .row_height(1)
+                .column_spacing(2)
+                .widths(&[12, 88])
+                .table(
+                    TableBuilder::default()
+                        .add_col(TextSpan::from("No bookmarks"))
+                        .build(),
+                ),
+        }
+    }
+}
+
+impl Component<Msg, NoUserEvent> for BookmarksPopup {
+    fn on(&mut self, ev: Event<NoUserEvent>) -> Option<Msg> {
+        let index = match self.state() {
+            State::One(StateValue::Usize(index)) => index,
+            _ => 0,
+        };
+        match ev {
+            Event::Keyboard(KeyEvent {
+                code: Key::Esc | Key::Char('q'),
+                ..
+            }) => return Some(Msg::Bookmarks(BMMsg::PopupClose)),
+            Event::Keyboard(KeyEvent {
+                code: Key::Down | Key::Char('j'),
+                ..
+            }) => {
+                self.perform(Cmd::Move(Direction::Down));
+            }
+            Event::Keyboard(KeyEvent {
+                code: Key::Up | Key::Char('k'),
+                ..
+            }) => {
+                self.perform(Cmd::Move(Direction::Up));
+            }
+            Event::Keyboard(KeyEvent {
+                code: Key::Enter, ..
+            }) => return Some(Msg::Bookmarks(BMMsg::Jump(index))),
+            Event::Keyboard(KeyEvent {
+                code: Key::Char('a'),
+                ..
+            }) => return Some(Msg::Bookmarks(BMMsg::InputShow)),
+            Event::Keyboard(KeyEvent {
+                code: Key::Char('d'),
+                ..
+            }) => return Some(Msg::Bookmarks(BMMsg::Delete(index))),
+            _ => return None,
+        }
+        Some(Msg::None)
+    }
+}
+
+#[derive(MockComponent)]
+pub struct BookmarkInputPopup {
+    component: Input,
+}
+
+impl BookmarkInputPopup {
+    pub fn new(name: &str) -> Self {
+        Self {
+            component: Input::default()
+                .foreground(Color::Yellow)
+                .background(Color::Reset)
+                .borders(
+                    Borders::default()
+                        .color(Color::Green)
+                        .modifiers(BorderType::Rounded),
+                )
+                .input_type(InputType::Text)
+                .value(name)
+                .title("Name of the bookmark:", Alignment::Left),
+        }
+    }
+}
+
+impl Component<Msg, NoUserEvent> for BookmarkInputPopup {
+    fn on(&mut self, ev: Event<NoUserEvent>) -> Option<Msg> {
+        let cmd_result = match ev {
+            Event::Keyboard(KeyEvent {
+                code: Key::Left, ..
+            }) => self.perform(Cmd::Move(Direction::Left)),
+            Event::Keyboard(KeyEvent {
+                code: Key::Right, ..
+            }) => self.perform(Cmd::Move(Direction::Right)),
+            Event::Keyboard(KeyEvent {
+                code: Key::Home, ..
+            }) => self.perform(Cmd::GoTo(Position::Begin)),
+            Event::Keyboard(KeyEvent { code: Key::End, .. }) => {
+                self.perform(Cmd::GoTo(Position::End))
+            }
+            Event::Keyboard(KeyEvent {
+                code: Key::Delete, ..
+            }) => self.perform(Cmd::Cancel),
+            Event::Keyboard(KeyEvent {
+                code: Key::Backspace,
+                ..
+            }) => self.perform(Cmd::Delete),
+            Event::Keyboard(KeyEvent {
+                code: Key::Char(ch),
+                modifiers: KeyModifiers::SHIFT | KeyModifiers::NONE,
+            }) => self.perform(Cmd::Type(ch)),
+            Event::Keyboard(KeyEvent { code: Key::Esc, .. }) => {
+                return Some(Msg::Bookmarks(BMMsg::InputCloseCancel));
+            }
+            Event::Keyboard(KeyEvent {
+                code: Key::Enter, ..
+            }) => self.perform(Cmd::Submit),
+            _ => CmdResult::None,
+        };
+        match cmd_result {
+            CmdResult::Submit(State::One(StateValue::String(name))) => {
+                Some(Msg::Bookmarks(BMMsg::InputCloseOk(name)))
+            }
+            _ => Some(Msg::None),
+        }
+    }
+}
+
+impl Model {
+    fn bookmarks_file(&self) -> Option<&str> {
+        self.current_song.as_ref().and_then(Song::file)
+    }
+
+    /// Position of the player in whole seconds, as bookmarks keep it.
+    #[allow(clippy::cast_sign_loss)]
+    fn bookmarks_time_pos(&self) -> Duration {
+        Duration::from_secs(self.time_pos.max(0) as u64)
+    }
+
+    /// Keeps where the current song was left, so it continues there next time.
+    pub fn bookmarks_remember(&mut self) {
+        let result = self.current_song.as_ref().and_then(|song| {
+            song.file().map(|file| {
+                bookmarks::remember(
+                    file,
+                    self.bookmarks_time_pos(),
+                    song.duration(),
+                    self.config.resume_min_secs,
+                )
+            })
+        });
+        if let Some(Err(e)) = result {
+            self.mount_error_popup(&format!("Couldn't save the position: {}", e));
+        }
+    }
+
+    /// Where `song` continues, when it was left in the middle last time.
+    pub fn bookmarks_resume_position(&self, song: &Song) -> Option<Duration> {
+        song.file().and_then(|file| {
+            bookmarks::resume_position(file, song.duration(), self.config.resume_min_secs)
+        })
+    }
+
+    /// Plays `song` at the speed it was played at last time.
+    pub fn bookmarks_load_speed(&mut self, song: &Song) {
+        self.speed = song.file().map_or(1.0, bookmarks::speed);
+        self.player.set_speed(self.speed);
+        #[cfg(feature = "mpris")]
+        self.mpris.set_rate(self.speed);
+    }
+
+    /// Keeps the speed of the current song for next time.
+    pub fn bookmarks_remember_speed(&mut self) {
+        let result = self
+            .bookmarks_file()
+            .map(|file| bookmarks::remember_speed(file, self.speed));
+        if let Some(Err(e)) = result {
+            self.mount_error_popup(&format!("Couldn't save the speed: {}", e));
+        }
+    }
+
+    /// Asks for the name of a bookmark at the current position, which is its name unless changed.
+    pub fn bookmark_input_show(&mut self) {
+        self.bookmark_position = self.bookmarks_time_pos();
+        let name = Song::duration_formatted_short(&self.bookmark_position);
+        self.mount_bookmark_input(&name);
+    }
+
+    pub fn bookmark_add(&mut self, name: &str) {
+        let result = self
+            .bookmarks_file()
+            .map(|file| bookmarks::add(file, name, self.bookmark_position));
+        if let Some(Err(e)) = result {
+            self.mount_error_popup(&format!("Couldn't add the bookmark: {}", e));
+        }
+        self.bookmarks_sync();
+    }
+
+    pub fn bookmark_delete(&mut self, index: usize) {
+        let result = self
+            .bookmarks_file()
+            .map(|file| bookmarks::remove(file, index));
+        if let Some(Err(e)) = result {
+            self.mount_error_popup(&format!("Couldn't delete the bookmark: {}", e));
+        }
+        self.bookmarks_sync();
+    }
+
+    pub fn bookmark_jump(&mut self, index: usize) {
+        let bookmark = self
+            .bookmarks_file()
+            .and_then(|file| bookmarks::list(file).into_iter().nth(index));
+        if let Some(Bookmark { position, .. }) = bookmark {
+            self.player.seek_to(Duration::from_secs(position)).ok();
+            self.player_sync_time_pos();
+            self.progress_update();
+            #[cfg(feature = "mpris")]
+            self.mpris_seeked();
+        }
+    }
+
+    pub fn bookmarks_sync(&mut self) {
+        let list = self
+            .bookmarks_file()
+            .map(bookmarks::list)
+            .unwrap_or_default();
+        let mut table = TableBuilder::default();
+        for (index, bookmark) in list.iter().enumerate() {
+            if index > 0 {
+                table.add_row();
+            }
+            table
+                .add_col(
+                    TextSpan::new(Song::duration_formatted_short(&Duration::from_secs(
+                        bookmark.position,
+                    )))
+                    .fg(Color::LightYellow),
+                )
+                .add_col(TextSpan::new(&bookmark.name));
+        }
+        if list.is_empty() {
+            table.add_col(TextSpan::from("No bookmarks"));
+        }
+        self.app
+            .attr(
+                &Id::BookmarksPopup,
+                Attribute::Content,
+                AttrValue::Table(table.build()),
+            )
+            .ok();
+
+        let song = self
+            .current_song
+            .as_ref()
+            .and_then(Song::name)
+            .unwrap_or("nothing playing");
+        let title = format!(" Bookmarks: {} ── a: add, d: delete, Enter: jump ", song);
+        self.app
+            .attr(
+                &Id::BookmarksPopup,
+                Attribute::Title,
+                AttrValue::Title((title, Alignment::Left)),
+            )
+            .ok();
+    }
+}
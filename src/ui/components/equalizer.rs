@@ -0,0 +1,196 @@
+use crate::config::{EqualizerBand, EqualizerBandKind};
+use crate::player::GeneralP;
+use crate::ui::{EQMsg, Id, Model, Msg};
+use tui_realm_stdlib::Table;
+use tuirealm::command::{Cmd, Direction};
+use tuirealm::event::{Key, KeyEvent, NoUserEvent};
+use tuirealm::props::{Alignment, BorderType, Borders, Color, TableBuilder, TextSpan};
+use tuirealm::{AttrValue, Attribute, Component, Event, MockComponent, State, StateValue};
+
+/// Gains of the bands are kept within +/- this many dB.
+const MAX_GAIN: f32 = 12.0;
+
+#[derive(MockComponent)]
+pub struct EqualizerPopup {
+    component: Table,
+}
+
+impl Default for EqualizerPopup {
+    fn default() -> Self {
+        Self {
+            component: Table::default()
+                .borders(
+                    Borders::default()
+                        .modifiers(BorderType::Rounded)
+                        .color(Color::Green),
+                )
+                .background(Color::Black)
+                .title("Equalizer", Alignment::Left)
+                .scroll(true)
+                .highlighted_color(Color::LightBlue)
+                .highlighted_str("\u{1f680}")
+                .rewind(true)
+                .step(1)
+                .row_height(1)
+                .headers(&["Type", "Freq", "Gain", ""])
+                .column_spacing(2)
+                .widths(&[16, 14, 14, 56])
+                .table(
+                    TableBuilder::default()
+                        .add_col(TextSpan::from("No bands"))
+                        .build(),
+                ),
+        }
+    }
+}
+
+impl Component<Msg, NoUserEvent> for EqualizerPopup {
+    fn on(&mut self, ev: Event<NoUserEvent>) -> Option<Msg> {
+        let band = match self.state() {
+            State::One(StateValue::Usize(index)) => index,
+            _ => 0,
+        };
+        match ev {
+            Event::Keyboard(KeyEvent {
+                code: Key::Esc | Key::Enter | Key::Char('q'),
+                ..
+            }) => return Some(Msg::Equalizer(EQMsg::PopupClose)),
+            Event::Keyboard(KeyEvent {
+                code: Key::Down | Key::Char('j'),
+                ..
+            }) => {
+                self.perform(Cmd::Move(Direction::Down));
+            }
+            Event::Keyboard(KeyEvent {
+                code: Key::Up | Key::Char('k'),
+                ..
+            }) => {
+                self.perform(Cmd::Move(Direction::Up));
+            }
+            Event::Keyboard(KeyEvent {
+                code: Key::Right | Key::Char('l'),
+                ..
+            }) => return Some(Msg::Equalizer(EQMsg::BandGain(band, 1.0))),
+            Event::Keyboard(KeyEvent {
+                code: Key::Left | Key::Char('h'),
+                ..
+            }) => return Some(Msg::Equalizer(EQMsg::BandGain(band, -1.0))),
+            Event::Keyboard(KeyEvent {
+                code: Key::Char('0'),
+                ..
+            }) => return Some(Msg::Equalizer(EQMsg::BandReset(band))),
+            Event::Keyboard(KeyEvent { code: Key::Tab, .. }) => {
+                return Some(Msg::Equalizer(EQMsg::PresetNext))
+            }
+            _ => return None,
+        }
+        Some(Msg::None)
+    }
+}
+
+impl Model {
+    pub fn equalizer_band_gain(&mut self, index: usize, delta: f32) {
+        if let Some(band) = self.equalizer_bands_mut().get_mut(index) {
+            band.gain = (band.gain + delta).clamp(-MAX_GAIN, MAX_GAIN);
+        }
+        self.equalizer_apply();
+    }
+
+    pub fn equalizer_band_reset(&mut self, index: usize) {
+        if let Some(band) = self.equalizer_bands_mut().get_mut(index) {
+            band.gain = 0.0;
+        }
+        self.equalizer_apply();
+    }
+
+    pub fn equalizer_preset_next(&mut self) {
+        let presets = &self.config.equalizer_presets;
+        let next = presets
+            .iter()
+            .position(|preset| preset.name == self.config.equalizer_selected)
+            .map_or(0, |index| (index + 1) % presets.len());
+        if let Some(preset) = presets.get(next) {
+            self.config.equalizer_selected = preset.name.clone();
+        }
+        self.equalizer_apply();
+    }
+
+    // Changes go to the selected preset, so they are saved with the config.
+    fn equalizer_bands_mut(&mut self) -> &mut [EqualizerBand] {
+        let selected = &self.config.equalizer_selected;
+        self.config
+            .equalizer_presets
+            .iter_mut()
+            .find(|preset| &preset.name == selected)
+            .map_or(&mut [], |preset| preset.bands.as_mut_slice())
+    }
+
+    fn equalizer_apply(&mut self) {
+        self.player.set_equalizer(&self.config.equalizer_bands());
+        self.equalizer_sync();
+    }
+
+    pub fn equalizer_sync(&mut self) {
+        let bands = self.config.equalizer_bands();
+        let mut table = TableBuilder::default();
+        for (index, band) in bands.iter().enumerate() {
+            if index > 0 {
+                table.add_row();
+            }
+            let kind = match band.kind {
+                EqualizerBandKind::LowShelf => "low shelf",
+                EqualizerBandKind::Peaking => "peaking",
+                EqualizerBandKind::HighShelf => "high shelf",
+            };
+            table
+                .add_col(TextSpan::new(kind))
+                .add_col(TextSpan::new(format!("{} Hz", band.freq)))
+                .add_col(TextSpan::new(format!("{:+.1} dB", band.gain)).fg(Color::LightYellow))
+                .add_col(TextSpan::new(gain_bar(band.gain)).fg(Color::Cyan));
+        }
+        if bands.is_empty() {
+            table.add_col(TextSpan::from("No bands"));
+        }
+        self.app
+            .attr(
+                &Id::EqualizerPopup,
+                Attribute::Content,
+                AttrValue::Table(table.build()),
+            )
+            .ok();
+
+        let title = format!(
+            " Equalizer: {} ── h/l: gain, 0: reset, Tab: next preset ",
+            self.config.equalizer_selected
+        );
+        self.app
+            .attr(
+                &Id::EqualizerPopup,
+                Attribute::Title,
+                AttrValue::Title((title, Alignment::Left)),
+            )
+            .ok();
+    }
+}
+
+// A bar growing left or right from the center, one cell per dB.
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+fn gain_bar(gain: f32) -> String {
+    let width = MAX_GAIN as usize;
+    let cells = gain.abs().round().min(MAX_GAIN) as usize;
+    if gain < 0.0 {
+        format!(
+            "{}{}|{}",
+            " ".repeat(width - cells),
+            "\u{2588}".repeat(cells),
+            " ".repeat(width)
+        )
+    } else {
+        format!(
+            "{}|{}{}",
+            " ".repeat(width),
+            "\u{2588}".repeat(cells),
+            " ".repeat(width - cells)
+        )
+    }
+}
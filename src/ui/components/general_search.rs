@@ -27,8 +27,14 @@
  */
 use super::{GSMsg, Id, Msg};
 
+use super::music_library::get_pin_yin;
+use crate::query::Fields;
+use crate::song::Song;
 use crate::ui::Model;
+use anyhow::Error;
 use if_chain::if_chain;
+use std::ffi::OsStr;
+use std::path::Path;
 use tui_realm_stdlib::{Input, Table};
 use tui_realm_treeview::TREE_INITIAL_NODE;
 use tuirealm::command::{Cmd, CmdResult, Direction, Position};
@@ -55,7 +61,10 @@ impl GSInputPopup {
                 )
                 // .invalid_style(Style::default().fg(Color::Red))
                 .input_type(InputType::Text)
-                .title("Search for: (support * and ?)", Alignment::Left),
+                .title(
+                    "Search for: (artist:x year:>=2000 duration:<5m -not OR \"phrase\")",
+                    Alignment::Left,
+                ),
             source,
         }
     }
@@ -123,69 +132,43 @@ pub enum Source {
     Library,
     Playlist,
 }
+/// Columns of the results. The file comes after them, not shown but read to locate a result.
+const HEADERS: [&str; 5] = ["Duration", "Artist", "Title", "Album", "Year"];
+const FILE_COLUMN: usize = HEADERS.len();
+
 impl GSTablePopup {
     pub fn new(source: Source) -> Self {
-        match source {
-            Source::Library => Self {
-                component: Table::default()
-                    .borders(
-                        Borders::default()
-                            .modifiers(BorderType::Rounded)
-                            .color(Color::Green),
-                    )
-                    // .foreground(Color::Yellow)
-                    .background(Color::Black)
-                    .title(
-                        "Results:(Enter: locate/l: load to playlist)",
-                        Alignment::Left,
-                    )
-                    .scroll(true)
-                    .highlighted_color(Color::LightBlue)
-                    .highlighted_str("\u{1f680}")
-                    // .highlighted_str("🚀")
-                    .rewind(false)
-                    .step(4)
-                    .row_height(1)
-                    .headers(&["index", "File name"])
-                    .column_spacing(3)
-                    .widths(&[5, 95])
-                    .table(
-                        TableBuilder::default()
-                            .add_col(TextSpan::from("Empty result."))
-                            .add_col(TextSpan::from("Loading..."))
-                            .build(),
-                    ),
-                source,
-            },
-
-            Source::Playlist => Self {
-                component: Table::default()
-                    .borders(
-                        Borders::default()
-                            .modifiers(BorderType::Rounded)
-                            .color(Color::Green),
-                    )
-                    // .foreground(Color::Yellow)
-                    .background(Color::Black)
-                    .title("Results:(Enter: locate/l: play selected)", Alignment::Left)
-                    .scroll(true)
-                    .highlighted_color(Color::LightBlue)
-                    .highlighted_str("\u{1f680}")
-                    // .highlighted_str("🚀")
-                    .rewind(false)
-                    .step(4)
-                    .row_height(1)
-                    .headers(&["Duration", "Artist", "Title"])
-                    .column_spacing(3)
-                    .widths(&[14, 30, 56])
-                    .table(
-                        TableBuilder::default()
-                            .add_col(TextSpan::from("Empty result."))
-                            .add_col(TextSpan::from("Loading..."))
-                            .build(),
-                    ),
-                source,
-            },
+        let title = match source {
+            Source::Library => "Results:(Enter: locate/l: load to playlist/1-5: sort)",
+            Source::Playlist => "Results:(Enter: locate/l: play selected/1-5: sort)",
+        };
+        Self {
+            component: Table::default()
+                .borders(
+                    Borders::default()
+                        .modifiers(BorderType::Rounded)
+                        .color(Color::Green),
+                )
+                // .foreground(Color::Yellow)
+                .background(Color::Black)
+                .title(title, Alignment::Left)
+                .scroll(true)
+                .highlighted_color(Color::LightBlue)
+                .highlighted_str("\u{1f680}")
+                // .highlighted_str("🚀")
+                .rewind(false)
+                .step(4)
+                .row_height(1)
+                .headers(&HEADERS)
+                .column_spacing(2)
+                .widths(&[10, 22, 34, 26, 8])
+                .table(
+                    TableBuilder::default()
+                        .add_col(TextSpan::from("Empty result."))
+                        .add_col(TextSpan::from("Loading..."))
+                        .build(),
+                ),
+            source,
         }
     }
 }
@@ -247,6 +230,20 @@ impl Component<Msg, NoUserEvent> for GSTablePopup {
                     return Some(Msg::GeneralSearch(GSMsg::PopupCloseOkPlaylistLocate))
                 }
             },
+            Event::Keyboard(KeyEvent {
+                code: Key::Char(c @ '1'..='5'),
+                ..
+            }) => {
+                let column = c as usize - '1' as usize;
+                match self.source {
+                    Source::Library => {
+                        return Some(Msg::GeneralSearch(GSMsg::PopupSortLibrary(column)))
+                    }
+                    Source::Playlist => {
+                        return Some(Msg::GeneralSearch(GSMsg::PopupSortPlaylist(column)))
+                    }
+                }
+            }
             _ => CmdResult::None,
         };
         match cmd_result {
@@ -258,7 +255,98 @@ impl Component<Msg, NoUserEvent> for GSTablePopup {
     }
 }
 
+/// The rows of the folders and the songs a search found, the songs sorted by `sort`, a
+/// column and whether it goes downwards.
+pub fn general_search_table(
+    folders: &[&str],
+    mut results: Vec<Fields>,
+    sort: Option<(usize, bool)>,
+) -> Vec<Vec<TextSpan>> {
+    if let Some((column, descending)) = sort {
+        match column {
+            0 => results.sort_by_key(|fields| fields.duration),
+            4 => results.sort_by_key(|fields| fields.year),
+            _ => results.sort_by_cached_key(|fields| get_pin_yin(column_text(fields, column))),
+        }
+        if descending {
+            results.reverse();
+        }
+    }
+
+    let mut table = TableBuilder::default();
+    for (idx, folder) in folders.iter().enumerate() {
+        if idx > 0 {
+            table.add_row();
+        }
+        let name = Path::new(folder)
+            .file_name()
+            .and_then(OsStr::to_str)
+            .unwrap_or(folder);
+        table
+            .add_col(TextSpan::new("[folder]"))
+            .add_col(TextSpan::new(""))
+            .add_col(TextSpan::new(name).bold())
+            .add_col(TextSpan::new(""))
+            .add_col(TextSpan::new(""))
+            .add_col(TextSpan::new(*folder));
+    }
+    for (idx, fields) in results.iter().enumerate() {
+        if idx + folders.len() > 0 {
+            table.add_row();
+        }
+        let duration = Song::duration_formatted_short(&fields.duration);
+        table
+            .add_col(TextSpan::new(format!("[{duration:^6.6}]")))
+            .add_col(TextSpan::new(column_text(fields, 1)).fg(Color::LightYellow))
+            .add_col(TextSpan::new(column_text(fields, 2)).bold())
+            .add_col(TextSpan::new(column_text(fields, 3)))
+            .add_col(TextSpan::new(
+                fields.year.map(|year| year.to_string()).unwrap_or_default(),
+            ))
+            .add_col(TextSpan::new(fields.file));
+    }
+    table.build()
+}
+
+/// A row telling what is wrong with the query, in place of results.
+pub fn general_search_error(error: &Error) -> Vec<Vec<TextSpan>> {
+    TableBuilder::default()
+        .add_col(TextSpan::from(""))
+        .add_col(TextSpan::from("Invalid query:").fg(Color::Red))
+        .add_col(TextSpan::from(error.to_string()))
+        .build()
+}
+
+fn column_text<'a>(fields: &Fields<'a>, column: usize) -> &'a str {
+    match column {
+        1 => fields.artist.unwrap_or("Unknown Artist"),
+        2 => fields.title.unwrap_or_else(|| {
+            Path::new(fields.file)
+                .file_name()
+                .and_then(OsStr::to_str)
+                .unwrap_or(fields.file)
+        }),
+        _ => fields.album.unwrap_or("Unknown Album"),
+    }
+}
+
 impl Model {
+    /// Sorts the results by `column`, or the other way when they already are.
+    pub const fn general_search_sort(&mut self, column: usize) {
+        self.general_search_sort = match self.general_search_sort {
+            Some((sorted, descending)) if sorted == column => Some((column, !descending)),
+            _ => Some((column, false)),
+        };
+    }
+
+    /// What is typed in the search input.
+    pub fn general_search_input(&self) -> String {
+        match self.app.state(&Id::GeneralSearchInput) {
+            Ok(State::One(StateValue::String(input))) => input,
+            _ => String::new(),
+        }
+    }
+
     pub fn general_search_update_show(&mut self, table: Vec<Vec<TextSpan>>) {
         self.app
             .attr(
@@ -274,7 +362,7 @@ impl Model {
             if let Ok(Some(AttrValue::Table(table))) =
                 self.app.query(&Id::GeneralSearchTable, Attribute::Content);
             if let Some(line) = table.get(index);
-            if let Some(text_span) = line.get(1);
+            if let Some(text_span) = line.get(FILE_COLUMN);
             then {
                 let node = &text_span.content;
                 assert!(self
@@ -295,7 +383,7 @@ impl Model {
             if let Ok(Some(AttrValue::Table(table))) =
                 self.app.query(&Id::GeneralSearchTable, Attribute::Content);
             if let Some(line) = table.get(index);
-            if let Some(text_span) = line.get(1);
+            if let Some(text_span) = line.get(FILE_COLUMN);
             let text = &text_span.content;
             then {
                 self.playlist_add(text);
@@ -312,7 +400,7 @@ impl Model {
             if let Ok(Some(AttrValue::Table(table))) =
                 self.app.query(&Id::GeneralSearchTable, Attribute::Content);
             if let Some(line) = table.get(result_index);
-            if let Some(file_name_text_span) = line.get(3);
+            if let Some(file_name_text_span) = line.get(FILE_COLUMN);
             let file_name = &file_name_text_span.content;
             then {
                 for (idx, item) in self.playlist_items.iter().enumerate() {
@@ -338,7 +426,7 @@ impl Model {
             if let Ok(Some(AttrValue::Table(table))) =
                     self.app.query(&Id::GeneralSearchTable, Attribute::Content);
             if let Some(line) = table.get(result_index);
-            if let Some(file_name_text_span) = line.get(3);
+            if let Some(file_name_text_span) = line.get(FILE_COLUMN);
             let file_name = &file_name_text_span.content;
             then {
                 for (idx, item) in self.playlist_items.iter().enumerate() {
@@ -44,6 +44,10 @@ pub struct Keys {
     pub global_player_volume_plus_2: KeyBind,
     pub global_player_volume_minus_1: KeyBind,
     pub global_player_volume_minus_2: KeyBind,
+    #[serde(default = "default_global_player_speed_up")]
+    pub global_player_speed_up: KeyBind,
+    #[serde(default = "default_global_player_speed_down")]
+    pub global_player_speed_down: KeyBind,
     pub global_help: KeyBind,
     pub global_player_seek_forward: KeyBind,
     pub global_player_seek_backward: KeyBind,
@@ -52,6 +56,16 @@ pub struct Keys {
     pub global_lyric_cycle: KeyBind,
     pub global_color_editor_open: KeyBind,
     pub global_key_editor_open: KeyBind,
+    #[serde(default = "default_global_equalizer_open")]
+    pub global_equalizer_open: KeyBind,
+    #[serde(default = "default_global_output_device_open")]
+    pub global_output_device_open: KeyBind,
+    #[serde(default = "default_global_bookmarks_open")]
+    pub global_bookmarks_open: KeyBind,
+    #[serde(default = "default_global_sleep_timer_open")]
+    pub global_sleep_timer_open: KeyBind,
+    #[serde(default = "default_global_smart_playlists_open")]
+    pub global_smart_playlists_open: KeyBind,
     pub library_load_dir: KeyBind,
     pub library_delete: KeyBind,
     pub library_yank: KeyBind,
@@ -59,6 +73,10 @@ pub struct Keys {
     pub library_search: KeyBind,
     pub library_search_youtube: KeyBind,
     pub library_tag_editor_open: KeyBind,
+    #[serde(default = "default_library_replaygain_scan")]
+    pub library_replaygain_scan: KeyBind,
+    #[serde(default = "default_library_switch_mode")]
+    pub library_switch_mode: KeyBind,
     pub playlist_delete: KeyBind,
     pub playlist_delete_all: KeyBind,
     pub playlist_shuffle: KeyBind,
@@ -269,6 +287,13 @@ impl Default for Keys {
                 code: Key::Char('K'),
                 modifiers: KeyModifiers::SHIFT,
             },
+            global_equalizer_open: default_global_equalizer_open(),
+            global_output_device_open: default_global_output_device_open(),
+            global_bookmarks_open: default_global_bookmarks_open(),
+            global_sleep_timer_open: default_global_sleep_timer_open(),
+            global_smart_playlists_open: default_global_smart_playlists_open(),
+            global_player_speed_up: default_global_player_speed_up(),
+            global_player_speed_down: default_global_player_speed_down(),
             library_load_dir: KeyBind {
                 code: Key::Char('L'),
                 modifiers: KeyModifiers::SHIFT,
@@ -297,6 +322,8 @@ impl Default for Keys {
                 code: Key::Char('t'),
                 modifiers: KeyModifiers::NONE,
             },
+            library_replaygain_scan: default_library_replaygain_scan(),
+            library_switch_mode: default_library_switch_mode(),
             playlist_delete: KeyBind {
                 code: Key::Char('d'),
                 modifiers: KeyModifiers::NONE,
@@ -329,6 +356,70 @@ impl Default for Keys {
     }
 }
 
+// keys added after a config was saved are missing from it
+const fn default_global_equalizer_open() -> KeyBind {
+    KeyBind {
+        code: Key::Char('E'),
+        modifiers: KeyModifiers::SHIFT,
+    }
+}
+
+const fn default_global_bookmarks_open() -> KeyBind {
+    KeyBind {
+        code: Key::Char('M'),
+        modifiers: KeyModifiers::SHIFT,
+    }
+}
+
+const fn default_global_player_speed_up() -> KeyBind {
+    KeyBind {
+        code: Key::Char(']'),
+        modifiers: KeyModifiers::NONE,
+    }
+}
+
+const fn default_global_player_speed_down() -> KeyBind {
+    KeyBind {
+        code: Key::Char('['),
+        modifiers: KeyModifiers::NONE,
+    }
+}
+
+const fn default_global_sleep_timer_open() -> KeyBind {
+    KeyBind {
+        code: Key::Char('Z'),
+        modifiers: KeyModifiers::SHIFT,
+    }
+}
+
+const fn default_global_smart_playlists_open() -> KeyBind {
+    KeyBind {
+        code: Key::Char('S'),
+        modifiers: KeyModifiers::SHIFT,
+    }
+}
+
+const fn default_global_output_device_open() -> KeyBind {
+    KeyBind {
+        code: Key::Char('O'),
+        modifiers: KeyModifiers::SHIFT,
+    }
+}
+
+const fn default_library_replaygain_scan() -> KeyBind {
+    KeyBind {
+        code: Key::Char('R'),
+        modifiers: KeyModifiers::SHIFT,
+    }
+}
+
+const fn default_library_switch_mode() -> KeyBind {
+    KeyBind {
+        code: Key::Char('m'),
+        modifiers: KeyModifiers::NONE,
+    }
+}
+
 #[derive(MockComponent)]
 pub struct KERadioOk {
     component: Radio,
@@ -28,6 +28,8 @@
 // -- modules
 // mod clock;
 // mod counter;
+mod bookmarks;
+mod equalizer;
 mod general_search;
 mod label;
 mod lyric;
@@ -35,6 +37,8 @@ mod music_library;
 mod playlist;
 mod popups;
 mod progress;
+mod sleep_timer;
+mod smart_playlists;
 // mod table_playlist;
 mod color_editor;
 mod key_editor;
@@ -45,16 +49,22 @@ mod youtube_search;
 // -- export
 // pub use clock::Clock;
 // pub use counter::{Digit, Letter};
-pub use general_search::{GSInputPopup, GSTablePopup, Source};
+pub use bookmarks::{BookmarkInputPopup, BookmarksPopup};
+pub use equalizer::EqualizerPopup;
+pub use general_search::{
+    general_search_error, general_search_table, GSInputPopup, GSTablePopup, Source,
+};
 pub use label::Label;
 pub use lyric::Lyric;
 pub use music_library::MusicLibrary;
 pub use playlist::Playlist;
 pub use popups::{
     DeleteConfirmInputPopup, DeleteConfirmRadioPopup, ErrorPopup, HelpPopup, MessagePopup,
-    QuitPopup,
+    OutputDevicePopup, QuitPopup,
 };
 pub use progress::Progress;
+pub use sleep_timer::{SleepTimer, SleepTimerPopup};
+pub use smart_playlists::{Row as SmartPlaylistRow, SmartPlaylistsPopup};
 // pub use table_playlist::Table;
 pub use youtube_search::{YSInputPopup, YSTablePopup};
 //Tag Edotor Controls
@@ -82,8 +92,9 @@ pub use tag_editor::{
 };
 pub use xywh::Xywh;
 
-use crate::player::GeneralP;
-use crate::ui::{CEMsg, GSMsg, Id, KEMsg, Loop, Model, Msg, PLMsg, Status, YSMsg};
+use crate::player::{GeneralP, MAX_SPEED, MIN_SPEED};
+use crate::song::Song;
+use crate::ui::{BMMsg, CEMsg, EQMsg, GSMsg, Id, KEMsg, Loop, Model, Msg, PLMsg, Status, YSMsg};
 use tui_realm_stdlib::Phantom;
 use tuirealm::event::NoUserEvent;
 use tuirealm::props::{Alignment, Borders, Color, Style};
@@ -107,6 +118,7 @@ impl GlobalListener {
 }
 
 impl Component<Msg, NoUserEvent> for GlobalListener {
+    #[allow(clippy::too_many_lines)]
     fn on(&mut self, ev: Event<NoUserEvent>) -> Option<Msg> {
         match ev {
             Event::WindowResize(..) => Some(Msg::UpdatePhoto),
@@ -149,18 +161,28 @@ impl Component<Msg, NoUserEvent> for GlobalListener {
             {
                 Some(Msg::PlayerVolumeUp)
             }
+            Event::Keyboard(keyevent)
+                if keyevent == self.keys.global_player_speed_up.key_event() =>
+            {
+                Some(Msg::PlayerSpeedUp)
+            }
+            Event::Keyboard(keyevent)
+                if keyevent == self.keys.global_player_speed_down.key_event() =>
+            {
+                Some(Msg::PlayerSpeedDown)
+            }
             Event::Keyboard(keyevent) if keyevent == self.keys.global_help.key_event() => {
                 Some(Msg::HelpPopupShow)
             }
             Event::Keyboard(keyevent)
                 if keyevent == self.keys.global_player_seek_forward.key_event() =>
             {
-                Some(Msg::PlayerSeek(5))
+                Some(Msg::PlayerSeek(1))
             }
             Event::Keyboard(keyevent)
                 if keyevent == self.keys.global_player_seek_backward.key_event() =>
             {
-                Some(Msg::PlayerSeek(-5))
+                Some(Msg::PlayerSeek(-1))
             }
             Event::Keyboard(keyevent)
                 if keyevent == self.keys.global_lyric_adjust_forward.key_event() =>
@@ -186,6 +208,31 @@ impl Component<Msg, NoUserEvent> for GlobalListener {
             {
                 Some(Msg::KeyEditor(KEMsg::KeyEditorShow))
             }
+            Event::Keyboard(keyevent)
+                if keyevent == self.keys.global_equalizer_open.key_event() =>
+            {
+                Some(Msg::Equalizer(EQMsg::PopupShow))
+            }
+            Event::Keyboard(keyevent)
+                if keyevent == self.keys.global_output_device_open.key_event() =>
+            {
+                Some(Msg::OutputDevicePopupShow)
+            }
+            Event::Keyboard(keyevent)
+                if keyevent == self.keys.global_bookmarks_open.key_event() =>
+            {
+                Some(Msg::Bookmarks(BMMsg::PopupShow))
+            }
+            Event::Keyboard(keyevent)
+                if keyevent == self.keys.global_sleep_timer_open.key_event() =>
+            {
+                Some(Msg::SleepTimerPopupShow)
+            }
+            Event::Keyboard(keyevent)
+                if keyevent == self.keys.global_smart_playlists_open.key_event() =>
+            {
+                Some(Msg::SmartPlaylistsPopupShow)
+            }
 
             _ => None,
         }
@@ -233,6 +280,14 @@ impl Model {
                 SubEventClause::Keyboard(keys.global_player_volume_plus_2.key_event()),
                 SubClause::Always,
             ),
+            Sub::new(
+                SubEventClause::Keyboard(keys.global_player_speed_up.key_event()),
+                SubClause::Always,
+            ),
+            Sub::new(
+                SubEventClause::Keyboard(keys.global_player_speed_down.key_event()),
+                SubClause::Always,
+            ),
             Sub::new(
                 SubEventClause::Keyboard(keys.global_help.key_event()),
                 SubClause::Always,
@@ -265,21 +320,61 @@ impl Model {
                 SubEventClause::Keyboard(keys.global_key_editor_open.key_event()),
                 SubClause::Always,
             ),
+            Sub::new(
+                SubEventClause::Keyboard(keys.global_equalizer_open.key_event()),
+                SubClause::Always,
+            ),
+            Sub::new(
+                SubEventClause::Keyboard(keys.global_output_device_open.key_event()),
+                SubClause::Always,
+            ),
+            Sub::new(
+                SubEventClause::Keyboard(keys.global_bookmarks_open.key_event()),
+                SubClause::Always,
+            ),
+            Sub::new(
+                SubEventClause::Keyboard(keys.global_sleep_timer_open.key_event()),
+                SubClause::Always,
+            ),
+            Sub::new(
+                SubEventClause::Keyboard(keys.global_smart_playlists_open.key_event()),
+                SubClause::Always,
+            ),
             Sub::new(SubEventClause::WindowResize, SubClause::Always),
         ]
     }
     pub fn player_next(&mut self) {
+        self.player_start_next(false);
+    }
+
+    /// Moves on to the next song of the playlist. With `handoff`, the player has already
+    /// started the enqueued one by itself.
+    pub fn player_start_next(&mut self, handoff: bool) {
+        self.bookmarks_remember();
+        let enqueued = self.enqueued_song.take();
         if self.playlist_items.is_empty() {
             return;
         }
-        self.time_pos = 0;
-        self.time_pos_elapsed = std::time::Instant::now();
         self.status = Some(Status::Running);
         if let Some(song) = self.playlist_items.pop_front() {
             if let Some(file) = song.file() {
-                self.player.add_and_play(file);
+                self.time_pos = 0;
+                // the player is already on the enqueued song, its duration has been reported
+                self.library_db.lock().unwrap().played(file);
+                let resume = self.bookmarks_resume_position(&song);
+                self.bookmarks_load_speed(&song);
+                if handoff && enqueued.as_deref() == Some(file) {
+                    if let Some(position) = resume {
+                        self.player.seek_to(position).ok();
+                    }
+                } else {
+                    self.duration = 0;
+                    self.player
+                        .add_and_play(file, song.replaygain().factor(self.config.replaygain));
+                    self.resume_position = resume;
+                }
                 #[cfg(feature = "mpris")]
-                self.mpris.add_and_play(file);
+                self.mpris.add_and_play(&song);
             }
             match self.config.loop_mode {
                 Loop::Playlist => self.playlist_items.push_back(song.clone()),
@@ -287,6 +382,34 @@ impl Model {
                 Loop::Queue => {}
             }
             self.playlist_sync();
+            // the playlist has the tags from the library database, without lyrics or picture
+            let song = song
+                .file()
+                .and_then(|file| Song::read_from_path(file).ok())
+                .unwrap_or(song);
+            self.current_song = Some(song);
+            if let Err(e) = self.update_photo() {
+                self.mount_error_popup(format!("update photo error: {}", e).as_str());
+            };
+            self.progress_update_title();
+            self.update_playing_song();
+        }
+    }
+
+    /// Shows `file` as the current song, when the daemon moved on to it by itself.
+    pub fn player_follow(&mut self, file: &str) {
+        if self.current_song.as_ref().and_then(Song::file) == Some(file) {
+            return;
+        }
+        if let Ok(song) = Song::read_from_path(file) {
+            self.library_db.lock().unwrap().played(file);
+            #[cfg(feature = "mpris")]
+            self.mpris.add_and_play(&song);
+            // the daemon sets the speed of the song itself
+            self.speed = song.file().map_or(1.0, crate::bookmarks::speed);
+            #[cfg(feature = "mpris")]
+            self.mpris.set_rate(self.speed);
+            self.time_pos = 0;
             self.current_song = Some(song);
             if let Err(e) = self.update_photo() {
                 self.mount_error_popup(format!("update photo error: {}", e).as_str());
@@ -328,16 +451,52 @@ impl Model {
         }
     }
 
-    pub fn player_seek(&mut self, offset: i64) {
-        self.player.seek(offset).ok();
-        if let Ok((_, time_pos, _)) = self.player.get_progress() {
-            if let Some(t) = std::time::Instant::now()
-                .checked_sub(std::time::Duration::from_secs(time_pos.try_into().unwrap()))
-            {
-                self.time_pos_elapsed = t;
+    /// Changes the speed by `step`, and keeps it for the current song.
+    pub fn player_change_speed(&mut self, step: f32) {
+        self.player_set_speed(((self.speed + step) * 10.0).round() / 10.0);
+    }
+
+    /// Plays the current song at `speed` from now on, and next time too.
+    pub fn player_set_speed(&mut self, speed: f32) {
+        self.speed = speed.clamp(MIN_SPEED, MAX_SPEED);
+        self.player.set_speed(self.speed);
+        #[cfg(feature = "mpris")]
+        self.mpris.set_rate(self.speed);
+        self.bookmarks_remember_speed();
+        self.progress_update_title();
+    }
+
+    /// Seek by `steps` times the configured seek step, backward when negative.
+    #[allow(clippy::cast_possible_wrap)]
+    pub fn player_seek(&mut self, steps: i64) {
+        self.player
+            .seek(steps * self.config.seek_step_secs as i64)
+            .ok();
+        self.player_sync_time_pos();
+        self.progress_update();
+        #[cfg(feature = "mpris")]
+        self.mpris_seeked();
+    }
+
+    /// Preload the song after the current one, so the player can join them without a gap.
+    pub fn player_enqueue_next(&mut self) {
+        if self.enqueued_song.is_some() {
+            return;
+        }
+        if let Some(song) = self.playlist_items.front() {
+            if let Some(file) = song.file() {
+                let gain = song.replaygain().factor(self.config.replaygain);
+                if self.player.enqueue_next(file, gain).is_ok() {
+                    self.enqueued_song = Some(file.to_string());
+                }
             }
         }
-        self.progress_update();
+    }
+
+    pub fn player_sync_time_pos(&mut self) {
+        if let Ok((_, time_pos, _)) = self.player.get_progress() {
+            self.time_pos = time_pos;
+        }
     }
 }
 ///
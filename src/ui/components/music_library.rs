@@ -1,14 +1,21 @@
-use crate::ui::components::{Keys, StyleColorSymbol};
-use crate::ui::model::MAX_DEPTH;
+use crate::config::LibraryMode;
+use crate::library_db::{LibraryDb, Track};
+use crate::player::loudness::{self, Loudness};
+use crate::query::{Fields, Query};
+use crate::ui::components::{general_search_error, general_search_table, Keys, StyleColorSymbol};
+use crate::ui::model::{UpdateComponents, MAX_DEPTH};
 use crate::ui::{Id, LIMsg, Model, Msg, TEMsg, YSMsg};
 use anyhow::{bail, Result};
 use if_chain::if_chain;
+use std::collections::BTreeMap;
 use std::fs::{remove_dir_all, remove_file, rename};
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::thread;
 use tui_realm_treeview::{Node, Tree, TreeView, TREE_CMD_CLOSE, TREE_CMD_OPEN, TREE_INITIAL_NODE};
 use tuirealm::command::{Cmd, CmdResult, Direction, Position};
 use tuirealm::event::{Key, KeyEvent, KeyModifiers, NoUserEvent};
-use tuirealm::props::{Alignment, BorderType, Borders, TableBuilder, TextSpan};
+use tuirealm::props::{Alignment, BorderType, Borders};
 use tuirealm::tui::style::Color;
 use tuirealm::{AttrValue, Attribute, Component, Event, MockComponent, State, StateValue};
 
@@ -70,8 +77,7 @@ impl Component<Msg, NoUserEvent> for MusicLibrary {
                 modifiers: KeyModifiers::NONE,
             }) => {
                 let current_node = self.component.tree_state().selected().unwrap();
-                let p: &Path = Path::new(current_node);
-                if p.is_dir() {
+                if is_group(self.component.tree(), current_node) {
                     self.perform(Cmd::Custom(TREE_CMD_OPEN))
                 } else {
                     return Some(Msg::Playlist(crate::ui::PLMsg::Add(
@@ -81,8 +87,7 @@ impl Component<Msg, NoUserEvent> for MusicLibrary {
             }
             Event::Keyboard(keyevent) if keyevent == self.keys.global_right.key_event() => {
                 let current_node = self.component.tree_state().selected().unwrap();
-                let p: &Path = Path::new(current_node);
-                if p.is_dir() {
+                if is_group(self.component.tree(), current_node) {
                     self.perform(Cmd::Custom(TREE_CMD_OPEN))
                 } else {
                     return Some(Msg::Playlist(crate::ui::PLMsg::Add(
@@ -107,8 +112,7 @@ impl Component<Msg, NoUserEvent> for MusicLibrary {
 
             Event::Keyboard(keyevent) if keyevent == self.keys.library_load_dir.key_event() => {
                 let current_node = self.component.tree_state().selected().unwrap();
-                let p: &Path = Path::new(current_node);
-                if p.is_dir() {
+                if is_group(self.component.tree(), current_node) {
                     return Some(Msg::Playlist(crate::ui::PLMsg::Add(
                         current_node.to_string(),
                     )));
@@ -150,6 +154,14 @@ impl Component<Msg, NoUserEvent> for MusicLibrary {
             Event::Keyboard(keyevent) if keyevent == self.keys.library_paste.key_event() => {
                 return Some(Msg::Library(LIMsg::Paste))
             }
+            Event::Keyboard(keyevent)
+                if keyevent == self.keys.library_replaygain_scan.key_event() =>
+            {
+                return Some(Msg::Library(LIMsg::ReplayGainScan))
+            }
+            Event::Keyboard(keyevent) if keyevent == self.keys.library_switch_mode.key_event() => {
+                return Some(Msg::Library(LIMsg::SwitchMode))
+            }
             Event::Keyboard(keyevent) if keyevent == self.keys.library_search.key_event() => {
                 return Some(Msg::GeneralSearch(crate::ui::GSMsg::PopupShowLibrary))
             }
@@ -178,10 +190,132 @@ impl Component<Msg, NoUserEvent> for MusicLibrary {
     }
 }
 
+/// Separates the names in the ids of the groups of the tag views. No path has it, so these
+/// ids are never taken for files.
+const GROUP_SEPARATOR: char = '\0';
+
+/// Whether `id` is a folder, or a group of songs in a tag view.
+fn is_group(tree: &Tree, id: &str) -> bool {
+    Path::new(id).is_dir()
+        || tree
+            .root()
+            .query(&id.to_string())
+            .is_some_and(|node| !node.is_leaf())
+}
+
+/// Groups of a tag view by their sort key, with their name and what's in them.
+type Groups<T> = BTreeMap<String, (String, T)>;
+
+// The two levels of groups `track` is in for `mode`.
+fn group_names(mode: LibraryMode, track: &Track) -> (String, String) {
+    let name =
+        |tag: &Option<String>, unknown: &str| tag.clone().unwrap_or_else(|| unknown.to_string());
+    let artist = name(&track.artist, "Unknown Artist");
+    let album = name(&track.album, "Unknown Album");
+    match mode {
+        // the folders aren't grouped by tags
+        LibraryMode::Directory | LibraryMode::Artist => (artist, album),
+        LibraryMode::AlbumArtist => (track.album_artist.clone().unwrap_or(artist), album),
+        LibraryMode::Genre => (name(&track.genre, "Unknown Genre"), artist),
+        LibraryMode::Year => track.year.map_or_else(
+            || ("Unknown Year".to_string(), "Unknown Year".to_string()),
+            |year| (format!("{}s", year - year.rem_euclid(10)), year.to_string()),
+        ),
+    }
+}
+
+fn track_label(track: &Track) -> String {
+    let title = track.title.clone().unwrap_or_else(|| {
+        Path::new(&track.file)
+            .file_name()
+            .map_or_else(String::new, |name| name.to_string_lossy().into_owned())
+    });
+    match track.number {
+        Some(number) => format!("{:02}. {}", number, title),
+        None => title,
+    }
+}
+
 impl Model {
+    /// The library tree of the songs in `p`, arranged by `mode`.
+    pub fn library_tree(p: &Path, mode: LibraryMode, db: &LibraryDb) -> Tree {
+        if mode == LibraryMode::Directory {
+            Tree::new(Self::library_dir_tree(p, MAX_DEPTH))
+        } else {
+            Tree::new(Self::library_tag_tree(p, mode, db))
+        }
+    }
+
+    /// The songs of `p` grouped twice by their tags, as `mode` says. The groups are sorted by
+    /// name, the songs by album, disc, track number and title.
+    fn library_tag_tree(p: &Path, mode: LibraryMode, db: &LibraryDb) -> Node {
+        let mut tracks: Vec<&Track> = db.tracks_in(p).collect();
+        tracks.sort_by_cached_key(|track| {
+            (
+                track.album.as_deref().map(get_pin_yin),
+                track.disc,
+                track.number,
+                get_pin_yin(&track_label(track)),
+            )
+        });
+        let mut groups: Groups<Groups<Vec<&Track>>> = BTreeMap::new();
+        for track in tracks {
+            let (outer, inner) = group_names(mode, track);
+            groups
+                .entry(get_pin_yin(&outer))
+                .or_insert_with(|| (outer, BTreeMap::new()))
+                .1
+                .entry(get_pin_yin(&inner))
+                .or_insert_with(|| (inner, Vec::new()))
+                .1
+                .push(track);
+        }
+
+        let root_id = format!("{}{}", GROUP_SEPARATOR, mode);
+        let mut root = Node::new(root_id.clone(), mode.to_string());
+        for (outer, inner_groups) in groups.into_values() {
+            let outer_id = format!("{}{}{}", root_id, GROUP_SEPARATOR, outer);
+            let mut outer_node = Node::new(outer_id.clone(), outer);
+            for (inner, tracks) in inner_groups.into_values() {
+                let inner_id = format!("{}{}{}", outer_id, GROUP_SEPARATOR, inner);
+                let mut inner_node = Node::new(inner_id, inner);
+                for track in tracks {
+                    inner_node.add_child(Node::new(track.file.clone(), track_label(track)));
+                }
+                outer_node.add_child(inner_node);
+            }
+            root.add_child(outer_node);
+        }
+        root
+    }
+
+    /// The files of the group `id` of a tag view, `None` when it's a file or folder.
+    pub fn library_group_files(&self, id: &str) -> Option<Vec<String>> {
+        if !id.starts_with(GROUP_SEPARATOR) {
+            return None;
+        }
+        let node = self.tree.root().query(&id.to_string())?;
+        Some(
+            node.find(&|node: &Node| node.is_leaf() && !node.id().starts_with(GROUP_SEPARATOR))
+                .into_iter()
+                .map(|node| node.id().clone())
+                .collect(),
+        )
+    }
+
+    /// Shows the library in the next arrangement of `LibraryMode`.
+    pub fn library_switch_mode(&mut self) {
+        self.config.library_mode = self.config.library_mode.next();
+        self.library_reload_tree();
+    }
+
     pub fn library_scan_dir(&mut self, p: &Path) {
         self.path = p.to_path_buf();
-        self.tree = Tree::new(Self::library_dir_tree(p, MAX_DEPTH));
+        self.tree = Self::library_tree(
+            p,
+            self.config.library_mode,
+            &self.library_db.lock().unwrap(),
+        );
     }
 
     pub fn library_upper_dir(&self) -> Option<PathBuf> {
@@ -241,8 +375,41 @@ impl Model {
         }
     }
 
+    /// Brings the library database up to date with the shown folder in the background.
+    pub fn library_db_scan(&self) {
+        let db = Arc::clone(&self.library_db);
+        let root = self.path.clone();
+        let tx = self.sender.clone();
+        thread::spawn(move || {
+            if let Ok(changed) = LibraryDb::scan(&db, &root) {
+                if changed > 0 {
+                    tx.send(UpdateComponents::LibraryDbUpdated).ok();
+                }
+            }
+        });
+    }
+
     pub fn library_reload_tree(&mut self) {
-        self.tree = Tree::new(Self::library_dir_tree(self.path.as_ref(), MAX_DEPTH));
+        self.library_db_scan();
+        self.library_rebuild_tree();
+    }
+
+    /// Shows the library again as it changed, keeping the selected node and the focus. With a
+    /// popup open, that waits until it's closed.
+    pub fn library_refresh(&mut self) {
+        self.library_outdated = !matches!(self.app.focus(), Some(Id::Library | Id::Playlist));
+        if !self.library_outdated {
+            self.library_rebuild_tree();
+        }
+    }
+
+    /// Shows the library again as the files and the database have it now.
+    pub fn library_rebuild_tree(&mut self) {
+        self.tree = Self::library_tree(
+            &self.path,
+            self.config.library_mode,
+            &self.library_db.lock().unwrap(),
+        );
         let current_node = match self.app.state(&Id::Library).ok().unwrap() {
             State::One(StateValue::String(id)) => Some(id),
             _ => None,
@@ -318,6 +485,12 @@ impl Model {
                 let p: &Path = Path::new(node_id.as_str());
                 if p.is_file() {
                     remove_file(p)?;
+                    self.library_db.lock().unwrap().remove(&node_id);
+                } else if let Some(files) = self.library_group_files(&node_id) {
+                    for file in files {
+                        remove_file(&file)?;
+                        self.library_db.lock().unwrap().remove(&file);
+                    }
                 } else {
                     p.canonicalize()?;
                     remove_dir_all(p)?;
@@ -352,6 +525,58 @@ impl Model {
         Ok(())
     }
 
+    /// Measures the selected song, or the songs of the selected folder as one album, and writes
+    /// their `ReplayGain` tags in the background.
+    pub fn library_replaygain_scan(&self) {
+        if let Ok(State::One(StateValue::String(node_id))) = self.app.state(&Id::Library) {
+            let p: &Path = Path::new(node_id.as_str());
+            let group = self.library_group_files(&node_id);
+            let album = p.is_dir() || group.is_some();
+            let files = if p.is_dir() {
+                Self::library_dir_children(p)
+            } else {
+                group.unwrap_or_else(|| vec![node_id])
+            };
+            let tx = self.sender.clone();
+
+            thread::spawn(move || {
+                let total = files.len();
+                let mut measured = Vec::new();
+                for (index, file) in files.into_iter().enumerate() {
+                    tx.send(UpdateComponents::ReplayGainScanProgress((index, total)))
+                        .ok();
+                    // files that don't decode, like covers or lyrics, are skipped
+                    if let Ok(loudness) = Loudness::measure(Path::new(&file)) {
+                        measured.push((file, loudness));
+                    }
+                }
+                if measured.is_empty() {
+                    tx.send(UpdateComponents::ReplayGainScanError(
+                        "no song to scan".to_string(),
+                    ))
+                    .ok();
+                    return;
+                }
+
+                let album_loudness =
+                    album.then(|| Loudness::merge(measured.iter().map(|(_, l)| l)));
+                for (file, track_loudness) in &measured {
+                    let gain = loudness::replaygain(track_loudness, album_loudness.as_ref());
+                    if let Err(e) = gain.save_to_path(Path::new(file)) {
+                        tx.send(UpdateComponents::ReplayGainScanError(format!(
+                            "{}: {}",
+                            file, e
+                        )))
+                        .ok();
+                        return;
+                    }
+                }
+                tx.send(UpdateComponents::ReplayGainScanCompleted(measured.len()))
+                    .ok();
+            });
+        }
+    }
+
     pub fn library_yank(&mut self) {
         if let Ok(State::One(StateValue::String(node_id))) = self.app.state(&Id::Library) {
             self.yanked_node_id = Some(node_id);
@@ -363,6 +588,8 @@ impl Model {
             if let  Ok(State::One(StateValue::String(new_id))) = self.app.state(&Id::Library);
             if let Some(old_id) = self.yanked_node_id.as_ref();
             let p: &Path = Path::new(new_id.as_str());
+            // not onto a group of a tag view
+            if p.exists();
             let pold: &Path = Path::new(old_id.as_str());
             if let Some(p_parent) = p.parent();
             if let Some(pold_filename) = pold.file_name();
@@ -385,28 +612,25 @@ impl Model {
     }
 
     pub fn library_update_search(&mut self, input: &str) {
-        let mut table: TableBuilder = TableBuilder::default();
-        let root = self.tree.root();
-        let p: &Path = Path::new(root.id());
-        let all_items = walkdir::WalkDir::new(p).follow_links(true);
-        let mut idx = 0;
-        let search = format!("*{}*", input.to_lowercase());
-        for record in all_items.into_iter().filter_map(std::result::Result::ok) {
-            let file_name = record.path();
-            if wildmatch::WildMatch::new(&search)
-                .matches(&file_name.to_string_lossy().to_lowercase())
-            {
-                if idx > 0 {
-                    table.add_row();
-                }
-                idx += 1;
+        let table = match Query::parse(input) {
+            Ok(query) => {
+                let db = self.library_db.lock().unwrap();
+                let folders: Vec<&str> = db
+                    .folders_in(&self.path)
+                    .into_iter()
+                    .filter(|folder| query.matches(&Fields::folder(folder)))
+                    .collect();
+                let results = db
+                    .tracks_in(&self.path)
+                    .map(Fields::from)
+                    .filter(|fields| query.matches(fields))
+                    .collect();
+                let table = general_search_table(&folders, results, self.general_search_sort);
+                drop(db);
                 table
-                    .add_col(TextSpan::new(idx.to_string()))
-                    .add_col(TextSpan::new(file_name.to_string_lossy()));
             }
-        }
-        let table = table.build();
-
+            Err(e) => general_search_error(&e),
+        };
         self.general_search_update_show(table);
     }
 }
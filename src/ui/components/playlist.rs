@@ -1,10 +1,15 @@
 // use crate::song::Song;
 use crate::{
     config::get_app_config_path,
+    daemon::protocol::Request,
+    library_db::LibraryDb,
+    player::GeneralP,
+    query::{Fields, Query},
+    session::Session,
     song::Song,
     ui::{
-        components::{Keys, StyleColorSymbol},
-        GSMsg, Id, Loop, Model, Msg, PLMsg,
+        components::{general_search_error, general_search_table, Keys, StyleColorSymbol},
+        GSMsg, Id, Loop, Model, Msg, PLMsg, Status,
     },
 };
 use anyhow::Result;
@@ -14,8 +19,9 @@ use std::collections::VecDeque;
 use std::fs::File;
 use std::io::{BufRead, BufReader, Write};
 use std::path::Path;
+use std::sync::Arc;
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use tui_realm_stdlib::Table;
 use tuirealm::command::{Cmd, CmdResult, Direction, Position};
 use tuirealm::props::{Alignment, BorderType, PropPayload, PropValue, TableBuilder, TextSpan};
@@ -191,8 +197,13 @@ impl Model {
 
         assert!(self.app.active(&Id::Library).is_ok());
     }
-    fn playlist_add_item(&mut self, current_node: &str, add_playlist_front: bool) -> Result<()> {
-        match Song::read_from_path(current_node) {
+    pub fn playlist_add_item(
+        &mut self,
+        current_node: &str,
+        add_playlist_front: bool,
+    ) -> Result<()> {
+        let song = LibraryDb::song(&self.library_db, current_node);
+        match song {
             Ok(item) => {
                 if add_playlist_front {
                     self.playlist_items.push_front(item);
@@ -205,12 +216,12 @@ impl Model {
         }
         Ok(())
     }
-    fn playlist_add_items(&mut self, p: &Path) {
-        let new_items = Self::library_dir_children(p);
+    fn playlist_add_items(&mut self, new_items: &[String]) {
         let mut index = 0;
-        for s in &new_items {
+        for s in new_items {
             if self.config.add_playlist_front {
-                match Song::read_from_path(s) {
+                let song = LibraryDb::song(&self.library_db, s);
+                match song {
                     Ok(item) => {
                         self.playlist_items.insert(index, item);
                         index += 1;
@@ -228,19 +239,36 @@ impl Model {
     }
     pub fn playlist_add(&mut self, current_node: &str) {
         let p: &Path = Path::new(&current_node);
+        if let Some(files) = self.library_group_files(current_node) {
+            self.playlist_add_items(&files);
+            return;
+        }
         if !p.exists() {
             return;
         }
 
         if p.is_dir() {
-            self.playlist_add_items(p);
+            self.playlist_add_items(&Self::library_dir_children(p));
         } else if let Err(e) = self.playlist_add_item(current_node, self.config.add_playlist_front)
         {
             self.mount_error_popup(format!("Add Playlist error: {}", e).as_str());
         }
     }
 
+    /// Shows the changed playlist, and hands it to the daemon when attached to one.
     pub fn playlist_sync(&mut self) {
+        if let Some(link) = &self.daemon {
+            let files = self
+                .playlist_items
+                .iter()
+                .filter_map(|song| song.file().map(ToString::to_string))
+                .collect();
+            link.send(&Request::SetPlaylist { files });
+        }
+        self.playlist_sync_table();
+    }
+
+    pub fn playlist_sync_table(&mut self) {
         let mut table: TableBuilder = TableBuilder::default();
 
         for (idx, record) in self.playlist_items.iter().enumerate() {
@@ -305,7 +333,8 @@ impl Model {
         Ok(())
     }
 
-    pub fn playlist_load(&mut self) -> Result<()> {
+    /// Loads the saved playlist, turned to where `session` left off.
+    pub fn playlist_load(&mut self, session: Option<Session>) -> Result<()> {
         let mut path = get_app_config_path()?;
         path.push("playlist.log");
 
@@ -322,14 +351,19 @@ impl Model {
             .collect();
 
         let tx = self.sender_playlist_items.clone();
+        let db = Arc::clone(&self.library_db);
 
         thread::spawn(move || {
             let mut playlist_items = VecDeque::new();
             for line in &lines {
-                if let Ok(s) = Song::read_from_path(line) {
+                let song = LibraryDb::song(&db, line);
+                if let Ok(s) = song {
                     playlist_items.push_back(s);
                 };
             }
+            if let Some(session) = session {
+                session.align(&mut playlist_items, Song::file);
+            }
             tx.send(playlist_items).ok();
         });
 
@@ -344,6 +378,49 @@ impl Model {
         Ok(())
     }
 
+    #[allow(clippy::cast_sign_loss)]
+    pub fn session_save(&mut self) -> Result<()> {
+        self.session_saved = Instant::now();
+        let file = match self.status {
+            Some(Status::Running | Status::Paused) => {
+                self.current_song.as_ref().and_then(Song::file)
+            }
+            _ => None,
+        };
+        Session::new(
+            file,
+            Duration::from_secs(self.time_pos.max(0) as u64),
+            matches!(self.status, Some(Status::Paused)),
+            &self.config.loop_mode,
+            self.playlist_items.iter().map(Song::file),
+        )
+        .save()
+    }
+
+    /// Plays the song of `session` from where it was, paused if it was or if configured so.
+    pub fn session_restore(&mut self, session: &Session) {
+        self.config.loop_mode = session.loop_mode.clone();
+        #[cfg(feature = "mpris")]
+        self.mpris.set_loop_mode(&self.config.loop_mode);
+        if let Some((file, song)) = session
+            .file
+            .as_deref()
+            .and_then(|file| Some((file, Song::read_from_path(file).ok()?)))
+        {
+            self.bookmarks_load_speed(&song);
+            self.player
+                .add_and_play(file, song.replaygain().factor(self.config.replaygain));
+            self.resume_position =
+                Some(Duration::from_secs(session.position)).filter(|p| !p.is_zero());
+            self.status = Some(Status::Running);
+            self.player_follow(file);
+            self.time_pos = i64::try_from(session.position).unwrap_or_default();
+            if session.paused || self.config.resume_paused {
+                self.player_toggle_pause();
+            }
+        }
+    }
+
     pub fn playlist_shuffle(&mut self) {
         let mut rng = thread_rng();
         self.playlist_items.make_contiguous().shuffle(&mut rng);
@@ -361,6 +438,38 @@ impl Model {
         self.playlist_sync();
         // assert!(self.app.active(&Id::Library).is_ok());
     }
+
+    /// Follows the songs of the playlist to where they were renamed or moved to, and drops
+    /// the ones that are gone.
+    pub fn playlist_follow_library(&mut self, renames: &[(String, String)]) {
+        let mut changed = false;
+        for song in &mut self.playlist_items {
+            let new_file = song.file().and_then(|file| {
+                renames.iter().find_map(|(from, to)| {
+                    let rest = Path::new(file).strip_prefix(from).ok()?;
+                    Some(if rest.as_os_str().is_empty() {
+                        to.clone()
+                    } else {
+                        Path::new(to).join(rest).to_string_lossy().into_owned()
+                    })
+                })
+            });
+            if let Some(file) = new_file {
+                let moved = LibraryDb::song(&self.library_db, &file);
+                if let Ok(moved) = moved {
+                    *song = moved;
+                    changed = true;
+                }
+            }
+        }
+        let len = self.playlist_items.len();
+        self.playlist_items
+            .retain(|song| song.file().is_some_and(|file| Path::new(file).exists()));
+        if changed || self.playlist_items.len() != len {
+            self.playlist_sync();
+        }
+    }
+
     pub fn playlist_update_title(&mut self) {
         let mut duration = Duration::from_secs(0);
         for v in &self.playlist_items {
@@ -413,6 +522,13 @@ impl Model {
                 }
             }
         };
+        #[cfg(feature = "mpris")]
+        self.mpris.set_loop_mode(&self.config.loop_mode);
+        if let Some(link) = &self.daemon {
+            link.send(&Request::SetLoopMode {
+                mode: self.config.loop_mode.clone(),
+            });
+        }
         self.playlist_sync();
         self.playlist_update_title();
     }
@@ -426,44 +542,18 @@ impl Model {
         }
     }
     pub fn playlist_update_search(&mut self, input: &str) {
-        let mut table: TableBuilder = TableBuilder::default();
-        let mut idx = 0;
-        let search = format!("*{}*", input.to_lowercase());
-        for record in &self.playlist_items {
-            let artist = record.artist().unwrap_or("Unknown artist");
-            let title = record.title().unwrap_or("Unknown title");
-            if wildmatch::WildMatch::new(&search).matches(&artist.to_lowercase())
-                | wildmatch::WildMatch::new(&search).matches(&title.to_lowercase())
-            {
-                if idx > 0 {
-                    table.add_row();
-                }
-
-                let duration = record.duration_formatted().to_string();
-                let duration_string = format!("[{:^6.6}]", duration);
-
-                let noname_string = "No Name".to_string();
-                let name = record.name().unwrap_or(&noname_string);
-                let artist = record.artist().unwrap_or(name);
-                let title = record.title().unwrap_or("Unknown Title");
-                let file_name = record.file().unwrap_or("no file");
-
-                table
-                    .add_col(TextSpan::new(duration_string.as_str()))
-                    .add_col(TextSpan::new(artist).fg(tuirealm::tui::style::Color::LightYellow))
-                    .add_col(TextSpan::new(title).bold())
-                    .add_col(TextSpan::new(file_name));
-                // .add_col(TextSpan::new(record.album().unwrap_or("Unknown Album")));
-                idx += 1;
+        let table = match Query::parse(input) {
+            Ok(query) => {
+                let results = self
+                    .playlist_items
+                    .iter()
+                    .map(Fields::from)
+                    .filter(|fields| query.matches(fields))
+                    .collect();
+                general_search_table(&[], results, self.general_search_sort)
             }
-        }
-        if self.playlist_items.is_empty() {
-            table.add_col(TextSpan::from("0"));
-            table.add_col(TextSpan::from("empty playlist"));
-            table.add_col(TextSpan::from(""));
-        }
-        let table = table.build();
-
+            Err(e) => general_search_error(&e),
+        };
         self.general_search_update_show(table);
     }
 
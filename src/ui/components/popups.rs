@@ -223,6 +223,16 @@ impl HelpPopup {
                         .add_col(TextSpan::new(key_volume).bold().fg(Color::Cyan))
                         .add_col(TextSpan::from("Increase/Decrease volume"))
                         .add_row()
+                        .add_col(
+                            TextSpan::new(format!(
+                                "<{}/{}>",
+                                keys.global_player_speed_up, keys.global_player_speed_down
+                            ))
+                            .bold()
+                            .fg(Color::Cyan),
+                        )
+                        .add_col(TextSpan::from("Faster/Slower playback"))
+                        .add_row()
                         .add_col(
                             TextSpan::new(format!("<{}>", keys.global_color_editor_open))
                                 .bold()
@@ -237,6 +247,41 @@ impl HelpPopup {
                         )
                         .add_col(TextSpan::from("Open Key Editor(change hotkeys)"))
                         .add_row()
+                        .add_col(
+                            TextSpan::new(format!("<{}>", keys.global_equalizer_open))
+                                .bold()
+                                .fg(Color::Cyan),
+                        )
+                        .add_col(TextSpan::from("Open Equalizer"))
+                        .add_row()
+                        .add_col(
+                            TextSpan::new(format!("<{}>", keys.global_output_device_open))
+                                .bold()
+                                .fg(Color::Cyan),
+                        )
+                        .add_col(TextSpan::from("Choose output device"))
+                        .add_row()
+                        .add_col(
+                            TextSpan::new(format!("<{}>", keys.global_bookmarks_open))
+                                .bold()
+                                .fg(Color::Cyan),
+                        )
+                        .add_col(TextSpan::from("Bookmarks of the current song"))
+                        .add_row()
+                        .add_col(
+                            TextSpan::new(format!("<{}>", keys.global_sleep_timer_open))
+                                .bold()
+                                .fg(Color::Cyan),
+                        )
+                        .add_col(TextSpan::from("Sleep timer"))
+                        .add_row()
+                        .add_col(
+                            TextSpan::new(format!("<{}>", keys.global_smart_playlists_open))
+                                .bold()
+                                .fg(Color::Cyan),
+                        )
+                        .add_col(TextSpan::from("Smart playlists"))
+                        .add_row()
                         .add_col(TextSpan::new("Library").bold().fg(Color::LightYellow))
                         .add_row()
                         .add_col(
@@ -270,6 +315,22 @@ impl HelpPopup {
                         )
                         .add_col(TextSpan::from("Open tag editor for tag and lyric download"))
                         .add_row()
+                        .add_col(
+                            TextSpan::new(format!("<{}>", keys.library_replaygain_scan))
+                                .bold()
+                                .fg(Color::Cyan),
+                        )
+                        .add_col(TextSpan::from("Scan song or folder and write ReplayGain"))
+                        .add_row()
+                        .add_col(
+                            TextSpan::new(format!("<{}>", keys.library_switch_mode))
+                                .bold()
+                                .fg(Color::Cyan),
+                        )
+                        .add_col(TextSpan::from(
+                            "Switch between folders, artists, album artists, genres and years",
+                        ))
+                        .add_row()
                         .add_col(
                             TextSpan::new(format!(
                                 "<{}/{}>",
@@ -359,6 +420,80 @@ impl Component<Msg, NoUserEvent> for HelpPopup {
     }
 }
 
+#[derive(MockComponent)]
+pub struct OutputDevicePopup {
+    component: Table,
+}
+
+impl OutputDevicePopup {
+    /// The first row picks the system default, the others `devices` in order.
+    pub fn new(devices: &[String], selected: Option<&str>) -> Self {
+        let mut table = TableBuilder::default();
+        let mark = |chosen: bool| if chosen { "\u{2714}" } else { "" };
+        table
+            .add_col(TextSpan::new(mark(selected.is_none())).fg(Color::LightGreen))
+            .add_col(TextSpan::from("System default"));
+        for device in devices {
+            table
+                .add_row()
+                .add_col(
+                    TextSpan::new(mark(selected == Some(device.as_str()))).fg(Color::LightGreen),
+                )
+                .add_col(TextSpan::from(device.as_str()));
+        }
+        Self {
+            component: Table::default()
+                .borders(
+                    Borders::default()
+                        .modifiers(BorderType::Rounded)
+                        .color(Color::Green),
+                )
+                .background(Color::Reset)
+                .title("Output device", Alignment::Left)
+                .scroll(true)
+                .highlighted_color(Color::LightBlue)
+                .highlighted_str("\u{1f680}")
+                .rewind(true)
+                .step(4)
+                .row_height(1)
+                .column_spacing(1)
+                .widths(&[4, 96])
+                .table(table.build()),
+        }
+    }
+}
+
+impl Component<Msg, NoUserEvent> for OutputDevicePopup {
+    fn on(&mut self, ev: Event<NoUserEvent>) -> Option<Msg> {
+        match ev {
+            Event::Keyboard(KeyEvent {
+                code: Key::Down | Key::Char('j'),
+                ..
+            }) => {
+                self.perform(Cmd::Move(Direction::Down));
+                Some(Msg::None)
+            }
+            Event::Keyboard(KeyEvent {
+                code: Key::Up | Key::Char('k'),
+                ..
+            }) => {
+                self.perform(Cmd::Move(Direction::Up));
+                Some(Msg::None)
+            }
+            Event::Keyboard(KeyEvent { code: Key::Esc, .. }) => {
+                Some(Msg::OutputDevicePopupCloseCancel)
+            }
+            Event::Keyboard(KeyEvent {
+                code: Key::Enter, ..
+            }) => match self.state() {
+                State::One(StateValue::Usize(index)) => Some(Msg::OutputDevicePopupCloseOk(index)),
+                _ => Some(Msg::OutputDevicePopupCloseCancel),
+            },
+            _ => None,
+        }
+    }
+}
+
 #[derive(MockComponent)]
 pub struct DeleteConfirmRadioPopup {
     component: Radio,
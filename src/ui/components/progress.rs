@@ -1,5 +1,4 @@
 // use crate::song::Song;
-use crate::player::GeneralP;
 use crate::song::Song;
 use crate::ui::{Id, Model, Msg, Status};
 
@@ -12,6 +11,9 @@ use tuirealm::event::NoUserEvent;
 use tuirealm::props::{Alignment, BorderType, Borders, Color, PropPayload, PropValue};
 use tuirealm::{AttrValue, Attribute, Component, Event, MockComponent};
 
+/// Seconds before the end of a song at which the next one is handed to the player.
+const GAPLESS_PRELOAD_SECS: i64 = 5;
+
 #[derive(MockComponent)]
 pub struct Progress {
     component: ProgressBar,
@@ -72,9 +74,18 @@ impl Model {
             if let Some(song) = &self.current_song;
             let artist = song.artist().unwrap_or("Unknown Artist");
             let title = song.title().unwrap_or("Unknown Title");
+            let speed = if (self.speed - 1.0).abs() > f32::EPSILON {
+                format!(" | Speed: {:.1}x", self.speed)
+            } else {
+                String::new()
+            };
+            let sleep = self
+                .sleep_timer_readout()
+                .map(|sleep| format!(" | Sleep: {}", sleep))
+                .unwrap_or_default();
             let progress_title = format!(
-                "Playing: {:^.20} - {:^.20} | Volume: {}",
-                            artist, title, self.config.volume
+                "Playing: {:^.20} - {:^.20} | Volume: {}{}{}",
+                            artist, title, self.config.volume, speed, sleep
                 );
             then {
                 self.app.attr( &Id::Progress,
@@ -86,30 +97,35 @@ impl Model {
         }
     }
 
-    #[allow(clippy::cast_sign_loss)]
+    #[allow(clippy::cast_precision_loss)]
     pub fn progress_update(&mut self) {
-        if let Ok((progress, time_pos, duration)) = self.player.get_progress() {
-            // for unsupported file format, don't update progress
-            if duration == 0 {
-                return;
-            }
-
-            // if time_pos >= duration {
-            //     self.status = Some(Status::Stopped);
-            //     return;
-            // }
-            // if time_pos > self.time_pos && time_pos - self.time_pos < 1 {
-            //     return;
-            // }
+        // for unsupported file format, don't update progress
+        if self.duration == 0 {
+            return;
+        }
 
-            self.time_pos = time_pos;
-            if self.time_pos_elapsed.elapsed().as_secs() > duration as u64 {
-                self.status = Some(Status::Stopped);
-                return;
-            }
-            let new_prog = Self::progress_safeguard(progress);
-            self.progress_set(new_prog, duration);
+        if self.daemon.is_none()
+            && self.enqueued_song.is_none()
+            && self.duration - self.time_pos <= self.player_preload_secs()
+            && matches!(self.status, Some(Status::Running))
+        {
+            self.player_enqueue_next();
         }
+        let progress = self.time_pos as f64 * 100.0 / self.duration as f64;
+        let new_prog = Self::progress_safeguard(progress);
+        self.progress_set(new_prog, self.duration);
+    }
+
+    // The next song has to be ready before a crossfade would start. Faster playback gets
+    // through more of the song in the same time.
+    #[allow(
+        clippy::cast_possible_wrap,
+        clippy::cast_possible_truncation,
+        clippy::cast_precision_loss
+    )]
+    fn player_preload_secs(&self) -> i64 {
+        let secs = GAPLESS_PRELOAD_SECS.max(self.config.crossfade_secs as i64 + 1);
+        (secs as f32 * self.speed.max(1.0)).ceil() as i64
     }
 
     fn progress_safeguard(progress: f64) -> f64 {
@@ -0,0 +1,217 @@
+use crate::player::GeneralP;
+use crate::song::Song;
+use crate::ui::{Model, Msg, Status};
+use std::time::{Duration, Instant};
+use tui_realm_stdlib::Table;
+use tuirealm::command::{Cmd, Direction};
+use tuirealm::event::{Key, KeyEvent, NoUserEvent};
+use tuirealm::props::{Alignment, BorderType, Borders, Color, TableBuilder, TextSpan};
+use tuirealm::{Component, Event, MockComponent, State, StateValue};
+
+/// The volume goes down over this long before the sleep timer pauses.
+const FADE_OUT: Duration = Duration::from_secs(30);
+
+/// When the sleep timer pauses playback.
+#[derive(Clone, Copy, Debug)]
+pub enum SleepTimer {
+    At(Instant),
+    /// At the end of this many songs, counting the one playing.
+    Songs(u32),
+}
+
+/// The rows of the popup, `None` turns the timer off.
+const CHOICES: [Option<Choice>; 10] = [
+    Some(Choice::Minutes(15)),
+    Some(Choice::Minutes(30)),
+    Some(Choice::Minutes(45)),
+    Some(Choice::Minutes(60)),
+    Some(Choice::Minutes(90)),
+    Some(Choice::Songs(1)),
+    Some(Choice::Songs(2)),
+    Some(Choice::Songs(3)),
+    Some(Choice::Songs(5)),
+    None,
+];
+
+#[derive(Clone, Copy)]
+enum Choice {
+    Minutes(u64),
+    Songs(u32),
+}
+
+#[derive(MockComponent)]
+pub struct SleepTimerPopup {
+    component: Table,
+}
+
+impl Default for SleepTimerPopup {
+    fn default() -> Self {
+        let mut table = TableBuilder::default();
+        for (index, choice) in CHOICES.iter().enumerate() {
+            if index > 0 {
+                table.add_row();
+            }
+            let label = match choice {
+                Some(Choice::Minutes(minutes)) => format!("In {} minutes", minutes),
+                Some(Choice::Songs(1)) => "At the end of this song".to_string(),
+                Some(Choice::Songs(songs)) => format!("At the end of {} songs", songs),
+                None => "Off".to_string(),
+            };
+            table.add_col(TextSpan::from(label));
+        }
+        Self {
+            component: Table::default()
+                .borders(
+                    Borders::default()
+                        .modifiers(BorderType::Rounded)
+                        .color(Color::Green),
+                )
+                .background(Color::Reset)
+                .title("Sleep timer", Alignment::Left)
+                .scroll(true)
+                .highlighted_color(Color::LightBlue)
+                .highlighted_str("\u{1f680}")
+                .rewind(true)
+                .step(4)
+                .row_height(1)
+                .widths(&[100])
+                .table(table.build()),
+        }
+    }
+}
+
+impl Component<Msg, NoUserEvent> for SleepTimerPopup {
+    fn on(&mut self, ev: Event<NoUserEvent>) -> Option<Msg> {
+        match ev {
+            Event::Keyboard(KeyEvent {
+                code: Key::Down | Key::Char('j'),
+                ..
+            }) => {
+                self.perform(Cmd::Move(Direction::Down));
+                Some(Msg::None)
+            }
+            Event::Keyboard(KeyEvent {
+                code: Key::Up | Key::Char('k'),
+                ..
+            }) => {
+                self.perform(Cmd::Move(Direction::Up));
+                Some(Msg::None)
+            }
+            Event::Keyboard(KeyEvent {
+                code: Key::Esc | Key::Char('q'),
+                ..
+            }) => Some(Msg::SleepTimerPopupCloseCancel),
+            Event::Keyboard(KeyEvent {
+                code: Key::Enter, ..
+            }) => match self.state() {
+                State::One(StateValue::Usize(index)) => Some(Msg::SleepTimerPopupCloseOk(index)),
+                _ => Some(Msg::SleepTimerPopupCloseCancel),
+            },
+            _ => None,
+        }
+    }
+}
+
+impl Model {
+    /// Sets the sleep timer to the row `index` of the popup.
+    pub fn sleep_timer_choose(&mut self, index: usize) {
+        self.sleep_timer_cancel();
+        self.sleep_timer = CHOICES
+            .get(index)
+            .copied()
+            .flatten()
+            .map(|choice| match choice {
+                Choice::Minutes(minutes) => {
+                    SleepTimer::At(Instant::now() + Duration::from_secs(minutes * 60))
+                }
+                Choice::Songs(songs) => SleepTimer::Songs(songs),
+            });
+        self.progress_update_title();
+    }
+
+    /// Turns the timer off, with the volume back where it was.
+    pub fn sleep_timer_cancel(&mut self) {
+        if self.sleep_timer.take().is_some() {
+            self.player.set_fade(1.0);
+            self.progress_update_title();
+        }
+    }
+
+    /// Time left until the timer pauses, unknown while more than one song is left.
+    #[allow(clippy::cast_sign_loss)]
+    fn sleep_timer_remaining(&self) -> Option<Duration> {
+        match self.sleep_timer? {
+            SleepTimer::At(deadline) => Some(deadline.saturating_duration_since(Instant::now())),
+            SleepTimer::Songs(1) if self.duration > 0 => Some(
+                Duration::from_secs((self.duration - self.time_pos).max(0) as u64)
+                    .div_f32(self.speed),
+            ),
+            SleepTimer::Songs(_) => None,
+        }
+    }
+
+    /// What the progress title shows of the timer.
+    pub fn sleep_timer_readout(&self) -> Option<String> {
+        let readout = match (self.sleep_timer?, self.sleep_timer_remaining()) {
+            (SleepTimer::At(_), Some(remaining)) => Song::duration_formatted_short(&remaining),
+            (SleepTimer::Songs(1), _) => "end of song".to_string(),
+            (SleepTimer::Songs(songs), _) => format!("{} songs", songs),
+            (SleepTimer::At(_), None) => return None,
+        };
+        Some(readout)
+    }
+
+    /// Fades the volume out over the last seconds and pauses once the time is up.
+    pub fn sleep_timer_update(&mut self) {
+        if let Some(remaining) = self.sleep_timer_remaining() {
+            if matches!(self.sleep_timer, Some(SleepTimer::At(_))) && remaining.is_zero() {
+                self.sleep_timer_expire();
+                return;
+            }
+            if remaining < FADE_OUT {
+                self.player
+                    .set_fade(remaining.as_secs_f32() / FADE_OUT.as_secs_f32());
+            }
+            self.progress_update_title();
+        }
+    }
+
+    /// Counts the songs down as another one follows the last to its end, and pauses it when
+    /// it's one too many. Songs skipped to don't count.
+    pub fn sleep_timer_track_started(&mut self) {
+        if let Some(SleepTimer::Songs(songs)) = self.sleep_timer {
+            if songs > 1 {
+                self.sleep_timer = Some(SleepTimer::Songs(songs - 1));
+                self.progress_update_title();
+            } else {
+                self.sleep_timer_expire();
+            }
+        }
+    }
+
+    fn sleep_timer_expire(&mut self) {
+        if pauses(self.status) {
+            self.player_toggle_pause();
+        }
+        self.sleep_timer_cancel();
+        self.show_message_timeout("Sleep timer", "Playback paused", None);
+    }
+}
+
+/// Whether the timer running out pauses playback in `status`. Paused or stopped, it stays so.
+const fn pauses(status: Option<Status>) -> bool {
+    matches!(status, Some(Status::Running))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expire_while_paused() {
+        assert!(pauses(Some(Status::Running)));
+        assert!(!pauses(Some(Status::Paused)));
+        assert!(!pauses(Some(Status::Stopped)));
+        assert!(!pauses(None));
+    }
+}
@@ -0,0 +1,146 @@
+use crate::library_db::LibraryDb;
+use crate::smart_playlists::{self, SmartPlaylist};
+use crate::song::Song;
+use crate::ui::{Model, Msg};
+use std::collections::VecDeque;
+use tui_realm_stdlib::Table;
+use tuirealm::command::{Cmd, Direction};
+use tuirealm::event::{Key, KeyEvent, NoUserEvent};
+use tuirealm::props::{Alignment, BorderType, Borders, Color, TableBuilder, TextSpan};
+use tuirealm::{Component, Event, MockComponent, State, StateValue};
+
+/// A row of the popup: name, query, and how many songs, or what is wrong with the query.
+pub type Row = (String, String, Result<usize, String>);
+
+#[derive(MockComponent)]
+pub struct SmartPlaylistsPopup {
+    component: Table,
+}
+
+impl SmartPlaylistsPopup {
+    pub fn new(playlists: &[Row]) -> Self {
+        let mut table = TableBuilder::default();
+        for (index, (name, query, songs)) in playlists.iter().enumerate() {
+            if index > 0 {
+                table.add_row();
+            }
+            let songs = match songs {
+                Ok(songs) => TextSpan::new(format!("{songs} songs")),
+                Err(e) => TextSpan::new(e).fg(Color::Red),
+            };
+            table
+                .add_col(TextSpan::new(name).fg(Color::LightYellow).bold())
+                .add_col(TextSpan::new(query))
+                .add_col(songs);
+        }
+        if playlists.is_empty() {
+            table.add_col(TextSpan::from("No smart playlists"));
+        }
+        Self {
+            component: Table::default()
+                .borders(
+                    Borders::default()
+                        .modifiers(BorderType::Rounded)
+                        .color(Color::Green),
+                )
+                .background(Color::Reset)
+                .title(
+                    " Smart playlists ── Enter: load, edit smart_playlists.toml to change ",
+                    Alignment::Left,
+                )
+                .scroll(true)
+                .highlighted_color(Color::LightBlue)
+                .highlighted_str("\u{1f680}")
+                .rewind(true)
+                .step(4)
+                .row_height(1)
+                .column_spacing(2)
+                .widths(&[30, 50, 20])
+                .table(table.build()),
+        }
+    }
+}
+
+impl Component<Msg, NoUserEvent> for SmartPlaylistsPopup {
+    fn on(&mut self, ev: Event<NoUserEvent>) -> Option<Msg> {
+        match ev {
+            Event::Keyboard(KeyEvent {
+                code: Key::Down | Key::Char('j'),
+                ..
+            }) => {
+                self.perform(Cmd::Move(Direction::Down));
+                Some(Msg::None)
+            }
+            Event::Keyboard(KeyEvent {
+                code: Key::Up | Key::Char('k'),
+                ..
+            }) => {
+                self.perform(Cmd::Move(Direction::Up));
+                Some(Msg::None)
+            }
+            Event::Keyboard(KeyEvent {
+                code: Key::Esc | Key::Char('q'),
+                ..
+            }) => Some(Msg::SmartPlaylistsPopupCloseCancel),
+            Event::Keyboard(KeyEvent {
+                code: Key::Enter, ..
+            }) => match self.state() {
+                State::One(StateValue::Usize(index)) => {
+                    Some(Msg::SmartPlaylistsPopupCloseOk(index))
+                }
+                _ => Some(Msg::SmartPlaylistsPopupCloseCancel),
+            },
+            _ => None,
+        }
+    }
+}
+
+impl Model {
+    /// Reads the smart playlists again and shows them, each as the library has it now.
+    pub fn smart_playlists_show(&mut self) {
+        self.smart_playlists = match smart_playlists::load() {
+            Ok(playlists) => playlists,
+            Err(e) => {
+                self.mount_error_popup(&format!("Couldn't read the smart playlists: {e}"));
+                return;
+            }
+        };
+        let db = self.library_db.lock().unwrap();
+        let rows: Vec<Row> = self
+            .smart_playlists
+            .iter()
+            .map(|playlist| {
+                let songs = playlist
+                    .files(&db, &self.path)
+                    .map(|files| files.len())
+                    .map_err(|e| e.to_string());
+                (playlist.name.clone(), playlist.query.clone(), songs)
+            })
+            .collect();
+        drop(db);
+        self.mount_smart_playlists(&rows);
+    }
+
+    /// Replaces the playlist with the songs of the smart playlist `index` of the popup.
+    pub fn smart_playlist_load(&mut self, index: usize) {
+        if let Some(playlist) = self.smart_playlists.get(index).cloned() {
+            match self.smart_playlist_songs(&playlist) {
+                Ok(songs) => {
+                    self.playlist_items = songs;
+                    self.playlist_sync();
+                }
+                Err(e) => {
+                    self.mount_error_popup(&format!("Couldn't load {}: {e}", playlist.name));
+                }
+            }
+        }
+    }
+
+    fn smart_playlist_songs(&self, playlist: &SmartPlaylist) -> anyhow::Result<VecDeque<Song>> {
+        let files = playlist.files(&self.library_db.lock().unwrap(), &self.path)?;
+        Ok(files
+            .iter()
+            .filter_map(|file| LibraryDb::song(&self.library_db, file).ok())
+            .collect())
+    }
+}
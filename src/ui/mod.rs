@@ -31,6 +31,7 @@ pub mod components;
 pub mod model;
 
 use crate::config::Termusic;
+use crate::session::Session;
 use crate::songtag::SongTag;
 use model::Model;
 // Let's define the messages handled by our app. NOTE: it must derive `PartialEq`
@@ -47,10 +48,12 @@ const FORCED_REDRAW_INTERVAL: Duration = Duration::from_millis(1000);
 #[derive(Clone, Debug, PartialEq)]
 pub enum Msg {
     // AppClose,
+    Bookmarks(BMMsg),
     ColorEditor(CEMsg),
     DeleteConfirmCloseCancel,
     DeleteConfirmCloseOk,
     DeleteConfirmShow,
+    Equalizer(EQMsg),
     ErrorPopupClose,
     GeneralSearch(GSMsg),
     HelpPopupShow,
@@ -59,14 +62,25 @@ pub enum Msg {
     Library(LIMsg),
     LyricCycle,
     LyricAdjustDelay(i64),
+    OutputDevicePopupShow,
+    OutputDevicePopupCloseCancel,
+    OutputDevicePopupCloseOk(usize),
     PlayerTogglePause,
     PlayerVolumeUp,
     PlayerVolumeDown,
+    PlayerSpeedUp,
+    PlayerSpeedDown,
     PlayerSeek(isize),
     Playlist(PLMsg),
     QuitPopupCloseCancel,
     QuitPopupCloseOk,
     QuitPopupShow,
+    SleepTimerPopupShow,
+    SleepTimerPopupCloseCancel,
+    SleepTimerPopupCloseOk(usize),
+    SmartPlaylistsPopupShow,
+    SmartPlaylistsPopupCloseCancel,
+    SmartPlaylistsPopupCloseOk(usize),
     TagEditor(TEMsg),
     UpdatePhoto,
     YoutubeSearch(YSMsg),
@@ -80,6 +94,8 @@ pub enum LIMsg {
     TreeBlur,
     Yank,
     Paste,
+    ReplayGainScan,
+    SwitchMode,
 }
 #[derive(Clone, Debug, PartialEq)]
 pub enum PLMsg {
@@ -102,6 +118,8 @@ pub enum GSMsg {
     InputBlur,
     PopupUpdateLibrary(String),
     PopupUpdatePlaylist(String),
+    PopupSortLibrary(usize),
+    PopupSortPlaylist(usize),
     TableBlur,
     PopupCloseLibraryAddPlaylist,
     PopupCloseOkLibraryLocate,
@@ -109,6 +127,26 @@ pub enum GSMsg {
     PopupCloseOkPlaylistLocate,
 }
 
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum BMMsg {
+    PopupShow,
+    PopupClose,
+    Jump(usize),
+    Delete(usize),
+    InputShow,
+    InputCloseCancel,
+    InputCloseOk(String),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum EQMsg {
+    PopupShow,
+    PopupClose,
+    BandGain(usize, f32),
+    BandReset(usize),
+    PresetNext,
+}
+
 #[derive(Clone, Debug, PartialEq)]
 pub enum YSMsg {
     InputPopupShow,
@@ -345,9 +383,12 @@ pub enum KEMsg {
 // Let's define the component ids for our application
 #[derive(Debug, Eq, PartialEq, Clone, Hash)]
 pub enum Id {
+    BookmarkInputPopup,
+    BookmarksPopup,
     ColorEditor(IdColorEditor),
     DeleteConfirmRadioPopup,
     DeleteConfirmInputPopup,
+    EqualizerPopup,
     ErrorPopup,
     GeneralSearchInput,
     GeneralSearchTable,
@@ -358,9 +399,12 @@ pub enum Id {
     Library,
     Lyric,
     MessagePopup,
+    OutputDevicePopup,
     Playlist,
     Progress,
     QuitPopup,
+    SleepTimerPopup,
+    SmartPlaylistsPopup,
     TagEditor(IdTagEditor),
     YoutubeSearchInputPopup,
     YoutubeSearchTablePopup,
@@ -497,7 +541,7 @@ pub enum StatusLine {
     Error,
 }
 
-#[derive(Clone, Deserialize, Serialize)]
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
 pub enum Loop {
     Single,
     Playlist,
@@ -548,7 +592,14 @@ impl UI {
     /// Main loop for Ui thread
     pub fn run(&mut self) {
         self.model.init_terminal();
-        self.model.playlist_load().ok();
+        // an attached daemon sends its playlist instead
+        if self.model.daemon.is_none() {
+            let session = Session::load();
+            if let Some(session) = &session {
+                self.model.session_restore(session);
+            }
+            self.model.playlist_load(session).ok();
+        }
         // Main loop
         let mut progress_interval = 0;
         while !self.model.quit {
@@ -559,7 +610,9 @@ impl UI {
             self.model.update_playlist_items();
             self.model.update_components();
             self.model.update_lyric();
-            self.model.progress_update();
+            self.model.update_player_events();
+            self.model.update_daemon();
+            self.model.update_control();
 
             if progress_interval == 0 {
                 self.model.run();
@@ -591,7 +644,15 @@ impl UI {
             self.model.view();
             // sleep(Duration::from_millis(20));
         }
-        assert!(self.model.playlist_save().is_ok());
+        // an attached daemon would stay faded out
+        self.model.sleep_timer_cancel();
+        // the daemon keeps the playlist when attached, and plays on
+        if self.model.daemon.is_none() {
+            assert!(self.model.playlist_save().is_ok());
+            self.model.session_save().ok();
+            self.model.bookmarks_remember();
+        }
+        self.model.library_db.lock().unwrap().save().ok();
         // assert!(self.model.config.save().is_ok());
         if let Err(e) = self.model.config.save() {
             eprintln!("{}", e);
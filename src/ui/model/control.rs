@@ -0,0 +1,102 @@
+use crate::daemon::protocol::{self, Notification, Request, State};
+use crate::player::GeneralP;
+use crate::song::Song;
+use crate::ui::model::Model;
+use crate::ui::Status;
+use anyhow::{bail, Result};
+use std::path::Path;
+use std::time::Duration;
+
+impl Model {
+    /// Answers `termusic ctl`, when playing without a daemon.
+    pub fn update_control(&mut self) {
+        let requests = match &mut self.control {
+            Some(control) => control.requests(),
+            None => return,
+        };
+        for (id, request) in requests {
+            let reply = match self.control_request(request) {
+                Ok(reply) => reply,
+                Err(e) => Notification::Error(e.to_string()),
+            };
+            if let Some(control) = &mut self.control {
+                control.reply(id, &reply);
+            }
+        }
+    }
+
+    fn control_request(&mut self, request: Request) -> Result<Notification> {
+        match request {
+            Request::Play => match self.status {
+                Some(Status::Running) => {}
+                Some(Status::Paused) => self.player_toggle_pause(),
+                Some(Status::Stopped) | None => self.player_next(),
+            },
+            Request::Pause => {
+                if matches!(self.status, Some(Status::Running)) {
+                    self.player_toggle_pause();
+                }
+            }
+            Request::Resume => {
+                if matches!(self.status, Some(Status::Paused)) {
+                    self.player_toggle_pause();
+                }
+            }
+            Request::TogglePause => self.player_toggle_pause(),
+            Request::Next => self.player_next(),
+            Request::Previous => self.player_previous(),
+            Request::Seek { secs } => {
+                self.player.seek(secs)?;
+                self.control_seeked();
+            }
+            Request::SeekTo { position } => {
+                self.player.seek_to(position)?;
+                self.control_seeked();
+            }
+            Request::SetVolume { volume } => {
+                self.player.set_volume(volume);
+                self.config.volume = self.player.volume();
+                #[cfg(feature = "mpris")]
+                self.mpris.set_volume(self.config.volume);
+                self.progress_update_title();
+            }
+            Request::Add { file, front } => {
+                if !Path::new(&file).exists() {
+                    bail!("{file} doesn't exist");
+                }
+                self.playlist_add_item(&file, front)?;
+            }
+            Request::Status => return Ok(Notification::Status(self.control_status())),
+            _ => bail!("only `termusic daemon` takes this request, not the TUI"),
+        }
+        self.redraw = true;
+        Ok(Notification::Ok)
+    }
+
+    fn control_seeked(&mut self) {
+        self.player_sync_time_pos();
+        self.progress_update();
+        #[cfg(feature = "mpris")]
+        self.mpris_seeked();
+    }
+
+    #[allow(clippy::cast_sign_loss)]
+    fn control_status(&self) -> protocol::Status {
+        let song = self.current_song.as_ref();
+        protocol::Status {
+            state: match self.status {
+                Some(Status::Running) => State::Playing,
+                Some(Status::Paused) => State::Paused,
+                Some(Status::Stopped) | None => State::Stopped,
+            },
+            file: song.and_then(Song::file).map(ToString::to_string),
+            title: song.and_then(Song::title).map(ToString::to_string),
+            artist: song.and_then(Song::artist).map(ToString::to_string),
+            position: Duration::from_secs(self.time_pos.max(0) as u64),
+            duration: Duration::from_secs(self.duration.max(0) as u64),
+            volume: self.player.volume(),
+            loop_mode: self.config.loop_mode.clone(),
+            playlist_len: self.playlist_items.len(),
+        }
+    }
+}
@@ -25,31 +25,38 @@
  * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
  * SOFTWARE.
  */
-
+mod control;
 #[cfg(feature = "mpris")]
 mod mpris;
 mod update;
 mod view;
+mod watcher;
 mod youtube_options;
 #[cfg(feature = "cover")]
 use crate::ueberzug::UeInstance;
 use crate::{
     config::Termusic,
+    daemon,
+    library_db::LibraryDb,
+    smart_playlists::SmartPlaylist,
     song::Song,
     ui::{Application, Id, Msg},
 };
 
-use crate::player::{GeneralP, GeneralPl};
+use crate::player::GeneralPl;
+use crate::session::SAVE_INTERVAL;
 use crate::songtag::SongTag;
-use crate::ui::components::{Keys, StyleColorSymbol};
+use crate::ui::components::{Keys, SleepTimer, StyleColorSymbol};
 use crate::ui::{SearchLyricState, Status};
 use std::collections::VecDeque;
 use std::path::{Path, PathBuf};
 use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
 use std::time::{Duration, Instant};
 use tui_realm_treeview::Tree;
 use tuirealm::event::NoUserEvent;
 use tuirealm::terminal::TerminalBridge;
+use watcher::LibraryWatcher;
 use youtube_options::YoutubeOptions;
 
 pub const MAX_DEPTH: usize = 3;
@@ -65,6 +72,12 @@ pub enum UpdateComponents {
     MessageHide((String, String)),
     YoutubeSearchSuccess(YoutubeOptions),
     YoutubeSearchFail(String),
+    ReplayGainScanProgress((usize, usize)), // files done, files total
+    ReplayGainScanCompleted(usize),
+    ReplayGainScanError(String),
+    LibraryDbUpdated,
+    /// Files in the music folder changed, with the renamed ones from where to where.
+    LibraryChanged(Vec<(String, String)>),
 }
 
 pub struct Model {
@@ -79,14 +92,28 @@ pub struct Model {
     pub path: PathBuf,
     pub tree: Tree,
     pub playlist_items: VecDeque<Song>,
+    /// Tags of the songs, so they are read from the files only once they changed.
+    pub library_db: Arc<Mutex<LibraryDb>>,
+    library_watcher: Option<LibraryWatcher>,
+    /// The library changed while a popup was open, it's shown again once it's closed.
+    pub library_outdated: bool,
+    /// The column the search results are sorted by, and whether they go downwards.
+    pub general_search_sort: Option<(usize, bool)>,
+    /// The smart playlists as the popup lists them.
+    pub smart_playlists: Vec<SmartPlaylist>,
     pub config: Termusic,
     pub player: GeneralPl,
     pub status: Option<Status>,
     pub yanked_node_id: Option<String>,
     pub current_song: Option<Song>,
+    /// File handed to `enqueue_next` for gapless playback, if any.
+    pub enqueued_song: Option<String>,
     pub tageditor_song: Option<Song>,
     pub time_pos: i64,
-    pub time_pos_elapsed: std::time::Instant,
+    /// Length of the current song in seconds as the player reported it, 0 while unknown.
+    pub duration: i64,
+    /// Playback speed of the current song, the positions stay in the time of the song.
+    pub speed: f32,
     pub lyric_line: String,
     youtube_options: YoutubeOptions,
     pub sender: Sender<UpdateComponents>,
@@ -102,6 +129,18 @@ pub struct Model {
     pub ce_themes: Vec<String>,
     pub ce_style_color_symbol: StyleColorSymbol,
     pub ke_key_config: Keys,
+    /// Devices listed by the output device popup, in its order.
+    pub output_devices: Vec<String>,
+    /// Connection to the daemon playing for this TUI, `None` when it plays by itself.
+    pub daemon: Option<daemon::Link>,
+    /// Where `termusic ctl` reaches this TUI when it plays by itself.
+    pub control: Option<daemon::Control>,
+    /// Where the song restored from the last session continues, once the player started it.
+    pub resume_position: Option<Duration>,
+    pub session_saved: Instant,
+    /// Where the bookmark being named goes.
+    pub bookmark_position: Duration,
+    pub sleep_timer: Option<SleepTimer>,
     #[cfg(feature = "mpris")]
     pub mpris: mpris::Mpris,
 }
@@ -120,7 +159,8 @@ impl Model {
         };
 
         let p: &Path = Path::new(full_path.as_ref());
-        let tree = Tree::new(Self::library_dir_tree(p, MAX_DEPTH));
+        let library_db = LibraryDb::load();
+        let tree = Self::library_tree(p, config.library_mode, &library_db);
 
         let (tx, rx): (Sender<UpdateComponents>, Receiver<UpdateComponents>) = mpsc::channel();
         let (tx2, rx2): (Sender<VecDeque<Song>>, Receiver<VecDeque<Song>>) = mpsc::channel();
@@ -134,6 +174,13 @@ impl Model {
         }
         // let viuer_supported =
         //     viuer::KittySupport::None != viuer::get_kitty_support() || viuer::is_iterm_supported();
+        let (player, daemon) = match GeneralPl::attach() {
+            Ok((player, link)) => (player, Some(link)),
+            Err(_) => (
+                GeneralPl::new(config.backend_from_cli.unwrap_or(config.backend)),
+                None,
+            ),
+        };
         Self {
             app: Self::init_app(&tree, config),
             quit: false,
@@ -143,14 +190,21 @@ impl Model {
             path: p.to_path_buf(),
             terminal: TerminalBridge::new().expect("Could not initialize terminal"),
             playlist_items: VecDeque::with_capacity(100),
+            library_db: Arc::new(Mutex::new(library_db)),
+            library_watcher: None,
+            library_outdated: false,
+            general_search_sort: None,
+            smart_playlists: Vec::new(),
             config: config.clone(),
-            player: GeneralPl::default(),
+            player,
             yanked_node_id: None,
             status: None,
             current_song: None,
+            enqueued_song: None,
             tageditor_song: None,
             time_pos: 0,
-            time_pos_elapsed: std::time::Instant::now(),
+            duration: 0,
+            speed: 1.0,
             lyric_line: String::new(),
             youtube_options: YoutubeOptions::new(),
             sender: tx,
@@ -166,6 +220,17 @@ impl Model {
             ce_themes: vec![],
             ce_style_color_symbol: StyleColorSymbol::default(),
             ke_key_config: Keys::default(),
+            output_devices: Vec::new(),
+            // another TUI may have the socket already
+            control: daemon
+                .is_none()
+                .then(|| daemon::Control::listen().ok())
+                .flatten(),
+            daemon,
+            resume_position: None,
+            session_saved: Instant::now(),
+            bookmark_position: Duration::ZERO,
+            sleep_timer: None,
             #[cfg(feature = "mpris")]
             mpris: mpris::Mpris::default(),
         }
@@ -178,7 +243,26 @@ impl Model {
         // eprintln!("{}", full_path);
         // let p: &Path = Path::new(&full_path);
         // self.library_scan_dir(p);
-        self.player.set_volume(self.config.volume);
+        self.library_db_scan();
+        // without it, the library only changes when it's reloaded
+        self.library_watcher = LibraryWatcher::new(
+            &self.path,
+            Arc::clone(&self.library_db),
+            self.sender.clone(),
+        )
+        .ok();
+        // an attached daemon has been set up when it started
+        if self.daemon.is_none() {
+            self.player.apply_config(&self.config);
+        }
+        #[cfg(feature = "mpris")]
+        {
+            self.mpris.set_volume(self.config.volume);
+            self.mpris.set_loop_mode(&self.config.loop_mode);
+        }
+        if let Some(fallback) = self.player.take_fallback() {
+            self.mount_error_popup(&fallback);
+        }
         if let Err(e) = self.theme_select_load_themes() {
             self.mount_error_popup(format!("Error load themes: {}", e).as_str());
         }
@@ -206,6 +290,20 @@ impl Model {
     }
 
     pub fn run(&mut self) {
+        self.sleep_timer_update();
+        if self.library_outdated {
+            self.library_refresh();
+        }
+        // an attached daemon moves on by itself
+        if self.daemon.is_some() {
+            return;
+        }
+        if self.session_saved.elapsed() >= SAVE_INTERVAL {
+            self.session_save().ok();
+            if let Some(Status::Running | Status::Paused) = self.status {
+                self.bookmarks_remember();
+            }
+        }
         match self.status {
             Some(Status::Stopped) => {
                 if self.playlist_items.is_empty() {
@@ -213,6 +311,8 @@ impl Model {
                 }
                 self.status = Some(Status::Running);
                 self.player_next();
+                // the last song played to its end
+                self.sleep_timer_track_started();
             }
             None => self.status = Some(Status::Stopped),
             Some(Status::Running | Status::Paused) => {}
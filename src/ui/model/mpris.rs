@@ -1,40 +1,30 @@
 use super::Status;
 use crate::player::GeneralP;
 use crate::song::Song;
-// use crate::souvlaki::{
-//     MediaControlEvent, MediaControls, MediaMetadata, MediaPlayback, PlatformConfig,
-// };
+use crate::souvlaki::{
+    LoopStatus, MediaControlEvent, MediaControls, MediaMetadata, MediaPlayback, PlatformConfig,
+};
 use crate::ui::model::Model;
-use souvlaki::{MediaControlEvent, MediaControls, MediaMetadata, MediaPlayback, PlatformConfig};
-// use std::str::FromStr;
+use crate::ui::Loop;
+use std::fs;
+use std::path::PathBuf;
 use std::sync::mpsc::{self, Receiver};
-// use std::sync::{mpsc, Arc, Mutex};
-// use std::thread::{self, JoinHandle};
+use std::time::Duration;
 
 pub struct Mpris {
     controls: MediaControls,
     pub rx: Receiver<MediaControlEvent>,
+    // the cover of the current song, written out for the clients to show
+    cover: Option<PathBuf>,
 }
 impl Default for Mpris {
     fn default() -> Self {
-        // #[cfg(not(target_os = "windows"))]
-        let hwnd = None;
-
-        // #[cfg(target_os = "windows")]
-        // let hwnd = {
-        //     use raw_window_handle::windows::WindowsHandle;
-
-        //     let handle: WindowsHandle = unimplemented!();
-        //     Some(handle.hwnd)
-        // };
-
         let config = PlatformConfig {
             dbus_name: "termusic",
             display_name: "Termuisc in Rust",
-            hwnd,
         };
 
-        let mut controls = MediaControls::new(config).unwrap();
+        let mut controls = MediaControls::new(&config).unwrap();
 
         let (tx, rx) = mpsc::sync_channel(32);
         // The closure must be Send and have a static lifetime.
@@ -44,35 +34,89 @@ impl Default for Mpris {
             })
             .ok();
 
-        Self { controls, rx }
+        Self {
+            controls,
+            rx,
+            cover: None,
+        }
     }
 }
 
-impl Mpris {
-    pub fn add_and_play(&mut self, song_str: &str) {
-        if let Ok(song) = Song::read_from_path(song_str) {
-            self.controls
-                .set_metadata(MediaMetadata {
-                    title: Some(song.title().unwrap_or("Unknown Title")),
-                    artist: Some(song.artist().unwrap_or("Unknown Artist")),
-                    album: Some(song.album().unwrap_or("")),
-                    ..MediaMetadata::default()
-                })
-                .ok();
+impl Drop for Mpris {
+    fn drop(&mut self) {
+        if let Some(cover) = self.cover.take() {
+            fs::remove_file(cover).ok();
         }
-        self.controls
-            .set_playback(MediaPlayback::Playing { progress: None })
-            .ok();
     }
-    pub fn pause(&mut self) {
-        self.controls
-            .set_playback(MediaPlayback::Paused { progress: None })
-            .ok();
+}
+
+impl Mpris {
+    pub fn add_and_play(&mut self, song: &Song) {
+        let cover_url = self
+            .update_cover(song)
+            .map(|path| format!("file://{}", path));
+        self.controls.set_metadata(MediaMetadata {
+            title: Some(song.title().unwrap_or("Unknown Title")),
+            artist: Some(song.artist().unwrap_or("Unknown Artist")),
+            album: Some(song.album().unwrap_or("")),
+            cover_url: cover_url.as_deref(),
+            duration: Some(song.duration()).filter(|d| !d.is_zero()),
+        });
+        self.controls.set_position(Duration::ZERO);
+        self.controls.set_playback(MediaPlayback::Playing);
+    }
+    pub fn pause(&self) {
+        self.controls.set_playback(MediaPlayback::Paused);
+    }
+    pub fn resume(&self) {
+        self.controls.set_playback(MediaPlayback::Playing);
+    }
+    pub fn stop(&self) {
+        self.controls.set_playback(MediaPlayback::Stopped);
     }
-    pub fn resume(&mut self) {
+    pub fn set_position(&self, position: Duration) {
+        self.controls.set_position(position);
+    }
+    pub fn seeked(&self, position: Duration) {
+        self.controls.seeked(position);
+    }
+    pub fn set_volume(&self, volume: i32) {
         self.controls
-            .set_playback(MediaPlayback::Playing { progress: None })
-            .ok();
+            .set_volume(f64::from(volume.clamp(0, 100)) / 100.0);
+    }
+    pub fn set_rate(&self, speed: f32) {
+        self.controls.set_rate(f64::from(speed));
+    }
+    pub fn set_loop_mode(&self, mode: &Loop) {
+        self.controls.set_loop_status(match mode {
+            Loop::Single => LoopStatus::Track,
+            Loop::Playlist => LoopStatus::Playlist,
+            Loop::Queue => LoopStatus::None,
+        });
+    }
+
+    // a file per cover, so clients that cache by url notice the change
+    fn update_cover(&mut self, song: &Song) -> Option<String> {
+        let data = song.picture().map(lofty::Picture::data);
+        let path = data.map(|data| {
+            let mut path = dirs::cache_dir().unwrap_or_else(|| PathBuf::from("/tmp"));
+            path.push(format!("termusic_mpris_{:x}", md5::compute(data)));
+            path
+        });
+        if self.cover != path {
+            if let Some(old) = self.cover.take() {
+                fs::remove_file(old).ok();
+            }
+            if let (Some(path), Some(data)) = (path, data) {
+                if fs::write(&path, data).is_ok() {
+                    self.cover = Some(path);
+                }
+            }
+        }
+        self.cover
+            .as_ref()
+            .and_then(|path| path.to_str())
+            .map(ToString::to_string)
     }
 }
 
@@ -86,34 +130,87 @@ impl Model {
                 self.player_previous();
             }
             MediaControlEvent::Pause => {
-                self.player.pause();
+                if matches!(self.status, Some(Status::Running)) {
+                    self.player_toggle_pause();
+                }
             }
-            MediaControlEvent::Toggle => {
-                if self.player.is_paused() {
-                    self.status = Some(Status::Running);
-                    self.player.resume();
-                } else {
-                    self.status = Some(Status::Paused);
-                    self.player.pause();
+            // the TUI moves on to the next song once stopped, so this pauses at the start
+            MediaControlEvent::Stop => {
+                if matches!(self.status, Some(Status::Running)) {
+                    self.player_toggle_pause();
                 }
+                if matches!(self.status, Some(Status::Paused)) {
+                    self.player.seek_to(Duration::ZERO).ok();
+                    self.player_sync_time_pos();
+                    self.progress_update();
+                    self.mpris.stop();
+                }
+            }
+            MediaControlEvent::Play => match self.status {
+                Some(Status::Running) => {}
+                Some(Status::Paused) => self.player_toggle_pause(),
+                Some(Status::Stopped) | None => self.player_next(),
+            },
+            MediaControlEvent::Toggle => self.player_toggle_pause(),
+            #[allow(clippy::cast_possible_wrap)]
+            MediaControlEvent::SeekBy(offset, backward) => {
+                let secs = offset.as_secs() as i64;
+                self.player.seek(if backward { -secs } else { secs }).ok();
+                self.player_sync_time_pos();
+                self.progress_update();
+                self.mpris_seeked();
             }
-            MediaControlEvent::Play => {
-                self.player.resume();
+            MediaControlEvent::SetPosition(position) => {
+                self.player.seek_to(position).ok();
+                self.player_sync_time_pos();
+                self.progress_update();
+                self.mpris_seeked();
+            }
+            #[allow(clippy::cast_possible_truncation)]
+            MediaControlEvent::SetVolume(volume) => {
+                self.player.set_volume((volume * 100.0).round() as i32);
+                self.config.volume = self.player.volume();
+                self.mpris.set_volume(self.config.volume);
+                self.progress_update_title();
+            }
+            #[allow(clippy::cast_possible_truncation)]
+            MediaControlEvent::SetRate(rate) => self.player_set_speed(rate as f32),
+            MediaControlEvent::SetLoopStatus(status) => {
+                let mode = match status {
+                    LoopStatus::Track => Loop::Single,
+                    LoopStatus::Playlist => Loop::Playlist,
+                    LoopStatus::None => Loop::Queue,
+                };
+                // cycling keeps the playlist in the order each mode expects
+                while self.config.loop_mode != mode {
+                    self.playlist_cycle_loop_mode();
+                }
+            }
+            MediaControlEvent::SetShuffle(shuffle) => {
+                if shuffle {
+                    self.playlist_shuffle();
+                }
             }
-            // MediaControlEvent::Seek(x) => match x {
-            //     SeekDirection::Forward => activity.player.seek(5).ok(),
-            //     SeekDirection::Backward => activity.player.seek(-5).ok(),
-            // },
-            // MediaControlEvent::SetPosition(position) => {
-            //     let _position = position. / 1000;
-            // }
             MediaControlEvent::OpenUri(uri) => {
-                self.player.add_and_play(&uri);
+                let uri = uri.strip_prefix("file://").unwrap_or(&uri).to_string();
+                if let Ok(song) = Song::read_from_path(&uri) {
+                    self.bookmarks_load_speed(&song);
+                    let gain = song.replaygain().factor(self.config.replaygain);
+                    self.player.add_and_play(&uri, gain);
+                    self.status = Some(Status::Running);
+                    self.mpris.add_and_play(&song);
+                }
             }
-            _ => {}
+            MediaControlEvent::Raise | MediaControlEvent::Quit => {}
         }
     }
 
+    pub fn mpris_seeked(&self) {
+        #[allow(clippy::cast_sign_loss)]
+        let position = Duration::from_secs(self.time_pos.max(0) as u64);
+        self.mpris.seeked(position);
+    }
+
     pub fn update_mpris(&mut self) {
         if let Ok(m) = self.mpris.rx.try_recv() {
             self.mpris_handler(m);
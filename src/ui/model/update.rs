@@ -25,18 +25,26 @@
  * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
  * SOFTWARE.
  */
-use crate::player::GeneralP;
+use crate::config::LibraryMode;
+use crate::daemon::protocol::{Notification, State};
+use crate::library_db::LibraryDb;
+use crate::player::{GeneralP, PlayerEvent};
+use crate::song::Song;
 use crate::ui::components::{load_alacritty_theme, ColorConfig};
 use crate::ui::{
-    model::UpdateComponents, CEMsg, GSMsg, Id, IdColorEditor, IdKeyEditor, IdTagEditor, KEMsg,
-    LIMsg, Model, Msg, PLMsg, StatusLine, TEMsg, YSMsg,
+    model::UpdateComponents, BMMsg, CEMsg, EQMsg, GSMsg, Id, IdColorEditor, IdKeyEditor,
+    IdTagEditor, KEMsg, LIMsg, Model, Msg, PLMsg, Status, StatusLine, TEMsg, YSMsg,
 };
 use std::path::PathBuf;
+use std::sync::Arc;
 use std::thread::{self, sleep};
 use std::time::Duration;
 use tuirealm::props::{AttrValue, Attribute, Color};
 use tuirealm::Update;
 
+/// How much faster or slower a key press makes the playback.
+const SPEED_STEP: f32 = 0.1;
+
 // Let's implement Update for model
 
 #[allow(clippy::too_many_lines)]
@@ -109,6 +117,14 @@ impl Update<Msg> for Model {
                     self.progress_update_title();
                     None
                 }
+                Msg::PlayerSpeedUp => {
+                    self.player_change_speed(SPEED_STEP);
+                    None
+                }
+                Msg::PlayerSpeedDown => {
+                    self.player_change_speed(-SPEED_STEP);
+                    None
+                }
                 Msg::HelpPopupShow => {
                     self.mount_help_popup();
                     None
@@ -122,6 +138,64 @@ impl Update<Msg> for Model {
                     self.update_youtube_search(&m);
                     None
                 }
+                Msg::OutputDevicePopupShow => {
+                    self.mount_output_device_popup();
+                    None
+                }
+                Msg::OutputDevicePopupCloseCancel => {
+                    let _ = self.app.umount(&Id::OutputDevicePopup);
+                    self.app.unlock_subs();
+                    None
+                }
+                Msg::OutputDevicePopupCloseOk(index) => {
+                    let _ = self.app.umount(&Id::OutputDevicePopup);
+                    self.app.unlock_subs();
+                    // the first row is the system default
+                    self.config.output_device = index
+                        .checked_sub(1)
+                        .and_then(|i| self.output_devices.get(i).cloned());
+                    self.player
+                        .set_output_device(self.config.output_device.as_deref());
+                    None
+                }
+                Msg::SleepTimerPopupShow => {
+                    self.mount_sleep_timer_popup();
+                    None
+                }
+                Msg::SleepTimerPopupCloseCancel => {
+                    let _ = self.app.umount(&Id::SleepTimerPopup);
+                    self.app.unlock_subs();
+                    None
+                }
+                Msg::SleepTimerPopupCloseOk(index) => {
+                    let _ = self.app.umount(&Id::SleepTimerPopup);
+                    self.app.unlock_subs();
+                    self.sleep_timer_choose(index);
+                    None
+                }
+                Msg::SmartPlaylistsPopupShow => {
+                    self.smart_playlists_show();
+                    None
+                }
+                Msg::SmartPlaylistsPopupCloseCancel => {
+                    let _ = self.app.umount(&Id::SmartPlaylistsPopup);
+                    self.app.unlock_subs();
+                    None
+                }
+                Msg::SmartPlaylistsPopupCloseOk(index) => {
+                    let _ = self.app.umount(&Id::SmartPlaylistsPopup);
+                    self.app.unlock_subs();
+                    self.smart_playlist_load(index);
+                    None
+                }
+                Msg::Equalizer(m) => {
+                    self.update_equalizer(&m);
+                    None
+                }
+                Msg::Bookmarks(m) => {
+                    self.update_bookmarks(&m);
+                    None
+                }
                 Msg::LyricCycle => {
                     self.lyric_cycle();
                     None
@@ -733,12 +807,14 @@ impl Model {
             LIMsg::TreeBlur => {
                 assert!(self.app.active(&Id::Playlist).is_ok());
             }
-            LIMsg::TreeExtendDir(path) => {
+            // the tag views have no folders to step into or out of
+            LIMsg::TreeExtendDir(path) if self.config.library_mode == LibraryMode::Directory => {
                 self.library_stepinto(path);
             }
-            LIMsg::TreeGoToUpperDir => {
+            LIMsg::TreeGoToUpperDir if self.config.library_mode == LibraryMode::Directory => {
                 self.library_stepout();
             }
+            LIMsg::TreeExtendDir(_) | LIMsg::TreeGoToUpperDir => {}
             LIMsg::Yank => {
                 self.library_yank();
             }
@@ -747,6 +823,12 @@ impl Model {
                     self.mount_error_popup(format!("Paste error: {}", e).as_str());
                 }
             }
+            LIMsg::ReplayGainScan => {
+                self.library_replaygain_scan();
+            }
+            LIMsg::SwitchMode => {
+                self.library_switch_mode();
+            }
         }
     }
     fn update_color_editor(&mut self, msg: &CEMsg) {
@@ -995,6 +1077,57 @@ impl Model {
         }
     }
 
+    fn update_equalizer(&mut self, msg: &EQMsg) {
+        match msg {
+            EQMsg::PopupShow => {
+                self.mount_equalizer();
+            }
+            EQMsg::PopupClose => {
+                if self.app.mounted(&Id::EqualizerPopup) {
+                    assert!(self.app.umount(&Id::EqualizerPopup).is_ok());
+                }
+                self.app.unlock_subs();
+            }
+            EQMsg::BandGain(index, delta) => self.equalizer_band_gain(*index, *delta),
+            EQMsg::BandReset(index) => self.equalizer_band_reset(*index),
+            EQMsg::PresetNext => self.equalizer_preset_next(),
+        }
+    }
+
+    fn update_bookmarks(&mut self, msg: &BMMsg) {
+        match msg {
+            BMMsg::PopupShow => self.mount_bookmarks(),
+            BMMsg::PopupClose => {
+                if self.app.mounted(&Id::BookmarksPopup) {
+                    assert!(self.app.umount(&Id::BookmarksPopup).is_ok());
+                }
+                self.app.unlock_subs();
+            }
+            BMMsg::Jump(index) => {
+                self.bookmark_jump(*index);
+                if self.app.mounted(&Id::BookmarksPopup) {
+                    assert!(self.app.umount(&Id::BookmarksPopup).is_ok());
+                }
+                self.app.unlock_subs();
+            }
+            BMMsg::Delete(index) => self.bookmark_delete(*index),
+            BMMsg::InputShow => self.bookmark_input_show(),
+            BMMsg::InputCloseCancel => {
+                if self.app.mounted(&Id::BookmarkInputPopup) {
+                    assert!(self.app.umount(&Id::BookmarkInputPopup).is_ok());
+                }
+                self.app.active(&Id::BookmarksPopup).ok();
+            }
+            BMMsg::InputCloseOk(name) => {
+                if self.app.mounted(&Id::BookmarkInputPopup) {
+                    assert!(self.app.umount(&Id::BookmarkInputPopup).is_ok());
+                }
+                self.app.active(&Id::BookmarksPopup).ok();
+                self.bookmark_add(name);
+            }
+        }
+    }
+
     fn update_youtube_search(&mut self, msg: &YSMsg) {
         match msg {
             YSMsg::InputPopupShow => {
@@ -1057,12 +1190,14 @@ impl Model {
     fn update_general_search(&mut self, msg: &GSMsg) {
         match msg {
             GSMsg::PopupShowLibrary => {
+                self.general_search_sort = None;
                 self.mount_search_library();
-                self.library_update_search("*");
+                self.library_update_search("");
             }
             GSMsg::PopupShowPlaylist => {
+                self.general_search_sort = None;
                 self.mount_search_playlist();
-                self.playlist_update_search("*");
+                self.playlist_update_search("");
             }
 
             GSMsg::PopupUpdateLibrary(input) => {
@@ -1071,6 +1206,14 @@ impl Model {
             GSMsg::PopupUpdatePlaylist(input) => {
                 self.playlist_update_search(input);
             }
+            GSMsg::PopupSortLibrary(column) => {
+                self.general_search_sort(*column);
+                self.library_update_search(&self.general_search_input());
+            }
+            GSMsg::PopupSortPlaylist(column) => {
+                self.general_search_sort(*column);
+                self.playlist_update_search(&self.general_search_input());
+            }
 
             GSMsg::PopupCloseCancel => {
                 self.app.umount(&Id::GeneralSearchInput).ok();
@@ -1304,6 +1447,34 @@ impl Model {
                 }
                 UpdateComponents::MessageHide((title, text)) => {
                     self.umount_message(&title, &text);
+                }
+                UpdateComponents::ReplayGainScanProgress((done, total)) => {
+                    self.mount_message(
+                        "ReplayGain",
+                        &format!("Scanning loudness: {}/{} files", done, total),
+                    );
+                }
+                UpdateComponents::ReplayGainScanCompleted(count) => {
+                    self.app.umount(&Id::MessagePopup).ok();
+                    self.show_message_timeout(
+                        "ReplayGain",
+                        &format!("Tags written to {} files", count),
+                        None,
+                    );
+                }
+                UpdateComponents::ReplayGainScanError(e) => {
+                    self.app.umount(&Id::MessagePopup).ok();
+                    self.mount_error_popup(format!("ReplayGain scan error: {}", e).as_str());
+                }
+                // the tag views show the songs read meanwhile
+                UpdateComponents::LibraryDbUpdated => {
+                    if self.config.library_mode != LibraryMode::Directory {
+                        self.library_refresh();
+                    }
+                }
+                UpdateComponents::LibraryChanged(renames) => {
+                    self.playlist_follow_library(&renames);
+                    self.library_refresh();
                 } //_ => {}
             }
         };
@@ -1396,11 +1567,104 @@ impl Model {
     pub fn update_playlist_items(&mut self) {
         if let Ok(playlist_items) = self.receiver_playlist_items.try_recv() {
             self.playlist_items = playlist_items;
-            self.playlist_sync();
+            self.playlist_sync_table();
             // self.redraw = true;
         }
     }
 
+    // follow what the player reports, rather than guessing from the clock
+    #[allow(clippy::cast_possible_wrap)]
+    pub fn update_player_events(&mut self) {
+        for event in self.player.events() {
+            match event {
+                PlayerEvent::TrackStarted(file) => {
+                    let handoff = self.enqueued_song.as_deref() == Some(file.as_str());
+                    // a song the TUI didn't start itself, the daemon moved on to it
+                    let followed = self.daemon.is_some()
+                        && self.current_song.as_ref().and_then(Song::file) != Some(file.as_str());
+                    if handoff {
+                        self.player_start_next(true);
+                    } else if self.daemon.is_some() {
+                        self.player_follow(&file);
+                    }
+                    if handoff || followed {
+                        self.sleep_timer_track_started();
+                    }
+                }
+                PlayerEvent::TrackEnded => {
+                    self.status = Some(Status::Stopped);
+                    #[cfg(feature = "mpris")]
+                    self.mpris.stop();
+                }
+                PlayerEvent::PositionChanged(position) => {
+                    #[cfg(feature = "mpris")]
+                    self.mpris.set_position(position);
+                    self.time_pos = position.as_secs() as i64;
+                    self.progress_update();
+                }
+                PlayerEvent::DurationKnown(duration) => {
+                    if let Some(position) = self.resume_position.take() {
+                        self.player.seek_to(position).ok();
+                    }
+                    self.duration = duration.as_secs() as i64;
+                    self.progress_update();
+                }
+                PlayerEvent::VolumeChanged(volume) => {
+                    #[cfg(feature = "mpris")]
+                    self.mpris.set_volume(volume);
+                    self.config.volume = volume;
+                    self.progress_update_title();
+                }
+                PlayerEvent::Error(e) => self.mount_error_popup(&e),
+            }
+        }
+    }
+
+    // follow the daemon this TUI is attached to
+    #[allow(clippy::cast_possible_wrap)]
+    pub fn update_daemon(&mut self) {
+        let notifications = match &self.daemon {
+            Some(link) => link.notifications(),
+            None => return,
+        };
+        for notification in notifications {
+            match notification {
+                Notification::Status(status) => {
+                    self.status = Some(match status.state {
+                        State::Playing => Status::Running,
+                        State::Paused => Status::Paused,
+                        State::Stopped => Status::Stopped,
+                    });
+                    if let Some(file) = &status.file {
+                        self.player_follow(file);
+                    }
+                    self.time_pos = status.position.as_secs() as i64;
+                    self.duration = status.duration.as_secs() as i64;
+                    self.config.volume = status.volume;
+                    self.config.loop_mode = status.loop_mode;
+                    #[cfg(feature = "mpris")]
+                    self.mpris.set_loop_mode(&self.config.loop_mode);
+                    self.playlist_update_title();
+                    self.progress_update_title();
+                    self.progress_update();
+                }
+                Notification::Playlist(files) => {
+                    let tx = self.sender_playlist_items.clone();
+                    let db = Arc::clone(&self.library_db);
+                    thread::spawn(move || {
+                        let playlist_items = files
+                            .iter()
+                            .filter_map(|file| LibraryDb::song(&db, file).ok())
+                            .collect();
+                        tx.send(playlist_items).ok();
+                    });
+                }
+                Notification::Error(e) => self.mount_error_popup(&e),
+                Notification::Ok | Notification::Player(_) => {}
+            }
+        }
+    }
+
     // show a popup for playing song
     pub fn update_playing_song(&self) {
         if let Some(song) = &self.current_song {
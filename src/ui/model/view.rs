@@ -1,33 +1,35 @@
 use crate::config::Termusic;
+use crate::player::output_devices;
 use crate::ui::components::{
-    draw_area_in_absolute, draw_area_in_relative, draw_area_top_right_absolute, CEHelpPopup,
-    CELibraryBackground, CELibraryBorder, CELibraryForeground, CELibraryHighlight,
-    CELibraryHighlightSymbol, CELibraryTitle, CELyricBackground, CELyricBorder, CELyricForeground,
-    CELyricTitle, CEPlaylistBackground, CEPlaylistBorder, CEPlaylistForeground,
+    draw_area_in_absolute, draw_area_in_relative, draw_area_top_right_absolute, BookmarkInputPopup,
+    BookmarksPopup, CEHelpPopup, CELibraryBackground, CELibraryBorder, CELibraryForeground,
+    CELibraryHighlight, CELibraryHighlightSymbol, CELibraryTitle, CELyricBackground, CELyricBorder,
+    CELyricForeground, CELyricTitle, CEPlaylistBackground, CEPlaylistBorder, CEPlaylistForeground,
     CEPlaylistHighlight, CEPlaylistHighlightSymbol, CEPlaylistTitle, CEProgressBackground,
     CEProgressBorder, CEProgressForeground, CEProgressTitle, CERadioOk, DeleteConfirmInputPopup,
-    DeleteConfirmRadioPopup, ErrorPopup, GSInputPopup, GSTablePopup, GlobalListener, HelpPopup,
-    KEGlobalColorEditor, KEGlobalColorEditorInput, KEGlobalDown, KEGlobalDownInput,
-    KEGlobalGotoBottom, KEGlobalGotoBottomInput, KEGlobalGotoTop, KEGlobalGotoTopInput,
-    KEGlobalHelp, KEGlobalHelpInput, KEGlobalKeyEditor, KEGlobalKeyEditorInput, KEGlobalLeft,
-    KEGlobalLeftInput, KEGlobalLyricAdjustBackward, KEGlobalLyricAdjustBackwardInput,
-    KEGlobalLyricAdjustForward, KEGlobalLyricAdjustForwardInput, KEGlobalLyricCycle,
-    KEGlobalLyricCycleInput, KEGlobalPlayerNext, KEGlobalPlayerNextInput, KEGlobalPlayerPrevious,
-    KEGlobalPlayerPreviousInput, KEGlobalPlayerSeekBackward, KEGlobalPlayerSeekBackwardInput,
-    KEGlobalPlayerSeekForward, KEGlobalPlayerSeekForwardInput, KEGlobalPlayerTogglePause,
-    KEGlobalPlayerTogglePauseInput, KEGlobalQuit, KEGlobalQuitInput, KEGlobalRight,
-    KEGlobalRightInput, KEGlobalUp, KEGlobalUpInput, KEGlobalVolumeDown, KEGlobalVolumeDownInput,
-    KEGlobalVolumeUp, KEGlobalVolumeUpInput, KEHelpPopup, KELibraryDelete, KELibraryDeleteInput,
-    KELibraryLoadDir, KELibraryLoadDirInput, KELibraryPaste, KELibraryPasteInput, KELibrarySearch,
-    KELibrarySearchInput, KELibrarySearchYoutube, KELibrarySearchYoutubeInput, KELibraryTagEditor,
-    KELibraryTagEditorInput, KELibraryYank, KELibraryYankInput, KEPlaylistAddFront,
-    KEPlaylistAddFrontInput, KEPlaylistDelete, KEPlaylistDeleteAll, KEPlaylistDeleteAllInput,
-    KEPlaylistDeleteInput, KEPlaylistModeCycle, KEPlaylistModeCycleInput, KEPlaylistPlaySelected,
-    KEPlaylistPlaySelectedInput, KEPlaylistSearch, KEPlaylistSearchInput, KEPlaylistShuffle,
-    KEPlaylistShuffleInput, KERadioOk, Label, Lyric, MessagePopup, MusicLibrary, Playlist,
-    Progress, QuitPopup, Source, TECounterDelete, TEHelpPopup, TEInputArtist, TEInputTitle,
-    TERadioTag, TESelectLyric, TETableLyricOptions, TETextareaLyric, ThemeSelectTable,
-    YSInputPopup, YSTablePopup,
+    DeleteConfirmRadioPopup, EqualizerPopup, ErrorPopup, GSInputPopup, GSTablePopup,
+    GlobalListener, HelpPopup, KEGlobalColorEditor, KEGlobalColorEditorInput, KEGlobalDown,
+    KEGlobalDownInput, KEGlobalGotoBottom, KEGlobalGotoBottomInput, KEGlobalGotoTop,
+    KEGlobalGotoTopInput, KEGlobalHelp, KEGlobalHelpInput, KEGlobalKeyEditor,
+    KEGlobalKeyEditorInput, KEGlobalLeft, KEGlobalLeftInput, KEGlobalLyricAdjustBackward,
+    KEGlobalLyricAdjustBackwardInput, KEGlobalLyricAdjustForward, KEGlobalLyricAdjustForwardInput,
+    KEGlobalLyricCycle, KEGlobalLyricCycleInput, KEGlobalPlayerNext, KEGlobalPlayerNextInput,
+    KEGlobalPlayerPrevious, KEGlobalPlayerPreviousInput, KEGlobalPlayerSeekBackward,
+    KEGlobalPlayerSeekBackwardInput, KEGlobalPlayerSeekForward, KEGlobalPlayerSeekForwardInput,
+    KEGlobalPlayerTogglePause, KEGlobalPlayerTogglePauseInput, KEGlobalQuit, KEGlobalQuitInput,
+    KEGlobalRight, KEGlobalRightInput, KEGlobalUp, KEGlobalUpInput, KEGlobalVolumeDown,
+    KEGlobalVolumeDownInput, KEGlobalVolumeUp, KEGlobalVolumeUpInput, KEHelpPopup, KELibraryDelete,
+    KELibraryDeleteInput, KELibraryLoadDir, KELibraryLoadDirInput, KELibraryPaste,
+    KELibraryPasteInput, KELibrarySearch, KELibrarySearchInput, KELibrarySearchYoutube,
+    KELibrarySearchYoutubeInput, KELibraryTagEditor, KELibraryTagEditorInput, KELibraryYank,
+    KELibraryYankInput, KEPlaylistAddFront, KEPlaylistAddFrontInput, KEPlaylistDelete,
+    KEPlaylistDeleteAll, KEPlaylistDeleteAllInput, KEPlaylistDeleteInput, KEPlaylistModeCycle,
+    KEPlaylistModeCycleInput, KEPlaylistPlaySelected, KEPlaylistPlaySelectedInput,
+    KEPlaylistSearch, KEPlaylistSearchInput, KEPlaylistShuffle, KEPlaylistShuffleInput, KERadioOk,
+    Label, Lyric, MessagePopup, MusicLibrary, OutputDevicePopup, Playlist, Progress, QuitPopup,
+    SleepTimerPopup, SmartPlaylistRow, SmartPlaylistsPopup, Source, TECounterDelete, TEHelpPopup,
+    TEInputArtist, TEInputTitle, TERadioTag, TESelectLyric, TETableLyricOptions, TETextareaLyric,
+    ThemeSelectTable, YSInputPopup, YSTablePopup,
 };
 
 use crate::ui::model::Model;
@@ -186,6 +188,30 @@ impl Model {
                         let popup = draw_area_in_relative(f.size(), 60, 90);
                         f.render_widget(Clear, popup);
                         self.app.view(&Id::HelpPopup, f, popup);
+                    } else if self.app.mounted(&Id::BookmarkInputPopup) {
+                        let popup = draw_area_in_absolute(f.size(), 50, 3);
+                        f.render_widget(Clear, popup);
+                        self.app.view(&Id::BookmarkInputPopup, f, popup);
+                    } else if self.app.mounted(&Id::BookmarksPopup) {
+                        let popup = draw_area_in_absolute(f.size(), 70, 12);
+                        f.render_widget(Clear, popup);
+                        self.app.view(&Id::BookmarksPopup, f, popup);
+                    } else if self.app.mounted(&Id::SmartPlaylistsPopup) {
+                        let popup = draw_area_in_relative(f.size(), 70, 40);
+                        f.render_widget(Clear, popup);
+                        self.app.view(&Id::SmartPlaylistsPopup, f, popup);
+                    } else if self.app.mounted(&Id::SleepTimerPopup) {
+                        let popup = draw_area_in_absolute(f.size(), 40, 12);
+                        f.render_widget(Clear, popup);
+                        self.app.view(&Id::SleepTimerPopup, f, popup);
+                    } else if self.app.mounted(&Id::OutputDevicePopup) {
+                        let popup = draw_area_in_absolute(f.size(), 60, 12);
+                        f.render_widget(Clear, popup);
+                        self.app.view(&Id::OutputDevicePopup, f, popup);
+                    } else if self.app.mounted(&Id::EqualizerPopup) {
+                        let popup = draw_area_in_absolute(f.size(), 80, 12);
+                        f.render_widget(Clear, popup);
+                        self.app.view(&Id::EqualizerPopup, f, popup);
                     } else if self.app.mounted(&Id::DeleteConfirmRadioPopup) {
                         let popup = draw_area_in_absolute(f.size(), 30, 3);
                         f.render_widget(Clear, popup);
@@ -266,6 +292,89 @@ impl Model {
         self.app.lock_subs();
     }
 
+    pub fn mount_output_device_popup(&mut self) {
+        self.output_devices = output_devices();
+        assert!(self
+            .app
+            .remount(
+                Id::OutputDevicePopup,
+                Box::new(OutputDevicePopup::new(
+                    &self.output_devices,
+                    self.config.output_device.as_deref()
+                )),
+                vec![]
+            )
+            .is_ok());
+        assert!(self.app.active(&Id::OutputDevicePopup).is_ok());
+        self.app.lock_subs();
+    }
+
+    pub fn mount_smart_playlists(&mut self, rows: &[SmartPlaylistRow]) {
+        assert!(self
+            .app
+            .remount(
+                Id::SmartPlaylistsPopup,
+                Box::new(SmartPlaylistsPopup::new(rows)),
+                vec![]
+            )
+            .is_ok());
+        assert!(self.app.active(&Id::SmartPlaylistsPopup).is_ok());
+        self.app.lock_subs();
+    }
+
+    pub fn mount_sleep_timer_popup(&mut self) {
+        assert!(self
+            .app
+            .remount(
+                Id::SleepTimerPopup,
+                Box::new(SleepTimerPopup::default()),
+                vec![]
+            )
+            .is_ok());
+        assert!(self.app.active(&Id::SleepTimerPopup).is_ok());
+        self.app.lock_subs();
+    }
+
+    pub fn mount_bookmarks(&mut self) {
+        assert!(self
+            .app
+            .remount(
+                Id::BookmarksPopup,
+                Box::new(BookmarksPopup::default()),
+                vec![]
+            )
+            .is_ok());
+        self.bookmarks_sync();
+        assert!(self.app.active(&Id::BookmarksPopup).is_ok());
+        self.app.lock_subs();
+    }
+
+    pub fn mount_bookmark_input(&mut self, name: &str) {
+        assert!(self
+            .app
+            .remount(
+                Id::BookmarkInputPopup,
+                Box::new(BookmarkInputPopup::new(name)),
+                vec![]
+            )
+            .is_ok());
+        assert!(self.app.active(&Id::BookmarkInputPopup).is_ok());
+    }
+
+    pub fn mount_equalizer(&mut self) {
+        assert!(self
+            .app
+            .remount(
+                Id::EqualizerPopup,
+                Box::new(EqualizerPopup::default()),
+                vec![]
+            )
+            .is_ok());
+        self.equalizer_sync();
+        assert!(self.app.active(&Id::EqualizerPopup).is_ok());
+        self.app.lock_subs();
+    }
+
     pub fn mount_confirm_radio(&mut self) {
         assert!(self
             .app
@@ -396,6 +505,10 @@ impl Model {
             self.mount_error_popup("directory doesn't have tag!");
             return;
         }
+        if self.library_group_files(node_id).is_some() {
+            self.mount_error_popup("a group of songs doesn't have tag!");
+            return;
+        }
 
         let p = p.to_string_lossy();
         match Song::read_from_path(p.as_ref()) {
@@ -0,0 +1,112 @@
+//! Watches the music folder, so the library follows the files other programs add, remove or
+//! rename, like downloads finishing or a sync from another machine.
+use super::UpdateComponents;
+use crate::library_db::{is_song, LibraryDb};
+use anyhow::Result;
+use notify::event::{AccessKind, AccessMode, ModifyKind, RenameMode};
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+/// Events are taken together until none came for this long, so a copy of many files is
+/// followed at once.
+const DEBOUNCE: Duration = Duration::from_secs(1);
+
+/// Stops watching when dropped.
+pub struct LibraryWatcher {
+    _watcher: RecommendedWatcher,
+}
+
+impl LibraryWatcher {
+    /// Watches `root` and the folders below it. The database follows the changes, and
+    /// `UpdateComponents::LibraryChanged` tells the library and the playlist to follow them.
+    pub fn new(
+        root: &Path,
+        db: Arc<Mutex<LibraryDb>>,
+        tx: Sender<UpdateComponents>,
+    ) -> Result<Self> {
+        let (event_tx, event_rx) = mpsc::channel();
+        let mut watcher = notify::recommended_watcher(event_tx)?;
+        watcher.watch(root, RecursiveMode::Recursive)?;
+        thread::spawn(move || {
+            while let Some(changes) = Changes::receive(&event_rx) {
+                if changes.apply(&db) {
+                    tx.send(UpdateComponents::LibraryChanged(changes.renames))
+                        .ok();
+                }
+            }
+        });
+        Ok(Self { _watcher: watcher })
+    }
+}
+
+#[derive(Default)]
+struct Changes {
+    /// Paths that may have been added, removed or changed.
+    paths: BTreeSet<PathBuf>,
+    /// Files or folders from where to where they were renamed.
+    renames: Vec<(String, String)>,
+    /// Whether files or folders were added, removed or renamed.
+    moved: bool,
+}
+
+impl Changes {
+    /// Waits for events, and takes them together until they stop coming for a moment.
+    /// `None` once the watcher is gone.
+    fn receive(rx: &Receiver<notify::Result<Event>>) -> Option<Self> {
+        let mut changes = Self::default();
+        changes.add(rx.recv().ok()?);
+        loop {
+            match rx.recv_timeout(DEBOUNCE) {
+                Ok(event) => changes.add(event),
+                Err(RecvTimeoutError::Timeout) => return Some(changes),
+                Err(RecvTimeoutError::Disconnected) => return None,
+            }
+        }
+    }
+
+    fn add(&mut self, event: notify::Result<Event>) {
+        if let Ok(event) = event {
+            match event.kind {
+                EventKind::Access(AccessKind::Close(AccessMode::Write))
+                | EventKind::Modify(ModifyKind::Data(_) | ModifyKind::Metadata(_)) => {}
+                // reading files, our own included, changes nothing
+                EventKind::Access(_) => return,
+                EventKind::Modify(ModifyKind::Name(RenameMode::Both)) => {
+                    if let [from, to] = &event.paths[..] {
+                        self.renames.push((
+                            from.to_string_lossy().into_owned(),
+                            to.to_string_lossy().into_owned(),
+                        ));
+                    }
+                    self.moved = true;
+                }
+                _ => self.moved = true,
+            }
+            self.paths.extend(event.paths);
+        }
+    }
+
+    /// Brings the database up to date with the changed paths. Returns whether the library
+    /// looks different now.
+    fn apply(&self, db: &Mutex<LibraryDb>) -> bool {
+        let mut changed = self.moved;
+        for path in &self.paths {
+            if path.is_dir() {
+                changed |= LibraryDb::scan(db, path).is_ok_and(|read| read > 0);
+            } else if path.is_file() {
+                if is_song(path) {
+                    changed |= LibraryDb::refresh(db, &path.to_string_lossy());
+                }
+            } else {
+                changed |= db.lock().unwrap().forget(path) > 0;
+            }
+        }
+        db.lock().unwrap().save().ok();
+        changed
+    }
+}